@@ -1,12 +1,18 @@
 use std::{fs, sync::Arc};
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use little_weirdo::synth::{
     self,
-    data::wavetables::{BoxedWavetable, BoxedWavetables},
+    data::{
+        frequencies::MIDI2FREQ,
+        wavetables::{BoxedWavetable, BoxedWavetables},
+    },
+    wavetable_oscillator::{Interpolation, WaveTableOscillator, WaveTableOscillatorConfig},
+    Clockable,
 };
 
 const SAMPLE_RATE: u16 = 44_100; // Audio sample rate in Hz
+const BLOCK_SIZES: [usize; 5] = [1, 16, 32, 64, 256]; // Frames rendered per call
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     // Initialize wavetables storage on heap
@@ -30,11 +36,47 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     let patch = serde_json::from_slice(include_bytes!("../examples/patches/bass.json")).unwrap();
 
     // Create a new synthesizer instance with specified parameters
-    let mut synth: synth::Synth = synth::Synth::new(SAMPLE_RATE as u16, &patch, Arc::clone(&wt));
+    let mut synth: synth::Synth = synth::Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
     synth.note_on(60, 100);
     c.bench_function("Little Weirdo Synth Clock and Output", |b| {
         b.iter(|| synth.clock_and_output())
     });
+
+    // Render whole blocks, reported per frame
+    let mut group = c.benchmark_group("Little Weirdo Synth Render");
+    for block_size in BLOCK_SIZES {
+        let mut frames = vec![[0i16; 2]; block_size];
+        group.throughput(Throughput::Elements(block_size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(block_size),
+            &block_size,
+            |b, _| b.iter(|| synth.render(&mut frames)),
+        );
+    }
+    group.finish();
+
+    // Cost of each wavetable interpolation mode, reported per sample
+    let mut group = c.benchmark_group("Little Weirdo Oscillator Interpolation");
+    let mut samples = [0i16; 256];
+    group.throughput(Throughput::Elements(samples.len() as u64));
+    for interpolation in [
+        Interpolation::None,
+        Interpolation::Linear,
+        Interpolation::Hermite,
+    ] {
+        let config = WaveTableOscillatorConfig {
+            soundbank_index: 1,
+            interpolation,
+            ..Default::default()
+        };
+        let mut osc = WaveTableOscillator::new(config, SAMPLE_RATE, Arc::clone(&wt));
+        osc.change_freq(MIDI2FREQ[45]);
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{:?}", interpolation)),
+            |b| b.iter(|| osc.clock_block(&mut samples)),
+        );
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -26,14 +26,13 @@ fn main() {
     let patch = serde_json::from_slice(include_bytes!("patches/bass.json")).unwrap();
 
     // Create a new synthesizer instance with specified parameters
-    let mut synth: synth::Synth = synth::Synth::new(SAMPLE_RATE as u16, &patch, Arc::clone(&wt));
+    let mut synth: synth::Synth = synth::Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
 
     // Trigger a note
     synth.note_on(60, 100);
 
-    loop {
-        let _sample: [i16; 2] = synth.clock_and_output();
-        // do something with the sample, stream it to a audio device for example
-        break;
-    }
+    // Render a block of stereo frames
+    let mut frames = [[0i16; 2]; 256];
+    synth.render(&mut frames);
+    // do something with the frames, stream them to a audio device for example
 }
@@ -18,14 +18,11 @@ const SOUNDBANK_OUTPUT: [&str; SOUNDBANK_ITEMS] = ["examples/soundbank/soundbank
 // Main function - entry point of the program
 fn main() {
     // Loop through each soundbank item defined
-    for i in 0..SOUNDBANK_ITEMS {
+    for (original, output) in SOUNDBANK_ORIGINAL.iter().zip(SOUNDBANK_OUTPUT.iter()) {
         // Get the original soundbank source path
-        let soundbank_original_source_path = Path::new(SOUNDBANK_ORIGINAL[i]);
+        let soundbank_original_source_path = Path::new(original);
         // Get the output soundbank source path
-        let soundbank_source_path = Path::new(SOUNDBANK_OUTPUT[i]);
-
-        // Initialize a counter for tracking processed files
-        let mut counter = 0;
+        let soundbank_source_path = Path::new(output);
 
         // Read directory entries and collect them into a vector
         let mut paths: Vec<_> = read_dir(soundbank_original_source_path)
@@ -46,14 +43,14 @@ fn main() {
         let _since_unix_epoch = now.duration_since(UNIX_EPOCH).unwrap();
 
         // Write initial markdown header to README
-        let _ = soundbank_reference_file.write(format!("# Soundbank \n\n").as_bytes());
+        let _ = soundbank_reference_file.write(b"# Soundbank \n\n");
 
         // Write table header for the soundbank documentation
         let _ = soundbank_reference_file
             .write("| Index | Original | Output | Samples | Size | \n|-|-|-|-|-|\n".as_bytes());
 
-        // Process each file in the directory
-        for entry in paths {
+        // Process each file in the directory, counting the processed files
+        for (counter, entry) in paths.into_iter().enumerate() {
             // Format the original source name for the README table
             let orginal_src_name = format!(
                 "| {} | {:?} | \"wav{:?}.lwt\" | 600 | 1200 |\n",
@@ -84,16 +81,13 @@ fn main() {
             println!("Amount of sample = {:?}", open.duration());
 
             // Iterate through all samples in the WAV file
-            for sample in open.samples::<i16>().into_iter() {
+            for sample in open.samples::<i16>() {
                 // Extract the sample value
                 let output = sample.unwrap();
 
                 // Convert the sample to little-endian byte representation and write to output file
                 let _ = soundbank_source_file.write(&output.to_ne_bytes());
             }
-
-            // Increment the counter for next iteration
-            counter = counter + 1;
         }
     }
 }
@@ -22,10 +22,11 @@ fn main() {
                 // Parse JSON
                 let patch: Patch = serde_json::from_str(&json_content).unwrap();
 
-                // Serialize to postcard format
-                let mut buf = [0u8; 256];
-                let serialized =
-                    postcard::to_slice(&patch, &mut buf).expect("Failed to serialize to postcard");
+                // Serialize to postcard format with a format version header
+                let mut buf = [0u8; 512];
+                let serialized = patch
+                    .to_postcard(&mut buf)
+                    .expect("Failed to serialize to postcard");
 
                 // Create output filename (same name, different extension)
                 let output_path = path.with_extension("lwp");
@@ -34,7 +35,7 @@ fn main() {
                 let mut postcard_patch_file =
                     File::create(&output_path).expect("Failed to create output file");
                 postcard_patch_file
-                    .write_all(&serialized)
+                    .write_all(serialized)
                     .expect("Failed to write to file");
 
                 println!("Converted {} to {}", path.display(), output_path.display());
@@ -14,7 +14,7 @@ const HEADROOM: f64 = -12.0; // Minimum acceptable decibel level
 fn main() {
     // Configure WAV file specification for output
     let spec = hound::WavSpec {
-        channels: 1,                             // Mono audio
+        channels: 2,                             // Stereo audio
         sample_rate: 44100,                      // Same as our constant
         bits_per_sample: 16,                     // 16-bit samples
         sample_format: hound::SampleFormat::Int, // Integer format
@@ -34,7 +34,7 @@ fn main() {
         );
         let contents = fs::read(filename).unwrap(); // Read file contents
         let bytes: &[u8] = &contents; // Convert to byte slice
-        wt_on_heap.add(BoxedWavetable::new(bytes)); // Add to wavetables collection
+        wt_on_heap.add(BoxedWavetable::new_band_limited(bytes)); // Add with band-limited versions to avoid aliasing
     }
 
     // Create an Arc (thread-safe reference) to the wavetables
@@ -45,7 +45,7 @@ fn main() {
         serde_json::from_slice(include_bytes!("patches/supersaw_4_oscillators.json")).unwrap();
 
     // Create a new synthesizer instance with specified parameters
-    let mut synth: synth::Synth = synth::Synth::new(SAMPLE_RATE as u16, &patch, Arc::clone(&wt));
+    let mut synth: synth::Synth = synth::Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
 
     // Array to track clipping occurrences
     let mut clipped: [i32; 2] = [0; 2];
@@ -62,8 +62,9 @@ fn main() {
             // Get output from synthesizer (stereo)
             let output = synth.clock_and_output();
 
-            // Write left channel sample to WAV file
+            // Write left and right channel sample to WAV file
             writer.write_sample(output[0]).unwrap();
+            writer.write_sample(output[1]).unwrap();
 
             // Convert sample to f64 for RMS calculation
             let left: f64 = Sample::from_sample(output[0]);
@@ -71,12 +72,12 @@ fn main() {
 
             // Check for clipping in left channel
             if output[0].abs() > CLIPPING as i16 {
-                clipped[0] = clipped[0] + 1;
+                clipped[0] += 1;
             }
 
-            // Check for clipping in right channel (though we're using mono)
+            // Check for clipping in right channel
             if output[1].abs() > CLIPPING as i16 {
-                clipped[0] = clipped[0] + 1;
+                clipped[0] += 1;
             }
         }
 
@@ -87,6 +88,7 @@ fn main() {
         for _n in 0..SAMPLE_RATE {
             let output = synth.clock_and_output();
             writer.write_sample(output[0]).unwrap();
+            writer.write_sample(output[1]).unwrap();
         }
 
         // Calculate RMS (Root Mean Square) value
@@ -116,12 +118,12 @@ fn main() {
 
         // Print newline every 8 notes for readability
         if note % 8 == 0 {
-            println!("");
+            println!();
         }
     }
 
     // Print final newline
-    println!("");
+    println!();
 }
 
 // Function to convert RMS amplitude to decibel scale
@@ -5,13 +5,13 @@ use little_weirdo::synth::{
     self,
     data::wavetables::{BoxedWavetable, BoxedWavetables},
 };
-use midi_control::{self, MidiMessage};
-use midir;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::TryRecvError;
 use std::{
     fs,
     sync::{mpsc, Arc},
+    thread,
+    time::Duration,
 };
 
 fn main() {
@@ -25,7 +25,7 @@ fn main() {
     }
 
     // Create a channel for MIDI messages (though receiver isn't used in this scope)
-    let (sender, _receiver) = channel::<midi_control::MidiMessage>();
+    let (sender, _receiver) = channel::<Vec<u8>>();
 
     // Unwrap the device port (we know it exists from the check above)
     let device_port = device_port.unwrap();
@@ -36,7 +36,7 @@ fn main() {
     // Define error callback for audio stream
     let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
-    // Create a collection of wavetables and load them from files
+    // Create a collection of wavetables and load them from files, band-limited to avoid aliasing
     let mut wt_on_heap = BoxedWavetables::new();
     for id in 0..10 {
         let filename = format!(
@@ -45,7 +45,7 @@ fn main() {
         );
         let contents = fs::read(filename).unwrap();
         let bytes: &[u8] = &contents;
-        wt_on_heap.add(BoxedWavetable::new(bytes));
+        wt_on_heap.add(BoxedWavetable::new_band_limited(bytes));
     }
     // Wrap wavetables in an Arc for thread-safe sharing
     let wt = Arc::new(wt_on_heap);
@@ -56,18 +56,16 @@ fn main() {
     // Initialize the synthesizer with sample rate, patch, and wavetables
     let mut synth: synth::Synth = synth::Synth::new(44100, &patch, Arc::clone(&wt));
 
-    // Create a channel specifically for MIDI messages from the input device
-    let (midi_tx, midi_rx) = mpsc::channel::<midi_control::MidiMessage>();
+    // Create a channel specifically for raw MIDI bytes from the input device
+    let (midi_tx, midi_rx) = mpsc::channel::<Vec<u8>>();
 
     // Connect to the MIDI input port and start listening for messages
     let _connect_in = midi_input.connect(
         &device_port,
         "IAC Driver",
         move |_timestamp, data, _sender| {
-            // Convert raw MIDI data to our MidiMessage type
-            let msg: midi_control::MidiMessage = midi_control::MidiMessage::from(data);
-            // Send the message through the channel
-            let _ = midi_tx.send(msg);
+            // Send the raw MIDI bytes through the channel, the synth parses them itself
+            let _ = midi_tx.send(data.to_vec());
         },
         sender, // This sender isn't actually used in the callback
     );
@@ -77,31 +75,28 @@ fn main() {
         .build_output_stream(
             &config,
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                // Process any pending MIDI messages
-                match midi_rx.try_recv() {
-                    Ok(message) => process_midimessage(&mut synth, message),
-                    Err(TryRecvError::Empty) => {} // No messages available
-                    Err(TryRecvError::Disconnected) => panic!("Channel disconnected"),
+                // Process all pending MIDI messages
+                loop {
+                    match midi_rx.try_recv() {
+                        Ok(bytes) => {
+                            if let Some(program) = synth.handle_midi(&bytes) {
+                                println!("Program change {} is not mapped to a patch", program);
+                            }
+                        }
+                        Err(TryRecvError::Empty) => break, // No messages available
+                        Err(TryRecvError::Disconnected) => panic!("Channel disconnected"),
+                    }
                 }
 
-                // Generate audio output by processing the synth in chunks
-                for frame in data.chunks_mut(2) {
-                    // Get the next sample from the synth
-                    let output = synth.clock_and_output();
+                // Render the synth in blocks of interleaved stereo samples (left, right, ...)
+                let mut block = [0i16; 2 * synth::BLOCK_SIZE];
+                for chunk in data.chunks_mut(block.len()) {
+                    let rendered = &mut block[..chunk.len()];
+                    synth.render_interleaved(rendered);
 
                     // Convert samples to f32 format
-                    let left: f32 = Sample::from_sample(output[0]);
-                    let right: f32 = Sample::from_sample(output[1]);
-
-                    // Write stereo output to the audio buffer
-                    let mut count = 0;
-                    for sample in frame.iter_mut() {
-                        if count == 0 {
-                            *sample = left; // Left channel
-                        } else {
-                            *sample = right; // Right channel
-                        }
-                        count = count + 1;
+                    for (sample, output) in chunk.iter_mut().zip(rendered.iter()) {
+                        *sample = Sample::from_sample(*output);
                     }
                 }
             },
@@ -114,7 +109,9 @@ fn main() {
     stream.play().unwrap();
 
     // Main loop - keeps the program running
-    loop {}
+    loop {
+        thread::sleep(Duration::from_millis(100));
+    }
 }
 
 /// Sets up the audio output device and configuration
@@ -137,18 +134,6 @@ fn setup_device() -> (Device, StreamConfig) {
     (device, config)
 }
 
-/// Processes MIDI messages and updates the synthesizer state
-fn process_midimessage(synth: &mut synth::Synth, command: MidiMessage) {
-    match command {
-        // Handle note-on messages
-        MidiMessage::NoteOn(_ch, e) => synth.note_on(e.key, e.value),
-        // Handle note-off messages
-        MidiMessage::NoteOff(_ch, e) => synth.note_off(e.key),
-        // Ignore other message types
-        _ => {}
-    }
-}
-
 /// Finds a MIDI port by name (looking for "IAC Driver")
 fn find_port<T>(midi_io: &T) -> Option<T::Port>
 where
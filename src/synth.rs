@@ -1,29 +1,57 @@
+pub mod clock;
 pub mod data;
 pub mod effects;
 pub mod envelope;
+pub mod lfo;
 pub mod math;
+pub mod midi;
 pub mod mixer;
+pub mod note_stack;
 pub mod patch;
 pub mod router;
 pub mod sampler;
+pub mod tuning;
+pub mod velocity;
+pub mod vibrato;
 pub mod wavetable_oscillator;
+use clock::TempoClock;
 use data::wavetables::BoxedWavetables;
 use effects::bitcrunch::Bitcrunch;
 use effects::{overdrive::Overdrive, Effect};
-use patch::SynthMode;
-use router::Router;
+use envelope::ENVELOPE_FULL_SCALE;
+use lfo::Lfo;
+use midi::{MidiChannel, MidiMessage, MidiParser};
+use note_stack::NoteStack;
+use patch::{MonoMode, NotePriority, SynthMode, VoiceStealing};
+use router::{
+    ModulationSources, Modulations, Router, MODULATION_FULL_SCALE, VOICE_FREQUENCY_RANGE,
+};
+use tuning::{TuningTable, DEFAULT_MASTER_TUNE};
+use velocity::VelocityConfiguration;
+use vibrato::VibratoConfiguration;
+use wavetable_oscillator::VoiceModulation;
 extern crate alloc;
 use alloc::sync::Arc;
 
-use self::{data::frequencies::MIDI2FREQ, effects::filter::Filter, mixer::Mixer, patch::Patch};
+use self::{
+    data::frequencies::FREQ_SHIFT,
+    effects::filter::{Filter, FilterConfig},
+    mixer::Mixer,
+    patch::Patch,
+};
 
 /// Trait for clockable components that can process samples
 pub trait Clockable {
     /// Process a sample, returning either the provided sample or a default value
     fn clock(&mut self, sample: Option<i16>) -> i16 {
-        match sample {
-            Some(s) => s, // Use the provided sample
-            None => 0,    // Default value if no sample is provided
+        // Use the provided sample, default value if no sample is provided
+        sample.unwrap_or_default()
+    }
+
+    /// Generate a block of samples, one `clock` per slot in the buffer
+    fn clock_block(&mut self, buffer: &mut [i16]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.clock(None);
         }
     }
 }
@@ -32,6 +60,44 @@ pub trait Clockable {
 pub const AMOUNT_OF_VOICES: usize = 8;
 /// Number of output channels (stereo)
 pub const AMOUNT_OF_OUTPUT_CHANNELS: usize = 2;
+/// Maximum number of frames processed in one pass of the render pipeline
+pub const BLOCK_SIZE: usize = 32;
+/// Time in ms to fade out a stolen voice before it plays its new note
+const STEAL_FADE_TIME: u32 = 3;
+/// Marks a free slot in the active notes, outside the MIDI note range
+const NO_NOTE: u8 = u8::MAX;
+
+/// Scratch buffers for one block, owned by the `Synth` so rendering never allocates
+struct BlockBuffers {
+    /// Generated samples per voice
+    voices: [[i16; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+    /// Envelope levels per envelope generator (0 to `ENVELOPE_FULL_SCALE`)
+    envelops: [[i16; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+    /// Filter envelope levels per note (0 to `ENVELOPE_FULL_SCALE`)
+    filter_envelops: [[i16; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+    /// LFO levels (-`i16::MAX` to `i16::MAX`)
+    lfos: [[i16; BLOCK_SIZE]; AMOUNT_OF_VOICES / 2],
+    /// Per voice, whether the cycle of the oscillator started at a sample, drives hard sync
+    cycle_starts: [[bool; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+    /// Summed signal of all voices per output channel
+    mix: [[i16; BLOCK_SIZE]; AMOUNT_OF_OUTPUT_CHANNELS],
+    /// Output of the modulation matrix per destination
+    modulations: Modulations,
+}
+
+impl BlockBuffers {
+    fn new() -> Self {
+        Self {
+            voices: [[0; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+            envelops: [[0; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+            filter_envelops: [[0; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+            lfos: [[0; BLOCK_SIZE]; AMOUNT_OF_VOICES / 2],
+            cycle_starts: [[false; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+            mix: [[0; BLOCK_SIZE]; AMOUNT_OF_OUTPUT_CHANNELS],
+            modulations: Modulations::new(),
+        }
+    }
+}
 
 /// Main synthesizer struct that handles audio generation
 pub struct Synth {
@@ -39,25 +105,93 @@ pub struct Synth {
     voices: [wavetable_oscillator::WaveTableOscillator; AMOUNT_OF_VOICES],
     /// Array of envelope generators for shaping sound
     envelops: [envelope::EnvelopeGenerator; AMOUNT_OF_VOICES],
+    /// Filter envelope generator per note, sweeping the filter cutoff
+    filter_envelops: [envelope::EnvelopeGenerator; AMOUNT_OF_VOICES],
     /// Array of Low-Frequency Oscillators for modulation
-    lfo: [wavetable_oscillator::WaveTableOscillator; AMOUNT_OF_VOICES / 2],
+    lfo: [Lfo; AMOUNT_OF_VOICES / 2],
     //  sampler: Sampler,
     /// Audio routing system
     router: Router,
-    /// Filter effect for sound shaping
-    filter: Filter,
+    /// Filter effect for sound shaping, one per output channel
+    filter: [Filter; AMOUNT_OF_OUTPUT_CHANNELS],
+    /// Filter per voice, used instead of the output filters when configured
+    voice_filters: [Filter; AMOUNT_OF_VOICES],
     /// Overdrive effect for distortion
     overdrive: Overdrive,
     /// Bitcrunch effect for digital degradation
     bitcrunch: Bitcrunch,
     /// Mixer for combining audio signals
     mixer: Mixer,
-    /// Velocity of the currently playing note
+    /// Velocity of the last played note after the response curve
     velocity: u8,
-    /// Array tracking active notes
+    /// Response curve and destinations of the velocity
+    velocity_config: VelocityConfiguration,
+    /// Per active note, the velocity of the sounding note after the response curve
+    note_velocities: [u8; AMOUNT_OF_VOICES],
+    /// Per active note, the velocity of the note that starts next, a stolen voice fades out first
+    next_velocities: [u8; AMOUNT_OF_VOICES],
+    /// LFO, depth, delay and voices of the vibrato
+    vibrato_config: VibratoConfiguration,
+    /// Per active note, the remaining samples before the vibrato sets in
+    vibrato_delays: [u32; AMOUNT_OF_VOICES],
+    /// Note number of the currently playing note
+    note: u8,
+    /// Position of the mod wheel (0-127)
+    mod_wheel: u8,
+    /// Channel pressure (0-127)
+    aftertouch: u8,
+    /// Note number played by every voice, kept during the release
+    voice_notes: [u8; AMOUNT_OF_VOICES],
+    /// Pitch bend (-8192 to 8191)
+    pitch_bend: i16,
+    /// Pitch change of a full pitch bend in cents
+    pitch_bend_range: u16,
+    /// How much the mod wheel controls the LFO depth (0-100)
+    mod_wheel_to_lfo_depth: u8,
+    /// Whether the sustain pedal is pressed
+    sustain: bool,
+    /// Whether the sostenuto pedal is pressed
+    sostenuto: bool,
+    /// Per active note, whether its key was released while a pedal kept it playing
+    sustained_notes: [bool; AMOUNT_OF_VOICES],
+    /// Per active note, whether its key was down when the sostenuto pedal was pressed
+    sostenuto_notes: [bool; AMOUNT_OF_VOICES],
+    /// Parser for incoming MIDI bytes
+    midi_parser: MidiParser,
+    /// MIDI channel the synthesizer responds to
+    midi_channel: MidiChannel,
+    /// Index of the last played note in the active notes, its filter envelope drives the output filter
+    last_note_id: usize,
+    /// Array tracking active notes, `NO_NOTE` marks a free slot
     active_note: [u8; AMOUNT_OF_VOICES],
+    /// Per active note, the number of the note on that started it, used to find the oldest note
+    note_order: [u32; AMOUNT_OF_VOICES],
+    /// Number of the last note on
+    note_counter: u32,
+    /// Policy to pick the voice of a new note when every voice is playing
+    voice_stealing: VoiceStealing,
+    /// Held keys in `Mono` mode
+    note_stack: NoteStack,
+    /// Which held key sounds in `Mono` mode
+    note_priority: NotePriority,
+    /// Retrigger or legato in `Mono` mode
+    mono_mode: MonoMode,
+    /// Per active note, the remaining samples to fade out a stolen voice, 0 = not stolen
+    steal_fades: [u16; AMOUNT_OF_VOICES],
+    /// Length in samples of the fade out of a stolen voice
+    steal_fade_length: u16,
+    /// Frequency of every MIDI note
+    tuning: TuningTable,
+    /// Frequency of A4 in Hz as 16.16 fixed point, transposes the whole tuning
+    master_tune: u32,
+    /// Tempo from a set BPM or from MIDI clock, drives the tempo synced LFOs
+    clock: TempoClock,
+    /// Sample rate in Hz
+    sample_rate: u16,
     /// Current operating mode of the synthesizer
     mode: SynthMode,
+    /// Scratch buffers for block rendering
+    buffers: BlockBuffers,
 }
 
 ///
@@ -78,19 +212,61 @@ impl Synth {
         patch: &Patch,
         wavetables: alloc::sync::Arc<BoxedWavetables>,
     ) -> Self {
+        let mut patch = *patch;
+        patch.migrate_legacy_routes();
+        let patch = &patch;
         Self {
             voices: Synth::init_voices(sample_rate, patch, Arc::clone(&wavetables)),
             envelops: Synth::init_envs(sample_rate, patch),
+            filter_envelops: array_init::array_init(|_| {
+                envelope::EnvelopeGenerator::new(patch.filter_envelop, sample_rate)
+            }),
             lfo: Synth::init_lfos(sample_rate, patch, Arc::clone(&wavetables)),
             //   sampler: Sampler::new(sample_rate, Rc::clone(&sample)),
-            filter: Filter::new(patch.filter_config),
-            mixer: Mixer::new(patch.mixer_config),
+            filter: [
+                Filter::new(patch.filter_config),
+                Filter::new(patch.filter_config),
+            ],
+            voice_filters: array_init::array_init(|_| Filter::new(patch.filter_config)),
+            mixer: Mixer::new(patch.mixer_config, patch.synth_config.mode),
             overdrive: Overdrive::new(patch.overdrive_config),
             bitcrunch: Bitcrunch::new(patch.bitcrunch_config),
             router: Router::new(patch.routering_config),
             velocity: 0,
-            active_note: [0; AMOUNT_OF_VOICES],
+            velocity_config: patch.velocity_config,
+            note_velocities: [0; AMOUNT_OF_VOICES],
+            next_velocities: [0; AMOUNT_OF_VOICES],
+            vibrato_config: patch.vibrato_config,
+            vibrato_delays: [0; AMOUNT_OF_VOICES],
+            note: 0,
+            mod_wheel: 0,
+            aftertouch: 0,
+            voice_notes: [0; AMOUNT_OF_VOICES],
+            pitch_bend: 0,
+            pitch_bend_range: patch.synth_config.pitch_bend_range,
+            mod_wheel_to_lfo_depth: patch.synth_config.mod_wheel_to_lfo_depth.min(100),
+            sustain: false,
+            sostenuto: false,
+            sustained_notes: [false; AMOUNT_OF_VOICES],
+            sostenuto_notes: [false; AMOUNT_OF_VOICES],
+            midi_parser: MidiParser::new(),
+            midi_channel: MidiChannel::Omni,
+            last_note_id: 0,
+            active_note: [NO_NOTE; AMOUNT_OF_VOICES],
+            note_order: [0; AMOUNT_OF_VOICES],
+            note_counter: 0,
+            voice_stealing: patch.synth_config.voice_stealing,
+            note_stack: NoteStack::new(),
+            note_priority: patch.synth_config.note_priority,
+            mono_mode: patch.synth_config.mono_mode,
+            steal_fades: [0; AMOUNT_OF_VOICES],
+            steal_fade_length: (sample_rate as u32 * STEAL_FADE_TIME / 1000).max(1) as u16,
+            tuning: TuningTable::equal_temperament(),
+            master_tune: DEFAULT_MASTER_TUNE,
+            clock: TempoClock::new(sample_rate),
+            sample_rate,
             mode: patch.synth_config.mode,
+            buffers: BlockBuffers::new(),
         }
     }
 
@@ -128,16 +304,10 @@ impl Synth {
         sample_rate: u16,
         patch: &Patch,
         wavetables: Arc<BoxedWavetables>,
-    ) -> [wavetable_oscillator::WaveTableOscillator; AMOUNT_OF_VOICES / 2] {
-        let voices: [wavetable_oscillator::WaveTableOscillator; AMOUNT_OF_VOICES / 2] =
-            array_init::array_init(|i: usize| {
-                wavetable_oscillator::WaveTableOscillator::new_lfo(
-                    patch.lfos[i],
-                    sample_rate,
-                    Arc::clone(&wavetables),
-                )
-            });
-        voices
+    ) -> [Lfo; AMOUNT_OF_VOICES / 2] {
+        array_init::array_init(|i: usize| {
+            Lfo::new(i, patch.lfos[i], sample_rate, Arc::clone(&wavetables))
+        })
     }
 
     ///
@@ -147,192 +317,886 @@ impl Synth {
     /// * `patch` - A `Patch` struct containing configuration data for the LttL Weirdo Wavetable Synthesizer engine.
     ///
     pub fn load_patch(&mut self, patch: &Patch) {
+        let mut patch = *patch;
+        patch.migrate_legacy_routes();
+        let patch = &patch;
         self.mode = patch.synth_config.mode;
+        self.pitch_bend_range = patch.synth_config.pitch_bend_range;
+        self.mod_wheel_to_lfo_depth = patch.synth_config.mod_wheel_to_lfo_depth.min(100);
+        self.voice_stealing = patch.synth_config.voice_stealing;
+        self.note_priority = patch.synth_config.note_priority;
+        self.mono_mode = patch.synth_config.mono_mode;
+        self.velocity_config = patch.velocity_config;
+        self.vibrato_config = patch.vibrato_config;
 
         for i in 0..AMOUNT_OF_VOICES {
             self.voices[i].reload(patch.voices[i]);
             self.envelops[i].reload(patch.envelops[i]);
+            self.filter_envelops[i].reload(patch.filter_envelop);
+        }
+        for (lfo, config) in self.lfo.iter_mut().zip(patch.lfos.iter()) {
+            lfo.set_beat_length(self.clock.beat_length());
+            lfo.reload(*config);
         }
 
         //effects
-        self.filter.reload(patch.filter_config);
+        for filter in self.filter.iter_mut().chain(self.voice_filters.iter_mut()) {
+            filter.reload(patch.filter_config);
+        }
         self.overdrive.reload(patch.overdrive_config);
 
+        //routing
+        self.router.reload(patch.routering_config);
+
         //mix
-        self.mixer.reload(patch.mixer_config);
+        self.mixer.reload(patch.mixer_config, self.mode);
     }
 
     ///
-    /// Generates a stereo audio sample by processing all voices and applying effects.
-    /// This function should be called every time an audio device requests a new sample.
+    /// Generates a block of stereo frames by processing all voices and applying effects.
+    /// Every stage runs over the whole block before the next stage starts, the result is
+    /// identical to generating the frames one by one.
     ///
-    /// # Returns
-    /// An array containing left and right channel samples
-    fn clock(&mut self) -> [i16; 2] {
-        // Pre-allocate arrays for generated signals
-        let mut generate_voices: [i16; AMOUNT_OF_VOICES] = [0; AMOUNT_OF_VOICES];
-        let mut generate_lfos: [i16; AMOUNT_OF_VOICES / 2] = [0; AMOUNT_OF_VOICES / 2];
-        let mut generate_env: [i16; AMOUNT_OF_VOICES] = [0; AMOUNT_OF_VOICES];
-        let mut sound_mixing: [i16; AMOUNT_OF_OUTPUT_CHANNELS] = [0; AMOUNT_OF_OUTPUT_CHANNELS];
-
-        // Clock voices and envelopes once
-        for i in 0..AMOUNT_OF_VOICES {
-            generate_voices[i] = self.voices[i].clock(None);
-            generate_env[i] = self.envelops[i].clock(None);
+    /// # Arguments
+    /// * `frames` - Output frames (left and right channel), at most `BLOCK_SIZE` long
+    fn render_block(&mut self, frames: &mut [[i16; 2]]) {
+        let mut start = 0;
+        while start < frames.len() {
+            // Split the block where a stolen voice has faded out, its new note starts right after
+            let end = self
+                .steal_fades
+                .iter()
+                .filter(|fade| **fade > 0)
+                .map(|fade| start + *fade as usize)
+                .min()
+                .unwrap_or(frames.len())
+                .min(frames.len());
+            self.render_frames(&mut frames[start..end]);
+            self.advance_steal_fades((end - start) as u16);
+            self.clock.advance(end - start);
+            start = end;
         }
+    }
+
+    /// Generates stereo frames, the part of `render_block` up to the next voice event
+    fn render_frames(&mut self, frames: &mut [[i16; 2]]) {
+        let amount_of_frames = frames.len();
+        let buffers = &mut self.buffers;
 
         // Process LFOs
         for i in 0..AMOUNT_OF_VOICES / 2 {
-            let lfo: i32 = self.lfo[i].clock(None) as i32;
-            let lfo_percentage = ((lfo + i16::MAX as i32) as u32 * 100) / u16::MAX as u32;
-            generate_lfos[i] = lfo_percentage as i16;
+            self.lfo[i].clock_block(&mut buffers.lfos[i][..amount_of_frames]);
         }
 
-        // Route LFO modulation to voices
-        for i in 0..AMOUNT_OF_VOICES / 2 {
-            if self.router.config.lfo_to_voice[i].enable {
-                for j in 0..2 {
-                    if self.router.config.lfo_to_voice[i].voices[j] != 255 {
-                        generate_voices[self.router.config.lfo_to_voice[i].voices[j] as usize] =
-                            math::percentage(
-                                generate_voices
-                                    [self.router.config.lfo_to_voice[i].voices[j] as usize],
-                                generate_lfos[i],
-                            );
+        // Clock envelopes
+        for i in 0..AMOUNT_OF_VOICES {
+            self.envelops[i].clock_block(&mut buffers.envelops[i][..amount_of_frames]);
+        }
+
+        // Clock filter envelopes, one per note
+        for i in 0..AMOUNT_OF_VOICES / self.mode as usize {
+            self.filter_envelops[i]
+                .clock_block(&mut buffers.filter_envelops[i][..amount_of_frames]);
+        }
+
+        // The mod wheel scales the depth of the LFO slots and the vibrato
+        let lfo_depth = MODULATION_FULL_SCALE
+            - self.mod_wheel_to_lfo_depth as i32 * 100 * (127 - self.mod_wheel as i32) / 127;
+
+        // Run the modulation matrix
+        let modulations = &mut buffers.modulations;
+        self.router.modulate(
+            &ModulationSources {
+                lfos: &buffers.lfos,
+                envelops: &buffers.envelops,
+                velocity: self.velocity,
+                note: self.note,
+                mod_wheel: self.mod_wheel,
+                aftertouch: self.aftertouch,
+                lfo_depth: lfo_depth as u16,
+            },
+            amount_of_frames,
+            modulations,
+        );
+
+        // Clock voices
+        let pitch_bend = self.pitch_bend as i32 * self.pitch_bend_range as i32 / 8192;
+        let divider = self.mode as usize;
+        for i in 0..AMOUNT_OF_VOICES {
+            // An earlier voice of the same note may modulate this voice
+            let config = self.voices[i].config;
+            let modulator = match config.modulation {
+                VoiceModulation::None => None,
+                _ => {
+                    let first = i - i % divider;
+                    let modulator = first + config.modulator as usize;
+                    (modulator < i).then_some(modulator)
+                }
+            };
+            let (earlier, voices) = buffers.voices.split_at_mut(i);
+            let voice = &mut voices[0][..amount_of_frames];
+            let pitch = &modulations.pitch[i];
+            let frequency = &modulations.frequency[i];
+            let position = &modulations.wavetable_position[i];
+            let vibrato = buffers
+                .lfos
+                .get(self.vibrato_config.lfo as usize)
+                .filter(|_| self.vibrato_config.applies_to(i));
+            // Samples of the block before the vibrato of the note sets in
+            let onset = self.vibrato_delays[i / divider] as usize;
+            let bend = pitch.active || vibrato.is_some();
+            if !bend {
+                self.voices[i].modulate_pitch(pitch_bend);
+            }
+            if !frequency.active {
+                self.voices[i].modulate_frequency(0);
+            }
+            for (n, sample) in voice.iter_mut().enumerate() {
+                if bend {
+                    let mut cents = pitch_bend;
+                    if pitch.active {
+                        cents += router::scale(pitch.signal[n], 1200);
+                    }
+                    if let Some(lfo) = vibrato.filter(|_| n >= onset) {
+                        cents +=
+                            self.vibrato_config.cents(lfo[n]) * lfo_depth / MODULATION_FULL_SCALE;
+                    }
+                    self.voices[i].modulate_pitch(cents);
+                }
+                if frequency.active {
+                    // Scaled to 24.8 first, the full 16.16 range would overflow
+                    let offset = router::scale(frequency.signal[n], VOICE_FREQUENCY_RANGE << 8);
+                    self.voices[i].modulate_frequency(offset << 8);
+                }
+                if position.active {
+                    self.voices[i]
+                        .modulate_position(router::scale(position.signal[n], u16::MAX as i32));
+                }
+                // The modulator after its envelope
+                let input = modulator.map(|modulator| {
+                    let envelop = self.router.config.voices_to_envelop[modulator] as usize;
+                    math::apply_level(earlier[modulator][n], buffers.envelops[envelop][n])
+                });
+                *sample = self.voices[i].clock(input);
+                if let Some(modulator) = modulator {
+                    if config.modulation == VoiceModulation::HardSync
+                        && buffers.cycle_starts[modulator][n]
+                    {
+                        self.voices[i].sync();
+                    }
+                }
+                buffers.cycle_starts[i][n] = self.voices[i].cycle_started();
+            }
+
+            // Fade out a stolen voice
+            let fade = self.steal_fades[i / divider] as i32;
+            if fade > 0 {
+                for (n, sample) in voice.iter_mut().enumerate() {
+                    let gain = (fade - n as i32) * 100 / self.steal_fade_length as i32;
+                    *sample = math::percentage(*sample, gain as i16);
+                }
+            }
+        }
+
+        // Count down the delays before the vibrato of the notes sets in
+        for delay in self.vibrato_delays.iter_mut() {
+            *delay = delay.saturating_sub(amount_of_frames as u32);
+        }
+
+        // Apply per-voice filters
+        let filter_config = self.filter[0].config;
+        let per_voice_filter = filter_config.enabled && filter_config.per_voice;
+        if per_voice_filter {
+            for i in 0..AMOUNT_OF_VOICES {
+                if self.mixer.config.gain_voices[i] == 0 {
+                    continue;
+                }
+                let envelop = &buffers.envelops[self.router.config.voices_to_envelop[i] as usize];
+                // Follow the note relative to C4, in cents
+                let key_tracking = math::cents_to_ratio(
+                    (self.voice_notes[i] as i32 - 60) * filter_config.key_tracking as i32,
+                );
+                let filter_envelop = &buffers.filter_envelops[i / self.mode as usize];
+                let velocity_cutoff = self
+                    .velocity_config
+                    .cutoff_offset(self.note_velocities[i / self.mode as usize]);
+                let filter = &mut self.voice_filters[i];
+                for (n, sample) in buffers.voices[i][..amount_of_frames].iter_mut().enumerate() {
+                    let mut cutoff = filter_config.cutoff_frequency as i32
+                        + velocity_cutoff
+                        + cutoff_offset(envelop[n], filter_config.envelope_amount)
+                        + cutoff_offset(filter_envelop[n], filter_config.filter_envelope_depth);
+                    let mut resonance = filter_config.resonance as i32;
+                    if modulations.cutoff.active {
+                        cutoff += router::scale(modulations.cutoff.signal[n], u16::MAX as i32);
                     }
+                    if modulations.resonance.active {
+                        resonance +=
+                            router::scale(modulations.resonance.signal[n], u16::MAX as i32);
+                    }
+                    let cutoff = ((cutoff.clamp(0, u16::MAX as i32) as u64 * key_tracking as u64)
+                        >> 16)
+                        .min(u16::MAX as u64) as u16;
+                    let resonance = resonance.clamp(0, u16::MAX as i32) as u16;
+                    if filter.config.cutoff_frequency != cutoff
+                        || filter.config.resonance != resonance
+                    {
+                        filter.reload(FilterConfig {
+                            cutoff_frequency: cutoff,
+                            resonance,
+                            ..filter_config
+                        });
+                    }
+                    *sample = filter.clock(*sample);
                 }
             }
         }
 
-        // Run and route voices through envelopes and apply gain
+        // Run and route voices through envelopes, apply gain and pan them over the output channels
+        let [left, right] = &mut buffers.mix;
+        let left = &mut left[..amount_of_frames];
+        let right = &mut right[..amount_of_frames];
+        left.fill(0);
+        right.fill(0);
         for i in 0..AMOUNT_OF_VOICES {
-            generate_voices[i] = math::percentage(
-                generate_voices[i],
-                generate_env[self.router.config.voices_to_envelop[i] as usize],
-            );
-            generate_voices[i] = math::percentage(generate_voices[i], self.velocity as i16);
-            generate_voices[i] =
-                math::percentage(generate_voices[i], self.mixer.config.gain_voices[i] as i16);
-            sound_mixing[0] += generate_voices[i];
-        }
-
-        // sampler
-        /*
-        let mut sampler_sample = self.sampler.clock(None);
-        sampler_sample = math::percentage(sampler_sample, generate_env[0]);
-        sound_mixing[0] = sound_mixing[0] +  math::percentage(sampler_sample, 10);
-        */
-
-        // Stereo output (mono to stereo)
-        sound_mixing[1] = sound_mixing[0];
-
-        // Apply filter effect
-        if self.router.config.lfo_to_freq {
-            self.voices[0].manipulate_freq(
-                generate_lfos[0] as u8,
-                self.router.config.lfo_to_freq_amount,
-            );
-        }
-        if self.router.config.lfo_to_filter {
-            let lfo_filter = 1_000 + math::percentage(10_000, generate_lfos[0]);
-            if self.filter.config.cutoff_frequency != lfo_filter as u16 {
-                let mut config = self.filter.config;
-                config.cutoff_frequency = lfo_filter as u16;
-                self.filter.reload(config);
+            let envelop = &buffers.envelops[self.router.config.voices_to_envelop[i] as usize];
+            let pan = &modulations.pan[i];
+            let amplitude = &modulations.amplitude[i];
+            let velocity_gain = self
+                .velocity_config
+                .gain(self.note_velocities[i / self.mode as usize]);
+            for (n, ((mixed_left, mixed_right), (sample, level))) in left
+                .iter_mut()
+                .zip(right.iter_mut())
+                .zip(buffers.voices[i].iter().zip(envelop.iter()))
+                .enumerate()
+            {
+                let [gain_left, gain_right] = if pan.active {
+                    let pan = self.mixer.pan(i) + router::scale(pan.signal[n], 100) as i16;
+                    Mixer::pan_law(pan)
+                } else {
+                    self.mixer.pan_gains(i)
+                };
+                let mut sample = math::apply_level(*sample, *level);
+                if amplitude.active {
+                    // Bipolar sources may raise the voice up to twice its level, after the envelope
+                    // so a boost below full scale does not clip
+                    let gain = 100 + router::scale(amplitude.signal[n], 100).clamp(-100, 100);
+                    sample = (sample as i32 * gain / 100).clamp(-(i16::MAX as i32), i16::MAX as i32)
+                        as i16;
+                }
+                sample = math::percentage(sample, velocity_gain);
+                sample = math::percentage(sample, self.mixer.config.gain_voices[i] as i16);
+                // Loud voices add up beyond full scale, clip the mix instead of wrapping around
+                *mixed_left = mixed_left.saturating_add(math::percentage(sample, gain_left));
+                *mixed_right = mixed_right.saturating_add(math::percentage(sample, gain_right));
             }
         }
 
         // Apply filter to mixed signal
-        sound_mixing[0] = self.filter.clock(sound_mixing[0]);
+        let [filter_left, filter_right] = &mut self.filter;
+        // The output filter follows the velocity of the last played note
+        let velocity_cutoff = self
+            .velocity_config
+            .cutoff_offset(self.note_velocities[self.last_note_id]);
+        if per_voice_filter {
+            // Every voice has been filtered on its own
+        } else if modulations.cutoff.active
+            || modulations.resonance.active
+            || filter_config.filter_envelope_depth != 0
+            || velocity_cutoff != 0
+        {
+            let base = filter_left.config;
+            let filter_envelop = &buffers.filter_envelops[self.last_note_id];
+            for (n, (sample_left, sample_right)) in
+                left.iter_mut().zip(right.iter_mut()).enumerate()
+            {
+                let mut config = base;
+                let mut cutoff = base.cutoff_frequency as i32
+                    + velocity_cutoff
+                    + cutoff_offset(filter_envelop[n], base.filter_envelope_depth);
+                if modulations.cutoff.active {
+                    cutoff += router::scale(modulations.cutoff.signal[n], u16::MAX as i32);
+                }
+                config.cutoff_frequency = cutoff.clamp(0, u16::MAX as i32) as u16;
+                if modulations.resonance.active {
+                    let resonance = base.resonance as i32
+                        + router::scale(modulations.resonance.signal[n], u16::MAX as i32);
+                    config.resonance = resonance.clamp(0, u16::MAX as i32) as u16;
+                }
+                if filter_left.config.cutoff_frequency != config.cutoff_frequency
+                    || filter_left.config.resonance != config.resonance
+                {
+                    filter_left.reload(config);
+                    filter_right.reload(config);
+                }
+                *sample_left = filter_left.clock(*sample_left);
+                *sample_right = filter_right.clock(*sample_right);
+            }
+            // Restore the configured cutoff and resonance, modulation is applied on top of them
+            filter_left.reload(base);
+            filter_right.reload(base);
+        } else {
+            filter_left.clock_block(left);
+            filter_right.clock_block(right);
+        }
 
         // Apply final effects
-        sound_mixing[0] = math::percentage(sound_mixing[0], self.mixer.config.gain_main as i16);
-        sound_mixing[0] = self.overdrive.clock(sound_mixing[0]);
-        sound_mixing[0] = self.bitcrunch.clock(sound_mixing[0]);
-        [sound_mixing[0], sound_mixing[0]]
+        let base_threshold = self.overdrive.config.threshold;
+        for channel in [left, right] {
+            for (n, sample) in channel.iter_mut().enumerate() {
+                let mut gain = self.mixer.config.gain_main as i16;
+                if modulations.gain.active {
+                    gain = (gain + router::scale(modulations.gain.signal[n], 100) as i16)
+                        .clamp(0, 100);
+                }
+                *sample = math::percentage(*sample, gain);
+            }
+            if modulations.threshold.active {
+                for (sample, modulation) in channel.iter_mut().zip(modulations.threshold.signal) {
+                    let threshold =
+                        base_threshold as i32 + router::scale(modulation, i16::MAX as i32);
+                    self.overdrive.config.threshold = threshold.clamp(0, i16::MAX as i32) as i16;
+                    *sample = self.overdrive.clock(*sample);
+                }
+                self.overdrive.config.threshold = base_threshold;
+            } else {
+                self.overdrive.clock_block(channel);
+            }
+            self.bitcrunch.clock_block(channel);
+        }
+
+        // Interleave the channels into stereo frames
+        for ((frame, left), right) in frames
+            .iter_mut()
+            .zip(buffers.mix[0].iter())
+            .zip(buffers.mix[1].iter())
+        {
+            *frame = [*left, *right];
+        }
     }
 
     /// Play a specific note on the synthesizer
     ///
     /// # Arguments
-    /// * `note` - The MIDI note number (0-108)
+    /// * `note` - The MIDI note number (0-127)
     /// * `velocity` - The velocity of the note (0-127)
     pub fn note_on(&mut self, note: u8, velocity: u8) {
-        // Cap note range between C0 and C8
-        if self.range_safeguard(note) {
+        // Keys the tuning leaves unmapped are silent
+        if self.tuning.frequency(note).is_none() {
             return;
         };
-        // Update the mixer velocity for this voice
+        let velocity = self.velocity_config.response(velocity);
         self.velocity = velocity;
+        self.note = note;
+
+        if self.mode == SynthMode::Mono {
+            self.mono_note_on(note, velocity);
+            return;
+        }
+        let id = match self.active_note.iter().position(|n| n == &note) {
+            Some(id) => id,
+            None => match self.free_note(note) {
+                Some(id) => id,
+                None => match self.steal_note(note) {
+                    Some(id) => {
+                        // Fade out the stolen note first, the new note starts when it is silent
+                        self.sostenuto_notes[id] = false;
+                        if self.steal_fades[id] == 0 {
+                            self.steal_fades[id] = self.steal_fade_length;
+                        }
+                        id
+                    }
+                    None => return,
+                },
+            },
+        };
+        self.active_note[id] = note;
+        self.next_velocities[id] = velocity;
+        self.note_counter = self.note_counter.wrapping_add(1);
+        self.note_order[id] = self.note_counter;
+        // A note struck again while a pedal holds it is played by a key again
+        self.sustained_notes[id] = false;
+        if self.steal_fades[id] == 0 {
+            self.start_note(id, true);
+        }
+    }
+
+    /// Play a note in `Mono` mode, the held key with the highest priority sounds
+    fn mono_note_on(&mut self, note: u8, velocity: u8) {
+        self.note_stack.push(note);
+        // Legato notes keep the velocity of the note that started the phrase
+        self.next_velocities[0] = velocity;
+        self.sustained_notes[0] = false;
+        let next = self.note_stack.pick(self.note_priority).unwrap_or(note);
+        let current = self.active_note[0];
+        if current == NO_NOTE {
+            // A legato phrase starts without glide
+            self.active_note[0] = next;
+            self.start_note(0, self.mono_mode == MonoMode::Retrigger);
+        } else if next != current {
+            self.change_mono_note(next);
+        } else if next == note && self.mono_mode == MonoMode::Retrigger {
+            self.start_note(0, true);
+        }
+    }
+
+    /// Release a key in `Mono` mode, returning to the held key with the highest priority
+    fn mono_note_off(&mut self, note: u8) {
+        self.note_stack.remove(note);
+        if self.active_note[0] != note {
+            return;
+        }
+        match self.note_stack.pick(self.note_priority) {
+            Some(next) => self.change_mono_note(next),
+            None if self.sustain || self.sostenuto_notes[0] => self.sustained_notes[0] = true,
+            None => self.release_note(0),
+        }
+    }
 
+    /// Move the sounding note in `Mono` mode to another held key
+    fn change_mono_note(&mut self, note: u8) {
+        self.active_note[0] = note;
+        match self.mono_mode {
+            MonoMode::Retrigger => self.start_note(0, true),
+            MonoMode::Legato => self.tune_note(0, true),
+        }
+    }
+
+    /// Open the gates and set the frequency of the voices of an active note
+    ///
+    /// # Arguments
+    /// * `id` - Index of the note in the active notes
+    /// * `glide` - Whether the voices glide to the note when they have glide enabled
+    fn start_note(&mut self, id: usize, glide: bool) {
+        let divider = self.mode as usize;
+        self.tune_note(id, glide);
+        self.note_velocities[id] = self.next_velocities[id];
+        let time_scale = self
+            .velocity_config
+            .envelope_time_scale(self.note_velocities[id]);
+        // Sweep the filter cutoff for this note
+        self.filter_envelops[id].set_time_scale(time_scale);
+        self.filter_envelops[id].open_gate();
+        self.vibrato_delays[id] = self.vibrato_config.delay_length(self.sample_rate);
+        self.last_note_id = id;
+        // Fade in and restart the key synced LFOs
+        let other_notes = self.other_notes_sounding(id);
+        for lfo in self.lfo.iter_mut() {
+            lfo.trigger(other_notes);
+        }
+        // Open the gate for all voice envelops
+        for i in 0..divider {
+            self.envelops[id * divider + i].set_time_scale(time_scale);
+            self.envelops[id * divider + i].open_gate();
+        }
+    }
+
+    /// Whether a note other than `id` is held or still rings out in its release phase
+    fn other_notes_sounding(&self, id: usize) -> bool {
         let divider = self.mode as usize;
+        (0..AMOUNT_OF_VOICES / divider)
+            .filter(|other| *other != id)
+            .any(|other| {
+                self.active_note[other] != NO_NOTE
+                    || self.envelops[other * divider..(other + 1) * divider]
+                        .iter()
+                        .any(|envelop| envelop.level() > 0)
+            })
+    }
 
-        let id = self.add_note(note);
-        if id != 255 {
-            // If we have only one voice, play both voices with a detune
-            for i in 0..divider {
-                let freq: u16 =
-                    MIDI2FREQ[(note as i8 + self.voices[id * divider + i].config.detune) as usize];
-                // Update the frequency of the voices
-                self.voices[id * divider + i].change_freq(
-                    (freq as i16 + self.voices[id * divider + i].config.freq_detune as i16) as u16,
-                );
-                //   self.sampler.change_freq(freq);
-                // Open the gate for all voice envelops
-                self.envelops[id * divider + i].open_gate();
+    /// Set the frequency of the voices of an active note
+    ///
+    /// # Arguments
+    /// * `id` - Index of the note in the active notes
+    /// * `glide` - Whether the voices glide to the note when they have glide enabled
+    fn tune_note(&mut self, id: usize, glide: bool) {
+        let note = self.active_note[id];
+        let divider = self.mode as usize;
+        // If we have only one voice, play both voices with a detune
+        for i in 0..divider {
+            let detune = self.voices[id * divider + i].config.detune;
+            // A detune onto an unmapped key plays the note itself
+            let freq = self
+                .note_frequency(note as i16 + detune as i16)
+                .or_else(|| self.note_frequency(note as i16))
+                .unwrap_or_default();
+            let voice = &mut self.voices[id * divider + i];
+            // A negative detune below the lowest notes stops at 0 Hz instead of wrapping around
+            let freq =
+                (freq as i64 + ((voice.config.freq_detune as i64) << FREQ_SHIFT)).max(0) as u32;
+            // Update the frequency of the voices
+            if glide {
+                voice.change_freq(freq);
+            } else {
+                voice.set_freq(freq);
+            }
+            self.voice_notes[id * divider + i] = note;
+            //   self.sampler.change_freq(freq);
+        }
+    }
+
+    /// Frequency of a key in the tuning, transposed by the master tune
+    ///
+    /// # Returns
+    /// The frequency in Hz as 16.16 fixed point, `None` for keys outside the MIDI range or not mapped
+    fn note_frequency(&self, key: i16) -> Option<u32> {
+        let frequency = self.tuning.frequency(u8::try_from(key).ok()?)?;
+        Some((frequency as u64 * self.master_tune as u64 / DEFAULT_MASTER_TUNE as u64) as u32)
+    }
+
+    /// Retune the sounding notes after the tuning or master tune changed
+    fn retune_notes(&mut self) {
+        for id in 0..AMOUNT_OF_VOICES / self.mode as usize {
+            if self.active_note[id] != NO_NOTE && self.steal_fades[id] == 0 {
+                self.tune_note(id, false);
+            }
+        }
+    }
+
+    /// Find a free slot for a new note
+    ///
+    /// # Returns
+    /// The index in the active notes, `None` when every voice is playing
+    fn free_note(&self, note: u8) -> Option<usize> {
+        let amount_of_notes = AMOUNT_OF_VOICES / self.mode as usize;
+        let mut free = (0..amount_of_notes).filter(|id| self.active_note[*id] == NO_NOTE);
+        if self.voice_stealing == VoiceStealing::SameNote {
+            // Prefer the voice that played this note before
+            let divider = self.mode as usize;
+            if let Some(id) = free
+                .clone()
+                .find(|id| self.voice_notes[id * divider] == note)
+            {
+                return Some(id);
+            }
+        }
+        free.next()
+    }
+
+    /// Pick the active note to steal for a new note, following the voice stealing policy
+    ///
+    /// # Returns
+    /// The index in the active notes, `None` when the new note should be ignored
+    fn steal_note(&self, note: u8) -> Option<usize> {
+        let amount_of_notes = AMOUNT_OF_VOICES / self.mode as usize;
+        let divider = self.mode as usize;
+        // Older notes first, order numbers wrap around
+        let age = |id: &usize| self.note_counter.wrapping_sub(self.note_order[*id]);
+        let ids = 0..amount_of_notes;
+        match self.voice_stealing {
+            VoiceStealing::None => None,
+            VoiceStealing::Oldest | VoiceStealing::SameNote => ids.max_by_key(age),
+            VoiceStealing::Quietest => ids.min_by_key(|id| {
+                let level: i32 = (0..divider)
+                    .map(|i| self.envelops[id * divider + i].level() as i32)
+                    .sum();
+                // The oldest of the equally quiet notes
+                (level, u32::MAX - age(id))
+            }),
+            VoiceStealing::LowestNote => ids
+                .max_by_key(|id| self.active_note[*id])
+                .filter(|id| self.active_note[*id] > note),
+            VoiceStealing::HighestNote => ids
+                .min_by_key(|id| self.active_note[*id])
+                .filter(|id| self.active_note[*id] < note),
+        }
+    }
+
+    /// Count down the fades of stolen voices, a voice that faded out starts its new note
+    fn advance_steal_fades(&mut self, frames: u16) {
+        let divider = self.mode as usize;
+        for id in 0..AMOUNT_OF_VOICES {
+            if self.steal_fades[id] == 0 {
+                continue;
+            }
+            self.steal_fades[id] = self.steal_fades[id].saturating_sub(frames);
+            if self.steal_fades[id] == 0 {
+                // Start the new note from silence
+                for i in 0..divider {
+                    self.envelops[id * divider + i].reset();
+                }
+                self.filter_envelops[id].reset();
+                if self.active_note[id] != NO_NOTE {
+                    self.start_note(id, true);
+                }
             }
         }
     }
 
     pub fn note_off(&mut self, note: u8) {
+        if self.mode == SynthMode::Mono {
+            self.mono_note_off(note);
+            return;
+        }
+        if let Some(id) = self.sounding_note(note) {
+            // Keep the note playing until the pedals holding it are released
+            if self.sustain || self.sostenuto_notes[id] {
+                self.sustained_notes[id] = true;
+            } else {
+                self.release_note(id);
+            }
+        }
+    }
+
+    /// Find a key that is sounding, a free slot in the active notes does not match
+    ///
+    /// # Returns
+    /// The index in the active notes, `None` when the key is not sounding
+    fn sounding_note(&self, note: u8) -> Option<usize> {
+        (0..AMOUNT_OF_VOICES / self.mode as usize)
+            .find(|id| note != NO_NOTE && self.active_note[*id] == note)
+    }
+
+    /// Close the gates of an active note, starting the release phase of its envelopes
+    ///
+    /// # Arguments
+    /// * `id` - Index of the note in the active notes
+    fn release_note(&mut self, id: usize) {
         let divider = self.mode as usize;
-        let id = self.remove_note(note);
-        if id != 255 {
-            for i in 0..divider {
-                self.envelops[id * divider + i].close_gate();
+        self.active_note[id] = NO_NOTE;
+        self.sustained_notes[id] = false;
+        self.sostenuto_notes[id] = false;
+        for i in 0..divider {
+            self.envelops[id * divider + i].close_gate();
+        }
+        self.filter_envelops[id].close_gate();
+    }
+
+    /// Bend the pitch of all voices
+    ///
+    /// # Arguments
+    /// * `value` - The pitch bend from -8192 (down) to 8191 (up), a full bend is the pitch bend range of the patch
+    pub fn pitch_bend(&mut self, value: i16) {
+        self.pitch_bend = value.clamp(-8192, 8191);
+    }
+
+    /// Press or release the sustain pedal, released notes keep playing while the pedal is pressed
+    pub fn sustain(&mut self, on: bool) {
+        self.sustain = on;
+        if !on {
+            self.release_sustained_notes();
+        }
+    }
+
+    /// Press or release the sostenuto pedal, only the notes held down while pressing the pedal
+    /// keep playing after their keys are released
+    pub fn sostenuto(&mut self, on: bool) {
+        if on && !self.sostenuto {
+            for id in 0..AMOUNT_OF_VOICES {
+                self.sostenuto_notes[id] =
+                    self.active_note[id] != NO_NOTE && !self.sustained_notes[id];
             }
+        } else if !on {
+            self.sostenuto_notes = [false; AMOUNT_OF_VOICES];
+            self.release_sustained_notes();
         }
+        self.sostenuto = on;
     }
 
-    /// Add a note to the active notes list
-    /// Returns the index of the note in the active notes array, or 255 if no space
-    fn add_note(&mut self, note: u8) -> usize {
-        let amount_of_notes: usize = 8 / self.mode as usize;
-        match self.active_note.iter().position(|n| n == &note) {
-            Some(position) => position,
-            None => match self.active_note.iter().position(|n| n == &0) {
-                Some(position) => {
-                    if position < amount_of_notes {
-                        self.active_note[position] = note;
-                        position
-                    } else {
-                        255
-                    }
-                }
-                None => 255,
-            },
+    /// Release the notes whose keys are up and are no longer held by a pedal
+    fn release_sustained_notes(&mut self) {
+        if self.sustain {
+            return;
+        }
+        for id in 0..AMOUNT_OF_VOICES {
+            if self.sustained_notes[id] && !self.sostenuto_notes[id] {
+                self.release_note(id);
+            }
         }
     }
 
-    /// Remove a note from the active notes list
-    /// Returns the index of the note that was removed, or 255 if not found
-    fn remove_note(&mut self, note: u8) -> usize {
-        match self.active_note.iter().position(|n| n == &note) {
-            Some(position) => {
-                self.active_note[position] = 0;
-                position
+    /// Release all playing notes, including notes held by the pedals
+    pub fn all_notes_off(&mut self) {
+        self.note_stack.clear();
+        for id in 0..AMOUNT_OF_VOICES {
+            if self.active_note[id] != NO_NOTE {
+                self.release_note(id);
             }
-            None => 255,
         }
     }
 
+    /// Silence all notes immediately, without a release phase
+    pub fn all_sound_off(&mut self) {
+        self.note_stack.clear();
+        self.steal_fades = [0; AMOUNT_OF_VOICES];
+        self.sustained_notes = [false; AMOUNT_OF_VOICES];
+        self.sostenuto_notes = [false; AMOUNT_OF_VOICES];
+        self.active_note = [NO_NOTE; AMOUNT_OF_VOICES];
+        for envelop in self
+            .envelops
+            .iter_mut()
+            .chain(self.filter_envelops.iter_mut())
+        {
+            envelop.reset();
+        }
+    }
+
+    /// Reset the mod wheel, aftertouch, pitch bend and pedals
+    pub fn reset_all_controllers(&mut self) {
+        self.mod_wheel = 0;
+        self.aftertouch = 0;
+        self.pitch_bend = 0;
+        self.sostenuto(false);
+        self.sustain(false);
+    }
+
+    /// Replace the tuning table, sounding notes are retuned right away
+    ///
+    /// # Arguments
+    /// * `tuning` - The frequency of every MIDI note, see `TuningTable::from_scala`
+    pub fn load_tuning(&mut self, tuning: TuningTable) {
+        self.tuning = tuning;
+        self.retune_notes();
+    }
+
+    /// Change the master tune, the frequency of A4 that transposes the whole tuning
+    ///
+    /// # Arguments
+    /// * `frequency` - The frequency of A4 in Hz as 16.16 fixed point, `DEFAULT_MASTER_TUNE` is 440 Hz
+    pub fn change_master_tune(&mut self, frequency: u32) {
+        self.master_tune = frequency;
+        self.retune_notes();
+    }
+
+    /// Set the tempo of the tempo synced LFOs, a running MIDI clock takes over again with its next ticks
+    ///
+    /// # Arguments
+    /// * `tempo` - Tempo in hundredths of a BPM (12000 = 120 BPM)
+    pub fn set_tempo(&mut self, tempo: u32) {
+        self.clock.set_tempo(tempo);
+        for lfo in self.lfo.iter_mut() {
+            lfo.set_beat_length(self.clock.beat_length());
+        }
+    }
+
+    /// Current tempo in hundredths of a BPM, set with `set_tempo` or measured from the MIDI clock
+    pub fn tempo(&self) -> u32 {
+        self.clock.tempo()
+    }
+
+    /// Follow a MIDI clock tick with the tempo and, while the clock runs, the phase of the synced LFOs
+    fn clock_tick(&mut self) {
+        let tick = self.clock.tick();
+        for lfo in self.lfo.iter_mut() {
+            lfo.set_beat_length(self.clock.beat_length());
+            if let Some(tick) = tick {
+                lfo.sync(tick);
+            }
+        }
+    }
+
+    /// Change the MIDI channel the synthesizer responds to
+    pub fn change_midi_channel(&mut self, channel: MidiChannel) {
+        self.midi_channel = channel;
+    }
+
+    /// Handle a stream of MIDI bytes, messages may be split over several calls and use running status
+    ///
+    /// # Arguments
+    /// * `bytes` - Raw MIDI bytes, for example as received from a UART
+    ///
+    /// # Returns
+    /// The program of the last program change, the patch to load is left to the caller
+    pub fn handle_midi(&mut self, bytes: &[u8]) -> Option<u8> {
+        let mut program = None;
+        for byte in bytes {
+            if let Some(message) = self.midi_parser.parse(*byte) {
+                program = self.handle_midi_message(message).or(program);
+            }
+        }
+        program
+    }
+
+    /// Handle a single MIDI message, channel messages on other channels than the receive channel are ignored
+    ///
+    /// # Returns
+    /// The program of a program change, the patch to load is left to the caller
+    pub fn handle_midi_message(&mut self, message: MidiMessage) -> Option<u8> {
+        if let Some(channel) = message.channel() {
+            if !self.midi_channel.accepts(channel) {
+                return None;
+            }
+        }
+        match message {
+            MidiMessage::NoteOn {
+                note, velocity: 0, ..
+            }
+            | MidiMessage::NoteOff { note, .. } => self.note_off(note),
+            MidiMessage::NoteOn { note, velocity, .. } => self.note_on(note, velocity),
+            MidiMessage::PolyAftertouch { note, pressure, .. } => {
+                if self.sounding_note(note).is_some() {
+                    self.change_aftertouch(pressure);
+                }
+            }
+            MidiMessage::ChannelPressure { pressure, .. } => self.change_aftertouch(pressure),
+            MidiMessage::PitchBend { value, .. } => self.pitch_bend(value),
+            MidiMessage::ControlChange {
+                controller, value, ..
+            } => match controller {
+                midi::controller::MOD_WHEEL => self.change_mod_wheel(value),
+                midi::controller::VOLUME => {
+                    self.change_main_volume((value as u16 * 100 / 127) as u8)
+                }
+                midi::controller::SUSTAIN => self.sustain(value >= 64),
+                midi::controller::SOSTENUTO => self.sostenuto(value >= 64),
+                midi::controller::RESONANCE => self.change_resonance(value),
+                midi::controller::CUTOFF => self.change_cutoff(value),
+                midi::controller::ALL_SOUND_OFF => self.all_sound_off(),
+                midi::controller::RESET_ALL_CONTROLLERS => self.reset_all_controllers(),
+                midi::controller::ALL_NOTES_OFF => self.all_notes_off(),
+                _ => {}
+            },
+            MidiMessage::ProgramChange { program, .. } => return Some(program),
+            MidiMessage::TimingClock => self.clock_tick(),
+            MidiMessage::Start => self.clock.start(),
+            MidiMessage::Continue => self.clock.resume(),
+            MidiMessage::Stop => self.clock.stop(),
+        }
+        None
+    }
+
     ///
     /// Returns a 16-bit sample value representing the synthesized audio signal.
     /// This function should be called every time an audio device requests a new sample, and it will compute the correct sample at the current time based on the internal state of the synthesizer and the desired sample rate.
     /// You need to compensate the delta time yourself.
     ///
     pub fn clock_and_output(&mut self) -> [i16; 2] {
-        self.clock()
+        let mut frame = [[0i16; 2]; 1];
+        self.render_block(&mut frame);
+        frame[0]
+    }
+
+    ///
+    /// Renders a buffer of stereo frames in one go, processing the synthesizer in blocks of
+    /// `BLOCK_SIZE` frames. The output is bit-identical to calling `clock_and_output` once per frame.
+    ///
+    /// # Arguments
+    /// * `out` - Buffer to fill with left and right channel samples
+    ///
+    pub fn render(&mut self, out: &mut [[i16; 2]]) {
+        for block in out.chunks_mut(BLOCK_SIZE) {
+            self.render_block(block);
+        }
+    }
+
+    ///
+    /// Renders an interleaved stereo buffer (`L, R, L, R, ...`), as used by most audio drivers and DMA buffers.
+    /// The output is bit-identical to calling `clock_and_output` once per frame.
+    /// A trailing odd sample is left untouched.
+    ///
+    /// # Arguments
+    /// * `out` - Interleaved buffer to fill with left and right channel samples
+    ///
+    pub fn render_interleaved(&mut self, out: &mut [i16]) {
+        let mut frames = [[0i16; 2]; BLOCK_SIZE];
+        for block in out.chunks_mut(BLOCK_SIZE * AMOUNT_OF_OUTPUT_CHANNELS) {
+            let amount_of_frames = block.len() / AMOUNT_OF_OUTPUT_CHANNELS;
+            self.render_block(&mut frames[..amount_of_frames]);
+            for (interleaved, frame) in block.chunks_exact_mut(2).zip(frames.iter()) {
+                interleaved.copy_from_slice(frame);
+            }
+        }
     }
 
     /// Change the main volume of the synthesizer
@@ -340,26 +1204,36 @@ impl Synth {
         self.mixer.config.gain_main = velocity;
     }
 
+    /// Change the position of the mod wheel (0-127), used as modulation source
+    pub fn change_mod_wheel(&mut self, value: u8) {
+        self.mod_wheel = value.min(127);
+    }
+
+    /// Change the channel pressure (0-127), used as modulation source
+    pub fn change_aftertouch(&mut self, value: u8) {
+        self.aftertouch = value.min(127);
+    }
+
     /// Change the filter cutoff frequency
     pub fn change_cutoff(&mut self, velocity: u8) {
-        let mut config = self.filter.config;
-        config.cutoff_frequency = velocity as u16 * 255;
-        self.filter.reload(config);
+        for filter in self.filter.iter_mut() {
+            let mut config = filter.config;
+            config.cutoff_frequency = velocity as u16 * 255;
+            filter.reload(config);
+        }
     }
 
     /// Change the filter resonance
     pub fn change_resonance(&mut self, velocity: u8) {
-        let mut config = self.filter.config;
-        config.resonance = velocity as u16 * 255;
-        self.filter.reload(config);
-    }
-
-    /// Check if the note is within the valid range (C0 to C8)
-    /// Returns true if the note is outside the valid range
-    fn range_safeguard(&mut self, note: u8) -> bool {
-        if !(24..=108).contains(&note) {
-            return true;
+        for filter in self.filter.iter_mut() {
+            let mut config = filter.config;
+            config.resonance = velocity as u16 * 255;
+            filter.reload(config);
         }
-        false
     }
 }
+
+/// Offset of the filter cutoff for an envelope level (0 to `ENVELOPE_FULL_SCALE`) and a depth as percentage of the cutoff range (-100 to 100)
+fn cutoff_offset(level: i16, depth: i8) -> i32 {
+    (level as i64 * depth as i64 * u16::MAX as i64 / (100 * ENVELOPE_FULL_SCALE as i64)) as i32
+}
@@ -0,0 +1,179 @@
+//! Tempo clock
+//!
+//! Keeps the tempo of the synthesizer, set as BPM or followed from incoming MIDI clock ticks
+//! (24 per quarter note). Tempo synced LFOs take their rate from the clock, and while the MIDI
+//! clock runs they follow its ticks so they stay locked to the beat.
+
+use serde::{Deserialize, Serialize};
+
+/// MIDI clock ticks per quarter note
+pub const PULSES_PER_QUARTER_NOTE: u32 = 24;
+
+/// Tempo in hundredths of a BPM before a tempo is set or a MIDI clock is received, 120 BPM
+pub const DEFAULT_TEMPO: u32 = 12_000;
+
+/// Fixed point precision of the length of a beat (48.16)
+pub const BEAT_LENGTH_SHIFT: u32 = 16;
+
+/// Amount of tick positions kept to measure the tempo, one beat of intervals
+const TICK_HISTORY: usize = PULSES_PER_QUARTER_NOTE as usize + 1;
+
+/// Length of a note relative to its straight note value
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum DivisionKind {
+    /// The note value itself
+    #[default]
+    Straight,
+    /// Two thirds of the note value, three fit in the time of two
+    Triplet,
+    /// One and a half times the note value
+    Dotted,
+}
+
+/// A length in note values, e.g. 1/4, 1/8T or 1/16D
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct NoteDivision {
+    /// Note value as a division of a whole note (1 = whole note, 4 = quarter note, 16 = sixteenth note)
+    pub note: u8,
+    /// Straight, triplet or dotted
+    #[serde(default)]
+    pub kind: DivisionKind,
+}
+
+impl NoteDivision {
+    /// Length in MIDI clock ticks as a fraction
+    ///
+    /// # Returns
+    /// The numerator and denominator of the length, 1/8T is 64/8 ticks
+    pub fn ticks(&self) -> (u32, u32) {
+        let whole = 4 * PULSES_PER_QUARTER_NOTE;
+        let numerator = match self.kind {
+            DivisionKind::Straight => whole,
+            DivisionKind::Triplet => whole * 2 / 3,
+            DivisionKind::Dotted => whole * 3 / 2,
+        };
+        (numerator, self.note.max(1) as u32)
+    }
+}
+
+/// Length of a beat (quarter note) at a tempo
+///
+/// # Arguments
+/// * `tempo` - Tempo in hundredths of a BPM (12000 = 120 BPM)
+/// * `sample_rate` - Sample rate in Hz
+///
+/// # Returns
+/// The length of a beat in samples as 48.16 fixed point
+pub fn beat_length(tempo: u32, sample_rate: u16) -> u64 {
+    ((60 * 100 * sample_rate as u64) << BEAT_LENGTH_SHIFT) / tempo.max(1) as u64
+}
+
+/// Tempo of the synthesizer, from a set BPM or from MIDI clock
+pub struct TempoClock {
+    /// Sample rate in Hz
+    sample_rate: u16,
+    /// Length of a beat in samples as 48.16 fixed point
+    beat_length: u64,
+    /// Samples rendered so far, the time stamp of the MIDI clock ticks
+    position: u64,
+    /// Time stamps of the last MIDI clock ticks, oldest first once the history is full
+    ticks: [u64; TICK_HISTORY],
+    /// Amount of MIDI clock ticks in the history
+    received: usize,
+    /// Whether the MIDI clock runs, between a start or continue and a stop
+    running: bool,
+    /// Ticks since the last start, the next tick gets this number
+    song_position: u32,
+}
+
+impl TempoClock {
+    /// Creates a new clock at the default tempo
+    pub fn new(sample_rate: u16) -> Self {
+        Self {
+            sample_rate,
+            beat_length: beat_length(DEFAULT_TEMPO, sample_rate),
+            position: 0,
+            ticks: [0; TICK_HISTORY],
+            received: 0,
+            running: false,
+            song_position: 0,
+        }
+    }
+
+    /// Set the tempo, a running MIDI clock takes over again with its next ticks
+    ///
+    /// # Arguments
+    /// * `tempo` - Tempo in hundredths of a BPM (12000 = 120 BPM)
+    pub fn set_tempo(&mut self, tempo: u32) {
+        self.beat_length = beat_length(tempo, self.sample_rate);
+        self.received = 0;
+    }
+
+    /// Tempo in hundredths of a BPM
+    pub fn tempo(&self) -> u32 {
+        (((60 * 100 * self.sample_rate as u64) << BEAT_LENGTH_SHIFT) / self.beat_length.max(1))
+            as u32
+    }
+
+    /// Length of a beat in samples as 48.16 fixed point
+    pub fn beat_length(&self) -> u64 {
+        self.beat_length
+    }
+
+    /// Whether the MIDI clock runs
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Move the time stamp of the next MIDI clock ticks on by rendered samples
+    pub fn advance(&mut self, samples: usize) {
+        self.position += samples as u64;
+    }
+
+    /// A MIDI clock tick arrived, the tempo follows the ticks of the last beat
+    ///
+    /// # Returns
+    /// The number of the tick since the last start while the clock runs, `None` when stopped
+    pub fn tick(&mut self) -> Option<u32> {
+        if self.received == TICK_HISTORY {
+            self.ticks.copy_within(1.., 0);
+            self.received -= 1;
+        }
+        self.ticks[self.received] = self.position;
+        self.received += 1;
+        if self.received > 1 {
+            let intervals = (self.received - 1) as u64;
+            let elapsed = self.position - self.ticks[0];
+            // Average the intervals, jitter of the time stamps cancels out over a beat
+            let beat_length =
+                ((elapsed * PULSES_PER_QUARTER_NOTE as u64) << BEAT_LENGTH_SHIFT) / intervals;
+            if beat_length > 0 {
+                self.beat_length = beat_length;
+            }
+        }
+        if !self.running {
+            return None;
+        }
+        let tick = self.song_position;
+        self.song_position = self.song_position.wrapping_add(1);
+        Some(tick)
+    }
+
+    /// MIDI start, the next tick is the first tick of the song
+    pub fn start(&mut self) {
+        self.running = true;
+        self.song_position = 0;
+        // Ticks from before the start may be far apart
+        self.received = 0;
+    }
+
+    /// MIDI continue, the ticks count on from where the clock stopped
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+
+    /// MIDI stop, synced LFOs keep running at the last tempo
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+}
@@ -1,10 +1,21 @@
-/// auto generated using examples/create_note_to_freq_table.rs
-pub static MIDI2FREQ: [u16; 127] = [
-    8, 8, 9, 9, 10, 10, 11, 12, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 23, 24, 25, 27, 29, 30, 32,
-    34, 36, 38, 41, 43, 46, 48, 51, 54, 58, 61, 65, 69, 73, 77, 82, 87, 92, 97, 103, 109, 116, 123,
-    130, 138, 146, 155, 164, 174, 184, 195, 207, 219, 233, 246, 261, 277, 293, 311, 329, 349, 369,
-    391, 415, 440, 466, 493, 523, 554, 587, 622, 659, 698, 739, 783, 830, 880, 932, 987, 1046,
-    1108, 1174, 1244, 1318, 1396, 1479, 1567, 1661, 1760, 1864, 1975, 2093, 2217, 2349, 2489, 2637,
-    2793, 2959, 3135, 3322, 3520, 3729, 3951, 4186, 4434, 4698, 4978, 5274, 5587, 5919, 6271, 6644,
-    7040, 7458, 7902, 8372, 8869, 9397, 9956, 10548, 11175, 11839,
+/// Amount of fractional bits of the fixed point frequencies (16.16, in Hz)
+pub const FREQ_SHIFT: u32 = 16;
+
+/// MIDI note number (0-127) to frequency in Hz as 16.16 fixed point, 12-TET tuned to A4 = 440 Hz.
+/// Generated with `round(440 * 2^((note - 69) / 12) * 65536)`.
+pub static MIDI2FREQ: [u32; 128] = [
+    535809, 567670, 601425, 637188, 675077, 715219, 757749, 802807, 850544, 901120, 954703,
+    1011473, 1071618, 1135340, 1202851, 1274376, 1350154, 1430439, 1515497, 1605613, 1701088,
+    1802240, 1909407, 2022946, 2143237, 2270680, 2405702, 2548752, 2700309, 2860878, 3030994,
+    3211227, 3402176, 3604480, 3818814, 4045892, 4286473, 4541360, 4811404, 5097505, 5400618,
+    5721755, 6061989, 6422453, 6804352, 7208960, 7637627, 8091784, 8572947, 9082720, 9622807,
+    10195009, 10801236, 11443511, 12123977, 12844906, 13608704, 14417920, 15275254, 16183568,
+    17145893, 18165441, 19245614, 20390018, 21602472, 22887021, 24247954, 25689813, 27217409,
+    28835840, 30550508, 32367136, 34291786, 36330882, 38491228, 40780036, 43204943, 45774043,
+    48495909, 51379626, 54434817, 57671680, 61101017, 64734272, 68583572, 72661764, 76982457,
+    81560072, 86409886, 91548086, 96991818, 102759252, 108869635, 115343360, 122202033, 129468544,
+    137167144, 145323527, 153964914, 163120144, 172819773, 183096171, 193983636, 205518503,
+    217739269, 230686720, 244404066, 258937088, 274334289, 290647054, 307929828, 326240288,
+    345639545, 366192342, 387967272, 411037006, 435478539, 461373440, 488808132, 517874176,
+    548668578, 581294109, 615859655, 652480576, 691279090, 732384684, 775934544, 822074013,
 ];
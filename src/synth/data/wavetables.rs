@@ -1,10 +1,40 @@
 extern crate alloc;
+use alloc::vec;
 use alloc::vec::Vec;
 
+/// Amount of samples in one wavetable cycle
+pub const WAVETABLE_SIZE: usize = 600;
+
+/// Amount of band-limited versions (one per octave) generated on top of the original wavetable
+pub const BAND_LIMITED_LEVELS: u8 = 8;
+
+// Fractional bits of the Fourier twiddle factors
+const TWIDDLE_SHIFT: u32 = 30;
+
+// cos(2π / WAVETABLE_SIZE) and sin(2π / WAVETABLE_SIZE) in Q30, used to build the Fourier twiddle table
+const COS_STEP: i64 = 1_073_682_950;
+const SIN_STEP: i64 = 11_243_993;
+
 // Trait defining the interface for accessing wavetables by index
 pub trait Wavetables {
     // Returns a reference to the wavetable data at the specified index
     fn get_wavetable_reference(&self, index: u8) -> &[i16];
+
+    // Returns the amount of frames (cycles of WAVETABLE_SIZE samples) in the wavetable at the specified index
+    fn frames(&self, _index: u8) -> u16 {
+        1
+    }
+
+    // Returns the amount of band-limited levels available for the wavetable at the specified index
+    fn band_limited_levels(&self, _index: u8) -> u8 {
+        0
+    }
+
+    // Returns a reference to a band-limited level (all frames) of the wavetable at the specified index
+    // Level 0 is the original wavetable, every next level holds half the harmonics of the previous level
+    fn get_band_limited_reference(&self, index: u8, _level: u8) -> &[i16] {
+        self.get_wavetable_reference(index)
+    }
 }
 
 // Struct that holds multiple boxed wavetables
@@ -34,19 +64,25 @@ impl BoxedWavetables {
 }
 
 // Struct representing a single wavetable containing audio samples
+// A wavetable holds one or more frames, each frame is one cycle of WAVETABLE_SIZE samples
 #[derive(Clone)]
 pub struct BoxedWavetable {
     data: Vec<i16>,
+    // Optional band-limited levels 1..=BAND_LIMITED_LEVELS (all frames per level), stored one after the other
+    band_limited: Vec<i16>,
 }
 
 impl BoxedWavetable {
     // Creates a new wavetable from raw byte data
     // Each sample is assumed to be 2 bytes (16-bit) in big-endian format
+    // Every complete block of WAVETABLE_SIZE samples is loaded as a frame, at least one frame is expected
     pub fn new(data: &[u8]) -> Self {
+        let frames = (data.len() / (WAVETABLE_SIZE * 2)).max(1);
         let mut init = Self {
-            data: Vec::with_capacity(600),
+            data: Vec::with_capacity(frames * WAVETABLE_SIZE),
+            band_limited: Vec::new(),
         };
-        for sample_index in 0..600 {
+        for sample_index in 0..frames * WAVETABLE_SIZE {
             // Extract two bytes and combine them into a 16-bit signed integer
             // The high byte is shifted left by 8 bits and combined with the low byte
             let b1 = (data[sample_index * 2 + 1] as i16) << 8;
@@ -56,6 +92,118 @@ impl BoxedWavetable {
         }
         init
     }
+
+    // Creates a new wavetable from raw byte data, together with a band-limited version per octave
+    // Costs BAND_LIMITED_LEVELS times the memory of a single wavetable, but removes aliasing at high pitches
+    // Removing harmonics makes the waveform overshoot (Gibbs). When a level overshoots full scale the
+    // whole wavetable, original included, is scaled down by the same gain instead of clipping, so every
+    // level and every frame keeps the loudness of the full-band wavetable
+    pub fn new_band_limited(data: &[u8]) -> Self {
+        let mut init = Self::new(data);
+        let size = init.data.len();
+        let mut levels = vec![0i32; BAND_LIMITED_LEVELS as usize * size];
+        for (frame, cycle) in init.data.chunks_exact(WAVETABLE_SIZE).enumerate() {
+            let frame_levels = band_limit(cycle);
+            for (level, band_limited) in frame_levels.chunks_exact(WAVETABLE_SIZE).enumerate() {
+                let start = level * size + frame * WAVETABLE_SIZE;
+                levels[start..start + WAVETABLE_SIZE].copy_from_slice(band_limited);
+            }
+        }
+
+        // A single gain for all levels, based on the loudest sample of the full-band and band-limited levels
+        let peak = init
+            .data
+            .iter()
+            .map(|sample| *sample as i32)
+            .chain(levels.iter().copied())
+            .map(i32::abs)
+            .max()
+            .unwrap_or(0)
+            .max(i16::MAX as i32) as i64;
+        let scale = |value: i32| {
+            let scaled = value as i64 * i16::MAX as i64;
+            let rounding = if scaled < 0 { -peak / 2 } else { peak / 2 };
+            ((scaled + rounding) / peak) as i16
+        };
+        for sample in init.data.iter_mut() {
+            *sample = scale(*sample as i32);
+        }
+        init.band_limited = levels.into_iter().map(scale).collect();
+        init
+    }
+
+    // Returns the amount of frames in the wavetable
+    pub fn frames(&self) -> u16 {
+        (self.data.len() / WAVETABLE_SIZE) as u16
+    }
+
+    // Returns the amount of band-limited levels on top of the original wavetable
+    pub fn band_limited_levels(&self) -> u8 {
+        (self.band_limited.len() / self.data.len()) as u8
+    }
+
+    // Returns the band-limited level with all frames, level 0 is the original wavetable
+    fn level(&self, level: u8) -> &[i16] {
+        let level = level.min(self.band_limited_levels()) as usize;
+        if level == 0 {
+            return self.data.as_slice();
+        }
+        let size = self.data.len();
+        &self.band_limited[(level - 1) * size..level * size]
+    }
+}
+
+// Generates the band-limited levels of one wavetable cycle, before scaling to full scale
+// Takes the discrete Fourier transform of the cycle and resynthesizes it per level with only the
+// harmonics that stay below Nyquist when the cycle is played one octave higher than the previous level
+// All arithmetic is fixed point, the twiddle factors are Q30
+fn band_limit(cycle: &[i16]) -> Vec<i32> {
+    let harmonics = WAVETABLE_SIZE / 2;
+
+    // Twiddle table, built by rotating a unit vector so no trigonometric functions are needed
+    let mut cos = vec![0i64; WAVETABLE_SIZE];
+    let mut sin = vec![0i64; WAVETABLE_SIZE];
+    let (mut c, mut s) = (1i64 << TWIDDLE_SHIFT, 0i64);
+    let round = 1i64 << (TWIDDLE_SHIFT - 1);
+    for n in 0..WAVETABLE_SIZE {
+        cos[n] = c;
+        sin[n] = s;
+        (c, s) = (
+            (c * COS_STEP - s * SIN_STEP + round) >> TWIDDLE_SHIFT,
+            (s * COS_STEP + c * SIN_STEP + round) >> TWIDDLE_SHIFT,
+        );
+    }
+
+    // Spectrum of the cycle, in sample units times WAVETABLE_SIZE
+    let dc = cycle.iter().map(|sample| *sample as i64).sum::<i64>();
+    let mut real = vec![0i64; harmonics];
+    let mut imaginary = vec![0i64; harmonics];
+    for harmonic in 1..harmonics {
+        for (n, sample) in cycle.iter().enumerate() {
+            let index = (harmonic * n) % WAVETABLE_SIZE;
+            real[harmonic] += *sample as i64 * cos[index];
+            imaginary[harmonic] += *sample as i64 * sin[index];
+        }
+        real[harmonic] = (real[harmonic] + round) >> TWIDDLE_SHIFT;
+        imaginary[harmonic] = (imaginary[harmonic] + round) >> TWIDDLE_SHIFT;
+    }
+
+    // Resynthesize every level with its own amount of harmonics
+    let divisor = (WAVETABLE_SIZE as i64) << TWIDDLE_SHIFT;
+    let mut levels = vec![0i32; BAND_LIMITED_LEVELS as usize * WAVETABLE_SIZE];
+    for (level, output) in levels.chunks_exact_mut(WAVETABLE_SIZE).enumerate() {
+        let max_harmonic = harmonics >> (level + 1);
+        for (n, sample) in output.iter_mut().enumerate() {
+            let mut value = dc << TWIDDLE_SHIFT;
+            for harmonic in 1..=max_harmonic {
+                let index = (harmonic * n) % WAVETABLE_SIZE;
+                value += 2 * (real[harmonic] * cos[index] + imaginary[harmonic] * sin[index]);
+            }
+            let rounding = if value < 0 { -divisor / 2 } else { divisor / 2 };
+            *sample = ((value + rounding) / divisor) as i32;
+        }
+    }
+    levels
 }
 
 // Implementation of the Wavetables trait for BoxedWavetables
@@ -64,4 +212,16 @@ impl Wavetables for BoxedWavetables {
     fn get_wavetable_reference(&self, index: u8) -> &[i16] {
         self.data[index as usize].data.as_slice()
     }
+
+    fn frames(&self, index: u8) -> u16 {
+        self.data[index as usize].frames()
+    }
+
+    fn band_limited_levels(&self, index: u8) -> u8 {
+        self.data[index as usize].band_limited_levels()
+    }
+
+    fn get_band_limited_reference(&self, index: u8, level: u8) -> &[i16] {
+        self.data[index as usize].level(level)
+    }
 }
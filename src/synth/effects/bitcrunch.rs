@@ -1,5 +1,3 @@
-use core::i16;
-
 use serde::{Deserialize, Serialize};
 
 use crate::synth::effects::Effect;
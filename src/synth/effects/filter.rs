@@ -2,7 +2,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::synth::effects::Effect;
-use crate::synth::math::{fx_mul, ifx_mul, ucfx_mul};
+use crate::synth::math::{fx_mul, ucfx_mul};
 
 /// Types of filters available
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -20,13 +20,28 @@ pub struct FilterConfig {
     pub resonance: u16,               // Resonance amount (0-65535)
     pub kind_of_filter: KindOfFilter, // Type of filter to apply
     pub enabled: bool,                // Whether the filter is active
+    /// Run one filter per voice instead of one filter on the mixed signal
+    #[serde(default)]
+    pub per_voice: bool,
+    /// Cutoff modulation by the envelope of the voice as percentage of the cutoff range
+    /// (-100 to 100), only used by per-voice filters
+    #[serde(default)]
+    pub envelope_amount: i8,
+    /// How much the cutoff follows the note relative to C4 (0 = fixed, 100 = one octave per octave),
+    /// only used by per-voice filters
+    #[serde(default)]
+    pub key_tracking: u8,
+    /// Cutoff modulation by the filter envelope of the note as percentage of the cutoff range
+    /// (-100 to 100), the output filter follows the last played note
+    #[serde(default)]
+    pub filter_envelope_depth: i8,
 }
 
 /// Digital filter implementation
 pub struct Filter {
     pub config: FilterConfig,
-    buf0: i64,     // First buffer for filter state
-    buf1: i64,     // Second buffer for filter state
+    buf0: i64,     // First buffer for filter state, 48.16 fixed point
+    buf1: i64,     // Second buffer for filter state, 48.16 fixed point
     feedback: u32, // Feedback coefficient
 }
 
@@ -42,8 +57,10 @@ impl Effect for Filter {
 }
 
 impl Filter {
-    const SHIFTED_1: u16 = u16::MAX;
     /// Maximum u16 value
+    const SHIFTED_1: u16 = u16::MAX;
+    /// Fractional bits of the buffers, small signals keep moving instead of getting stuck
+    const STATE_SHIFT: u32 = 16;
 
     /// Create a new filter with given configuration
     pub fn new(config: FilterConfig) -> Self {
@@ -75,33 +92,31 @@ impl Filter {
 
     /// Apply the filter to an input sample
     fn filter(&mut self, sample: i16) -> i16 {
-        // Convert sample to i64 for processing
-        let sample_i64 = sample as i64;
+        // Convert sample to the precision of the buffers
+        let sample_i64 = (sample as i64) << Filter::STATE_SHIFT;
 
         // Calculate highpass component (difference from previous buffer)
         let highpass = sample_i64 - self.buf0;
 
         // Calculate bandpass component (difference between buffers)
-        let bandpass = (self.buf0 - self.buf1) as i32;
+        let bandpass = self.buf0 - self.buf1;
 
         // Apply feedback to bandpass signal
-        let feedback_on_bandpass = fx_mul(self.feedback as i64, bandpass);
+        let feedback_on_bandpass = fx_mul(bandpass, self.feedback as i32);
 
         // Update buffer states using fixed-point multiplication
-        self.buf0 += fx_mul(
-            highpass + feedback_on_bandpass,
-            self.config.cutoff_frequency as i32,
-        );
-        self.buf1 += ifx_mul(bandpass, self.config.cutoff_frequency) as i64;
-
-        // Select output based on filter type
-        let out: i16 = match self.config.kind_of_filter {
-            KindOfFilter::Low => self.buf1 as i16,
-            KindOfFilter::High => highpass as i16,
-            KindOfFilter::Band => bandpass as i16,
-            KindOfFilter::Notch => (sample_i64 - self.buf0 + self.buf1) as i16,
+        let cutoff = self.config.cutoff_frequency as i32;
+        self.buf0 += fx_mul(highpass + feedback_on_bandpass, cutoff);
+        self.buf1 += fx_mul(bandpass, cutoff);
+
+        // Select output based on filter type, rounded toward zero so silence ends at zero
+        let out = match self.config.kind_of_filter {
+            KindOfFilter::Low => self.buf1,
+            KindOfFilter::High => highpass,
+            KindOfFilter::Band => bandpass,
+            KindOfFilter::Notch => sample_i64 - self.buf0 + self.buf1,
         };
 
-        out
+        (out / (1 << Filter::STATE_SHIFT)) as i16
     }
 }
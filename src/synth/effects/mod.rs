@@ -5,4 +5,11 @@ pub mod overdrive;
 
 pub trait Effect {
     fn clock(&mut self, sample: i16) -> i16;
+
+    /// Process a block of samples in place
+    fn clock_block(&mut self, buffer: &mut [i16]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.clock(*sample);
+        }
+    }
 }
@@ -2,21 +2,38 @@
 //!
 //! This module implements an ADSR (Attack, Decay, Sustain, Release) envelope generator
 //! that produces control signals for audio synthesis. The envelope responds to gate
-//! signals and follows the standard ADSR envelope shape.
+//! signals and follows the standard ADSR envelope shape, optionally with a delay before
+//! the attack and a hold after it. A looping envelope repeats the attack and decay while
+//! the gate is open. Every segment is linear, exponential or logarithmic, the level has
+//! a 16-bit resolution.
 
 use serde::{Deserialize, Serialize};
 
-use crate::synth::math::percentage;
-
 use super::{math, Clockable};
 
+/// Level of a fully open envelope, the output runs from 0 to this level
+pub const ENVELOPE_FULL_SCALE: i16 = i16::MAX;
+
+/// Octaves an exponential segment falls over its time, the higher the stronger the curve
+const CURVE_OCTAVES: i32 = 6;
+
+/// Fixed point precision of the progress through a segment (0.16)
+const PROGRESS_SHIFT: u32 = 16;
+
+/// Level an exponential segment would still be away from its end level, 2^-CURVE_OCTAVES (0.16)
+const CURVE_FLOOR: u32 = 1 << (PROGRESS_SHIFT - CURVE_OCTAVES as u32);
+
 /// Represents the different states an envelope can be in
 #[derive(PartialEq)]
 pub enum EnvelopeState {
     /// No active signal, idle state
     Idle,
+    /// Delay phase - waiting at the current level before the attack
+    Delay,
     /// Attack phase - rising from 0% to 100%
     Attack,
+    /// Hold phase - staying at 100% before the decay
+    Hold,
     /// Decay phase - falling from 100% to sustain level
     Decay,
     /// Sustain phase - maintaining the sustain level
@@ -25,17 +42,71 @@ pub enum EnvelopeState {
     Release,
 }
 
+/// Shape of an envelope segment
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum EnvelopeCurve {
+    /// A straight line from the start to the end level
+    #[default]
+    Linear,
+    /// Fast at the start and slowing down toward the end level, like an analog envelope
+    Exponential,
+    /// Slow at the start and speeding up toward the end level
+    Logarithmic,
+}
+
+impl EnvelopeCurve {
+    /// Shape the progress through a segment
+    ///
+    /// # Arguments
+    /// * `progress` - Progress through the segment as 0.16 fixed point (0 = start, 65536 = end)
+    ///
+    /// # Returns
+    /// The part of the way from the start to the end level as 0.16 fixed point
+    fn shape(self, progress: u32) -> u32 {
+        let full = 1 << PROGRESS_SHIFT;
+        // 1 - 2^(-octaves * progress), normalised to end at exactly one
+        let exponential = |progress: u32| {
+            let fall = math::exp2(-CURVE_OCTAVES * progress as i32);
+            ((full - fall) as u64 * full as u64 / (full - CURVE_FLOOR) as u64) as u32
+        };
+        match self {
+            EnvelopeCurve::Linear => progress,
+            EnvelopeCurve::Exponential => exponential(progress),
+            EnvelopeCurve::Logarithmic => full - exponential(full - progress),
+        }
+    }
+}
+
 /// Configuration parameters for the envelope generator
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
 pub struct EnvelopConfiguration {
     /// Time for the attack phase in milliseconds
-    pub attack_time: i16,
-    /// Time for the decay phase in milliseconds  
-    pub decay_time: i16,
+    pub attack_time: i32,
+    /// Time for the decay phase in milliseconds
+    pub decay_time: i32,
     /// Time for the release phase in milliseconds
-    pub release_time: i16,
+    pub release_time: i32,
     /// Sustain level percentage (0-100)
     pub sustain_level: i16,
+    /// Shape of the attack phase
+    #[serde(default)]
+    pub attack_curve: EnvelopeCurve,
+    /// Shape of the decay phase
+    #[serde(default)]
+    pub decay_curve: EnvelopeCurve,
+    /// Shape of the release phase
+    #[serde(default)]
+    pub release_curve: EnvelopeCurve,
+    /// Time before the attack phase in milliseconds
+    #[serde(default)]
+    pub delay_time: i32,
+    /// Time at full level between the attack and decay phase in milliseconds
+    #[serde(default)]
+    pub hold_time: i32,
+    /// Repeat the attack, hold and decay phase while the gate is open, the attack restarts
+    /// from the sustain level
+    #[serde(default)]
+    pub looping: bool,
 }
 
 /// Envelope generator implementation
@@ -49,89 +120,56 @@ pub struct EnvelopeGenerator {
     // Runtime State
     /// Current state of the envelope
     state: EnvelopeState,
-    /// Cumulative time ticks since last state transition
-    cumulative_time_tick: u32,
-    /// Number of time ticks per millisecond based on sample rate
-    amount_of_time_ticks_for_1ms: u16,
-    /// Time ticks needed for one percent increase during phases
-    amout_of_time_ticks_needed_for_one_percent_increase: u32,
-    /// Current output level as percentage (0-100)
-    current_output_level_percentage: i16,
-    /// Level at which release phase starts
-    release_level: i16,
-    /// Total time ticks for current phase
-    cumalative_time_ticks_for_one_phase: u32,
-    /// Level to restart from when retriggering
-    retrigger_level: i16,
+    /// Sample rate in Hz, times are converted to time ticks without rounding the ticks per millisecond
+    sample_rate: u16,
+    /// Time ticks since the start of the current segment
+    position: u32,
+    /// Total time ticks of the current segment
+    length: u32,
+    /// Level at the start of the current segment
+    start_level: i32,
+    /// Level at the end of the current segment
+    end_level: i32,
+    /// Shape of the current segment
+    curve: EnvelopeCurve,
+    /// Current output level (0 to `ENVELOPE_FULL_SCALE`)
+    level: i32,
     /// Counter for how many times the gate has been opened
     gate_open_counter: u8,
+    /// Scale of the attack and decay times as percentage (100 = configured times)
+    time_scale: u16,
 }
 
 impl Clockable for EnvelopeGenerator {
-    // Clock the envelope, return the percentage (0..100%) of the envelope.
+    /// Clock the envelope, return the level of the envelope (0 to `ENVELOPE_FULL_SCALE`)
     fn clock(&mut self, _sample: Option<i16>) -> i16 {
-        let mut output:u32 = 0;
-    
-        // Silence when we are in the IDLE state.
-        if !self.gate && self.state == EnvelopeState::Idle {
-            return output as i16;
-        }
         match self.state {
-            EnvelopeState::Attack => {
-                if self.cumulative_time_tick > self.cumalative_time_ticks_for_one_phase {
-                    output = 100;
-                    self.decay();
-                } else {
-                    if self.amout_of_time_ticks_needed_for_one_percent_increase > 0 {
-                        output = self.retrigger_level as u32 + self.cumulative_time_tick / self.amout_of_time_ticks_needed_for_one_percent_increase;
-                    } else {
-                        output = self.current_output_level_percentage as u32;
+            EnvelopeState::Idle => self.level = 0,
+            EnvelopeState::Sustain => self.level = self.sustain_level(),
+            _ => {
+                if self.position >= self.length {
+                    self.level = self.end_level;
+                    match self.state {
+                        EnvelopeState::Delay => self.attack(),
+                        EnvelopeState::Attack => self.hold(),
+                        EnvelopeState::Hold => self.decay(),
+                        EnvelopeState::Decay if self.configuration.looping && self.gate => {
+                            self.attack()
+                        }
+                        EnvelopeState::Decay => self.state = EnvelopeState::Sustain,
+                        _ => self.state = EnvelopeState::Idle,
                     }
-                }
-            }
-            EnvelopeState::Decay => {
-                if self.cumulative_time_tick > self.cumalative_time_ticks_for_one_phase {
-                    output = self.configuration.sustain_level as u32;
-                    self.transistion_state(EnvelopeState::Sustain);
-                } else {
-                    output = 100
-                        - math::percentage(
-                            100 - self.configuration.sustain_level as i16,
-                            (self.cumulative_time_tick / self.amout_of_time_ticks_needed_for_one_percent_increase) as i16,
-                        ) as u32;
-                }
-            }
-            EnvelopeState::Sustain => {
-                output = self.configuration.sustain_level as u32;
-            }
-            EnvelopeState::Release => {
-                if self.cumulative_time_tick > self.cumalative_time_ticks_for_one_phase {
-                    output = 0;
-                    self.transistion_state(EnvelopeState::Idle);
-                    self.cumalative_time_ticks_for_one_phase = 0;
                 } else {
-                    //TODO: weird fix. need better investigation, probably some rounding issue.
-                    if self.cumulative_time_tick / self.amout_of_time_ticks_needed_for_one_percent_increase < 101 {
-                        output = math::percentage(
-                            self.release_level as i16,
-                            (100 - self.cumulative_time_tick / self.amout_of_time_ticks_needed_for_one_percent_increase) as i16,
-                        ) as u32;
-                    } else {
-                        output = 0;
-                    }
+                    let progress = ((self.position as u64) << PROGRESS_SHIFT) / self.length as u64;
+                    let shaped = self.curve.shape(progress as u32) as i64;
+                    let distance = (self.end_level - self.start_level) as i64;
+                    self.level =
+                        self.start_level + ((distance * shaped) >> PROGRESS_SHIFT) as i32;
+                    self.position += 1;
                 }
             }
-            EnvelopeState::Idle => {}
-        }
-        self.cumulative_time_tick += 1;
-
-        //safeguard, make sure we never output greater then 100%
-        if output > 100 {
-            output = 100;
         }
-      
-        self.current_output_level_percentage = output as i16;
-        self.current_output_level_percentage
+        self.level as i16
     }
 }
 
@@ -142,14 +180,15 @@ impl EnvelopeGenerator {
             configuration: envelop,
             gate: false,
             state: EnvelopeState::Idle,
-            cumulative_time_tick: 0,
-            amount_of_time_ticks_for_1ms: sample_rate / 1000,
-            amout_of_time_ticks_needed_for_one_percent_increase: 0,
-            current_output_level_percentage: 0,
-            release_level: 0,
-            cumalative_time_ticks_for_one_phase: 0,
-            retrigger_level: 0,
+            sample_rate,
+            position: 0,
+            length: 0,
+            start_level: 0,
+            end_level: 0,
+            curve: EnvelopeCurve::Linear,
+            level: 0,
             gate_open_counter: 0,
+            time_scale: 100,
         }
     }
 
@@ -158,10 +197,51 @@ impl EnvelopeGenerator {
         self.configuration = envelop;
     }
 
-    /// Transitions the envelope to a new state and resets tick counter
-    fn transistion_state(&mut self, state: EnvelopeState) {
+    /// Current level of the envelope (0 to `ENVELOPE_FULL_SCALE`)
+    pub fn level(&self) -> i16 {
+        self.level as i16
+    }
+
+    /// Scales the attack and decay times, used from the next time the gate opens
+    ///
+    /// # Arguments
+    /// * `time_scale` - Percentage of the configured times (100 = configured times)
+    pub fn set_time_scale(&mut self, time_scale: u16) {
+        self.time_scale = time_scale;
+    }
+
+    /// Attack or decay time in time ticks after the time scale
+    fn scaled_ticks(&self, time: i32) -> u32 {
+        (self.ticks(time) as u64 * self.time_scale as u64 / 100).min(u32::MAX as u64) as u32
+    }
+
+    /// Time in milliseconds as time ticks, rounded to the nearest tick
+    fn ticks(&self, time: i32) -> u32 {
+        let ticks = (time.max(0) as u64 * self.sample_rate as u64 + 500) / 1000;
+        ticks.min(u32::MAX as u64) as u32
+    }
+
+    /// Sustain level on the envelope scale
+    fn sustain_level(&self) -> i32 {
+        self.configuration.sustain_level.clamp(0, 100) as i32 * ENVELOPE_FULL_SCALE as i32 / 100
+    }
+
+    /// Silences the envelope immediately, without a release phase
+    pub fn reset(&mut self) {
+        self.gate = false;
+        self.gate_open_counter = 0;
+        self.level = 0;
+        self.state = EnvelopeState::Idle;
+    }
+
+    /// Starts a segment from the current level
+    fn segment(&mut self, state: EnvelopeState, end_level: i32, length: u32, curve: EnvelopeCurve) {
         self.state = state;
-        self.cumulative_time_tick = 0;
+        self.position = 0;
+        self.length = length;
+        self.start_level = self.level;
+        self.end_level = end_level;
+        self.curve = curve;
     }
 
     /// Closes the gate signal, initiating release phase if configured
@@ -174,58 +254,69 @@ impl EnvelopeGenerator {
         }
 
         if self.gate_open_counter == 0 {
-            self.cumulative_time_tick = 0;
             self.gate = false;
             if self.configuration.release_time > 0 {
-                self.state = EnvelopeState::Release;
-                self.release_level = if self.release_level != self.configuration.sustain_level {
-                    self.current_output_level_percentage
-                } else {
-                    self.configuration.sustain_level
-                };
-                self.amout_of_time_ticks_needed_for_one_percent_increase =
-                    (self.configuration.release_time as u32 * self.amount_of_time_ticks_for_1ms as u32) / 100;
-                self.cumalative_time_ticks_for_one_phase =
-                    self.configuration.release_time as u32 * self.amount_of_time_ticks_for_1ms as u32;
+                let length = self.ticks(self.configuration.release_time);
+                self.segment(EnvelopeState::Release, 0, length, self.configuration.release_curve);
             } else {
                 self.state = EnvelopeState::Idle;
             }
         }
     }
 
-    /// Transition to decay phase from attack phase
+    /// Transition to attack phase from the current level
+    fn attack(&mut self) {
+        let full = ENVELOPE_FULL_SCALE as i32;
+        if self.level >= full {
+            // Already at the top, continue with the hold
+            self.hold();
+            return;
+        }
+        // From a sounding level only the remaining part of the attack is left
+        let length = (self.scaled_ticks(self.configuration.attack_time) as u64
+            * (full - self.level) as u64
+            / full as u64) as u32;
+        self.segment(
+            EnvelopeState::Attack,
+            full,
+            length,
+            self.configuration.attack_curve,
+        );
+    }
+
+    /// Transition to hold phase from attack phase, straight to the decay without a hold time
+    fn hold(&mut self) {
+        if self.configuration.hold_time > 0 {
+            let length = self.ticks(self.configuration.hold_time);
+            self.segment(EnvelopeState::Hold, self.level, length, EnvelopeCurve::Linear);
+        } else {
+            self.decay();
+        }
+    }
+
+    /// Transition to decay phase from hold phase
     fn decay(&mut self) {
-        self.transistion_state(EnvelopeState::Decay);
-        self.amout_of_time_ticks_needed_for_one_percent_increase =
-            (self.configuration.decay_time as u32 * self.amount_of_time_ticks_for_1ms as u32) / 100;
-        self.cumalative_time_ticks_for_one_phase = self.configuration.decay_time as u32 * self.amount_of_time_ticks_for_1ms as u32;
+        let length = self.scaled_ticks(self.configuration.decay_time);
+        self.segment(
+            EnvelopeState::Decay,
+            self.sustain_level(),
+            length,
+            self.configuration.decay_curve,
+        );
     }
 
-    /// Opens the gate signal, initiating attack phase
+    /// Opens the gate signal, initiating the delay or attack phase
     pub fn open_gate(&mut self) {
+        // Retriggering an open gate restarts the attack, a single close still releases it
+        if !self.gate {
+            self.gate_open_counter += 1;
+        }
         self.gate = true;
-        self.cumulative_time_tick = 0;
-        self.gate_open_counter += 1;
-        self.transistion_state(EnvelopeState::Attack);
-        let mut adjusted_attack_time = self.configuration.attack_time as u32;
-        self.retrigger_level = 0;
-        if self.current_output_level_percentage > 0 {
-            // calculate the remaining slope time to reach 100%
-            let percentage_remaining = 100 - self.current_output_level_percentage;
-            if percentage_remaining > 0 {
-                self.retrigger_level = self.current_output_level_percentage;
-                adjusted_attack_time =
-                    percentage(self.configuration.attack_time, percentage_remaining) as u32;
-                self.amout_of_time_ticks_needed_for_one_percent_increase = (adjusted_attack_time as u32
-                    * self.amount_of_time_ticks_for_1ms as u32)
-                    / percentage_remaining as u32;
-            } else {
-                self.decay();
-            }
+        if self.configuration.delay_time > 0 {
+            let length = self.ticks(self.configuration.delay_time);
+            self.segment(EnvelopeState::Delay, self.level, length, EnvelopeCurve::Linear);
         } else {
-            self.amout_of_time_ticks_needed_for_one_percent_increase =
-                (adjusted_attack_time as u32 * self.amount_of_time_ticks_for_1ms as u32) / 100;
+            self.attack();
         }
-        self.cumalative_time_ticks_for_one_phase = adjusted_attack_time as u32 * self.amount_of_time_ticks_for_1ms as u32;
     }
-}
\ No newline at end of file
+}
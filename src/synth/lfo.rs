@@ -0,0 +1,307 @@
+//! Low frequency oscillator
+//!
+//! An LFO produces a slow modulation signal from built-in shapes, independent of the audio
+//! wavetables. The rate is set in centihertz or as a note division of the tempo, the phase can
+//! restart with every note, run freely or play a single cycle, and the signal can fade in after
+//! a note starts.
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+extern crate alloc;
+use alloc::sync::Arc;
+
+use super::clock::{self, NoteDivision, BEAT_LENGTH_SHIFT, DEFAULT_TEMPO};
+use super::data::wavetables::{BoxedWavetables, Wavetables, WAVETABLE_SIZE};
+use super::{math, Clockable};
+
+/// Soundbank index of the legacy noise LFO, played as sample and hold
+const NOISE_SOUNDBANK: u8 = 255;
+
+/// Rate in centihertz of the legacy `time` setting: the LFO played a 440 Hz wavetable cycle
+/// read once every `4 * time` samples
+const LEGACY_RATE: u32 = 11_000;
+
+/// Seed of the random levels of the first LFO, every next LFO uses the next seed
+const RANDOM_SEED: u64 = 23702372039;
+
+/// Waveform of an LFO, every shape starts its cycle at phase 0
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum LfoShape {
+    /// Cycle of the wavetable at `soundbank_index`, 255 plays sample and hold
+    #[default]
+    Wavetable,
+    /// Sine starting at the centre and rising
+    Sine,
+    /// Triangle starting at the centre and rising
+    Triangle,
+    /// Ramp from the bottom to the top
+    SawUp,
+    /// Ramp from the top to the bottom
+    SawDown,
+    /// Top for the first half of the cycle, bottom for the second half
+    Square,
+    /// A new random level at the start of every cycle
+    SampleAndHold,
+    /// Glides from one random level to the next over every cycle
+    SmoothRandom,
+}
+
+/// What happens to the phase of an LFO when a note starts
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum LfoMode {
+    /// The LFO keeps running, notes do not change its phase
+    #[default]
+    Free,
+    /// Every note restarts the LFO at its start phase
+    KeySync,
+    /// Every note restarts the LFO at its start phase, it stops at the end of the cycle
+    OneShot,
+}
+
+/// Configuration for low-frequency oscillator
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+pub struct WaveTableLoFreqOscillatorConfig {
+    /// Index of the soundbank to use for the `Wavetable` shape
+    pub soundbank_index: u8,
+    /// Legacy rate, the LFO plays at 110 / `time` Hz. Only used without a `rate`
+    pub time: u16,
+    /// Rate in centihertz (100 = 1 Hz), 0 uses the legacy `time`
+    #[serde(default)]
+    pub rate: u16,
+    /// Waveform of the LFO
+    #[serde(default)]
+    pub shape: LfoShape,
+    /// Phase in degrees (0-359) the LFO starts at, and restarts at in `KeySync` and `OneShot` mode
+    #[serde(default)]
+    pub start_phase: u16,
+    /// What happens to the phase when a note starts
+    #[serde(default)]
+    pub mode: LfoMode,
+    /// Time in milliseconds the LFO takes to fade in from the centre after a note starts
+    #[serde(default)]
+    pub fade_in_time: u16,
+    /// Length of a cycle as a note division of the tempo, replaces the rate. A free running
+    /// LFO follows the ticks of a running MIDI clock
+    #[serde(default)]
+    pub sync: Option<NoteDivision>,
+}
+
+/// Low frequency oscillator implementation
+pub struct Lfo {
+    /// The LFO configuration settings
+    pub config: WaveTableLoFreqOscillatorConfig,
+    /// Sample rate in Hz
+    sample_rate: u16,
+    /// Position in the cycle, one cycle is the whole u32 range
+    phase: u32,
+    /// Phase advance every sample
+    phase_increment: u32,
+    /// Length of a beat in samples as 48.16 fixed point, the tempo of a synced LFO
+    beat_length: u64,
+    /// Whether the phase advances, a one shot LFO stops at the end of its cycle
+    running: bool,
+    /// Samples since the last note started, up to the fade length
+    fade_position: u32,
+    /// Length of the fade in samples, 0 = no fade
+    fade_length: u32,
+    /// Random level of the current cycle
+    random_level: i16,
+    /// Random level of the previous cycle, the smooth random shape starts here
+    previous_random_level: i16,
+    /// Generator of the random levels
+    random: SmallRng,
+    /// Shared reference to the wavetables data structure
+    wavetables: Arc<BoxedWavetables>,
+}
+
+impl Clockable for Lfo {
+    /// Clock the LFO, return its level (-`i16::MAX` to `i16::MAX`)
+    fn clock(&mut self, _sample: Option<i16>) -> i16 {
+        let level = self.shape_level();
+        let level = if self.fade_position < self.fade_length {
+            let fade = self.fade_position as u64 * i16::MAX as u64 / self.fade_length as u64;
+            self.fade_position += 1;
+            math::apply_level(level, fade as i16)
+        } else {
+            level
+        };
+        if self.running {
+            let (phase, wrapped) = self.phase.overflowing_add(self.phase_increment);
+            if wrapped && self.config.mode == LfoMode::OneShot {
+                // Stay at the end of the cycle
+                self.phase = u32::MAX;
+                self.running = false;
+            } else {
+                self.phase = phase;
+                if wrapped {
+                    self.next_random_level();
+                }
+            }
+        }
+        level
+    }
+}
+
+impl Lfo {
+    /// Creates a new LFO with the given configuration and sample rate
+    ///
+    /// # Arguments
+    /// * `index` - Index of the LFO in the synth, every LFO draws its own random levels
+    pub fn new(
+        index: usize,
+        config: WaveTableLoFreqOscillatorConfig,
+        sample_rate: u16,
+        wavetables: Arc<BoxedWavetables>,
+    ) -> Self {
+        let mut lfo = Self {
+            config,
+            sample_rate,
+            phase: 0,
+            phase_increment: 0,
+            beat_length: clock::beat_length(DEFAULT_TEMPO, sample_rate),
+            running: true,
+            fade_position: 0,
+            fade_length: 0,
+            random_level: 0,
+            previous_random_level: 0,
+            random: SmallRng::seed_from_u64(RANDOM_SEED.wrapping_add(index as u64)),
+            wavetables,
+        };
+        lfo.reload(config);
+        lfo.restart();
+        lfo
+    }
+
+    /// Reloads the LFO configuration, the phase continues
+    pub fn reload(&mut self, config: WaveTableLoFreqOscillatorConfig) {
+        self.config = config;
+        self.phase_increment = match config.sync {
+            Some(division) => {
+                // One cycle lasts the division, in samples as 48.16 fixed point
+                let (numerator, denominator) = division.ticks();
+                let length = self.beat_length * numerator as u64
+                    / (clock::PULSES_PER_QUARTER_NOTE * denominator) as u64;
+                ((1u64 << (32 + BEAT_LENGTH_SHIFT)) / length.max(1)).min(u32::MAX as u64) as u32
+            }
+            None => {
+                let rate = if config.rate > 0 {
+                    config.rate as u64
+                } else {
+                    (LEGACY_RATE / config.time.max(1) as u32) as u64
+                };
+                ((rate << 32) / (100 * self.sample_rate as u64)) as u32
+            }
+        };
+        self.fade_length = (config.fade_in_time as u32 * self.sample_rate as u32 + 500) / 1000;
+    }
+
+    /// Change the tempo of a synced LFO
+    ///
+    /// # Arguments
+    /// * `beat_length` - Length of a beat in samples as 48.16 fixed point
+    pub fn set_beat_length(&mut self, beat_length: u64) {
+        if self.beat_length != beat_length {
+            self.beat_length = beat_length;
+            self.reload(self.config);
+        }
+    }
+
+    /// A MIDI clock tick arrived, a synced LFO in `Free` mode moves to the phase of the tick
+    ///
+    /// # Arguments
+    /// * `tick` - Number of the tick since the MIDI start
+    pub fn sync(&mut self, tick: u32) {
+        let Some(division) = self.config.sync else {
+            return;
+        };
+        if self.config.mode != LfoMode::Free {
+            return;
+        }
+        let (numerator, denominator) = division.ticks();
+        let position = (tick as u64 * denominator as u64) % numerator as u64;
+        let phase = self
+            .start_phase()
+            .wrapping_add(((position << 32) / numerator as u64) as u32);
+        let distance = phase.wrapping_sub(self.phase);
+        if distance < 1 << 31 {
+            // Ahead of the LFO, a new cycle starts when the tick crosses the end of the cycle
+            if phase < self.phase {
+                self.next_random_level();
+            }
+            self.phase = phase;
+        } else if phase < self.phase {
+            // Slightly behind the LFO, wait for the LFO to go past the end of the cycle
+            self.phase = phase;
+        }
+    }
+
+    /// A note started: fade in again and restart the phase in `KeySync` and `OneShot` mode
+    ///
+    /// A `Free` LFO is shared by every note, it only fades in again when the note starts while
+    /// no other note is sounding so the notes that keep playing do not lose their modulation
+    ///
+    /// # Arguments
+    /// * `other_notes` - Whether other notes are still sounding
+    pub fn trigger(&mut self, other_notes: bool) {
+        if self.config.mode != LfoMode::Free {
+            self.fade_position = 0;
+            self.restart();
+        } else if !other_notes {
+            self.fade_position = 0;
+        }
+    }
+
+    /// Restart the cycle at the start phase
+    fn restart(&mut self) {
+        self.phase = self.start_phase();
+        self.running = true;
+        self.next_random_level();
+    }
+
+    /// The start phase as phase
+    fn start_phase(&self) -> u32 {
+        (self.config.start_phase as u64 % 360 * (1 << 32) / 360) as u32
+    }
+
+    /// Pick the random level of a new cycle
+    fn next_random_level(&mut self) {
+        self.previous_random_level = self.random_level;
+        self.random_level = self.random.random_range(-i16::MAX..=i16::MAX);
+    }
+
+    /// Level of the shape at the current phase (-`i16::MAX` to `i16::MAX`)
+    fn shape_level(&self) -> i16 {
+        let phase = self.phase;
+        // Ramp over the cycle from -i16::MAX to i16::MAX
+        let ramp = |phase: u32| ((phase >> 16) as i32 - (1 << 15)).max(-(i16::MAX as i32)) as i16;
+        match self.config.shape {
+            LfoShape::Wavetable if self.config.soundbank_index == NOISE_SOUNDBANK => {
+                self.random_level
+            }
+            LfoShape::Wavetable => {
+                let wavetable = self
+                    .wavetables
+                    .get_wavetable_reference(self.config.soundbank_index);
+                wavetable[((phase as u64 * WAVETABLE_SIZE as u64) >> 32) as usize]
+            }
+            LfoShape::Sine => math::sine(phase),
+            LfoShape::Triangle => {
+                // Fold a ramp that starts a quarter cycle later
+                let folded = ramp(phase.wrapping_add(1 << 30)) as i32;
+                (i16::MAX as i32 - 2 * folded.abs()).max(-(i16::MAX as i32)) as i16
+            }
+            LfoShape::SawUp => ramp(phase),
+            LfoShape::SawDown => -ramp(phase),
+            LfoShape::Square if phase < 1 << 31 => i16::MAX,
+            LfoShape::Square => -i16::MAX,
+            LfoShape::SampleAndHold => self.random_level,
+            LfoShape::SmoothRandom => {
+                let from = self.previous_random_level as i64;
+                let to = self.random_level as i64;
+                (from + (((to - from) * (phase >> 16) as i64) >> 16)) as i16
+            }
+        }
+    }
+}
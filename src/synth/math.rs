@@ -52,3 +52,166 @@ pub fn ifx_mul(a: i32, b: u16) -> i32 {
 pub fn fx_mul(a: i64, b: i32) -> i64 {
     (a * b as i64) >> self::FX_SHIFT
 }
+
+/// Linear interpolation between two samples.
+///
+/// # Arguments
+///
+/// * `a` - The sample at the start of the segment.
+/// * `b` - The sample at the end of the segment.
+/// * `fraction` - Position between `a` and `b` as 0.16 fixed point (0 = `a`, 65535 = almost `b`).
+///
+/// # Returns
+///
+/// The interpolated sample.
+pub fn linear_interpolation(a: i16, b: i16, fraction: u16) -> i16 {
+    (a as i64 + (((b as i64 - a as i64) * fraction as i64) >> FX_SHIFT)) as i16
+}
+
+/// 4-point, 3rd-order Hermite (Catmull-Rom) interpolation between `x0` and `x1`.
+///
+/// # Arguments
+///
+/// * `xm1` - The sample before `x0`.
+/// * `x0` - The sample at the start of the segment.
+/// * `x1` - The sample at the end of the segment.
+/// * `x2` - The sample after `x1`.
+/// * `fraction` - Position between `x0` and `x1` as 0.16 fixed point.
+///
+/// # Returns
+///
+/// The interpolated sample, clipped to the i16 range.
+pub fn hermite_interpolation(xm1: i16, x0: i16, x1: i16, x2: i16, fraction: u16) -> i16 {
+    let (xm1, x0, x1, x2) = (xm1 as i64, x0 as i64, x1 as i64, x2 as i64);
+    let t = fraction as i64;
+    // Polynomial coefficients, doubled to stay in integer precision
+    let c1 = x1 - xm1;
+    let c2 = 2 * xm1 - 5 * x0 + 4 * x1 - x2;
+    let c3 = (x2 - xm1) + 3 * (x0 - x1);
+    let mut acc = c3;
+    acc = ((acc * t) >> FX_SHIFT) + c2;
+    acc = ((acc * t) >> FX_SHIFT) + c1;
+    acc = (acc * t) >> FX_SHIFT;
+    (x0 + acc / 2).clamp(i16::MIN as i64, i16::MAX as i64) as i16
+}
+
+/// Scale a sample by an envelope level.
+///
+/// # Arguments
+///
+/// * `sample` - The sample to scale.
+/// * `level` - The level, from 0 (silent) to `i16::MAX` (unchanged).
+///
+/// # Returns
+///
+/// The scaled sample.
+pub fn apply_level(sample: i16, level: i16) -> i16 {
+    // Divide instead of shift, rounding toward zero keeps a silent level silent for negative samples
+    (sample as i32 * level as i32 / (1 << 15)) as i16
+}
+
+/// Taylor coefficients of 2^x (ln(2)^k / k!) as 2.30 fixed point, highest order first.
+const EXP2_COEFFICIENTS: [i64; 6] = [165394, 1431680, 10327387, 59597083, 257941248, 744261118];
+
+/// Raise 2 to the power of `x`.
+///
+/// # Arguments
+///
+/// * `x` - The exponent as 16.16 fixed point (e.g. octaves).
+///
+/// # Returns
+///
+/// 2^`x` as 16.16 fixed point, saturated to the u32 range.
+pub fn exp2(x: i32) -> u32 {
+    let integer = x >> FX_SHIFT;
+    let fraction = (x & 0xFFFF) as i64;
+    // 2^fraction in 2.30 fixed point, accurate to about 0.03 cent
+    let mut acc = 0;
+    for coefficient in EXP2_COEFFICIENTS {
+        acc = ((acc + coefficient) * fraction) >> FX_SHIFT;
+    }
+    let result = (1i64 << 30) + acc;
+    let shift = integer - 14;
+    if shift >= 0 {
+        (result << shift.min(31)).min(u32::MAX as i64) as u32
+    } else {
+        (result >> (-shift).min(63)) as u32
+    }
+}
+
+/// Base 2 logarithm of `x`.
+///
+/// # Arguments
+///
+/// * `x` - A positive value as 16.16 fixed point (e.g. a frequency or a ratio).
+///
+/// # Returns
+///
+/// log2(`x`) as 16.16 fixed point, `i32::MIN` for 0.
+pub fn log2(x: u32) -> i32 {
+    if x == 0 {
+        return i32::MIN;
+    }
+    let msb = 31 - x.leading_zeros() as i32;
+    let mut result = (msb - FX_SHIFT as i32) << FX_SHIFT;
+    // Normalise to 1..2 as 2.30 fixed point, every squaring reveals one bit of the fraction
+    let mut y = ((x as u64) << 30) >> msb;
+    for bit in (0..FX_SHIFT).rev() {
+        y = (y * y) >> 30;
+        if y >= 2 << 30 {
+            y >>= 1;
+            result |= 1 << bit;
+        }
+    }
+    result
+}
+
+/// Convert a pitch offset in cents into a frequency ratio.
+///
+/// # Arguments
+///
+/// * `cents` - The pitch offset in cents (1200 cents is one octave).
+///
+/// # Returns
+///
+/// The frequency ratio as 16.16 fixed point.
+pub fn cents_to_ratio(cents: i32) -> u32 {
+    exp2(((cents as i64) << FX_SHIFT).div_euclid(1200) as i32)
+}
+
+/// Coefficients of sin(π/2 · x) ≈ ax - bx³ + cx⁵ on a quarter wave as 2.30 fixed point, chosen
+/// so the quarter wave ends at exactly 1 with a flat top.
+const SINE_COEFFICIENTS: [i64; 3] = [1686629713, 688904866, 76016977];
+
+/// Sine of a phase.
+///
+/// # Arguments
+///
+/// * `phase` - The phase, one full cycle is the whole u32 range.
+///
+/// # Returns
+///
+/// The sine from -`i16::MAX` to `i16::MAX`, accurate to about 0.04%.
+pub fn sine(phase: u32) -> i16 {
+    let [a, b, c] = SINE_COEFFICIENTS;
+    // Progress through the quarter wave as 0.16 fixed point, mirrored in the second quarter
+    let quarter = (phase >> 14) & 0xFFFF;
+    let x = if phase & (1 << 30) == 0 {
+        quarter as i64
+    } else {
+        (1 << 16) - quarter as i64
+    };
+    let x2 = (x * x) >> FX_SHIFT;
+    // Horner form of ax - bx³ + cx⁵ in 2.30 fixed point
+    let polynomial = b - ((c * x2) >> FX_SHIFT);
+    let polynomial = a - ((polynomial * x2) >> FX_SHIFT);
+    let polynomial = (polynomial * x) >> FX_SHIFT;
+    let sine = (polynomial * i16::MAX as i64) >> 30;
+    let sine = sine.min(i16::MAX as i64) as i16;
+    // The second half of the cycle is the negative of the first half
+    if phase & (1 << 31) == 0 {
+        sine
+    } else {
+        -sine
+    }
+}
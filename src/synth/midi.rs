@@ -0,0 +1,192 @@
+//! MIDI 1.0 byte stream parser
+//!
+//! Turns a stream of MIDI bytes (for example from a UART) into messages, supporting running status,
+//! real-time messages in between data bytes and ignoring system exclusive data.
+
+/// Receive channel of the synthesizer
+#[derive(Copy, Clone, PartialEq, Default, Debug)]
+pub enum MidiChannel {
+    /// Respond to messages on every channel
+    #[default]
+    Omni,
+    /// Respond only to messages on a single channel (0-15)
+    Channel(u8),
+}
+
+impl MidiChannel {
+    /// Whether a message on the given channel (0-15) should be handled
+    pub fn accepts(&self, channel: u8) -> bool {
+        match self {
+            MidiChannel::Omni => true,
+            MidiChannel::Channel(receive) => *receive == channel,
+        }
+    }
+}
+
+/// Controller numbers handled by the synthesizer
+pub mod controller {
+    pub const MOD_WHEEL: u8 = 1;
+    pub const VOLUME: u8 = 7;
+    pub const SUSTAIN: u8 = 64;
+    pub const SOSTENUTO: u8 = 66;
+    pub const RESONANCE: u8 = 71;
+    pub const CUTOFF: u8 = 74;
+    pub const ALL_SOUND_OFF: u8 = 120;
+    pub const RESET_ALL_CONTROLLERS: u8 = 121;
+    pub const ALL_NOTES_OFF: u8 = 123;
+}
+
+/// A complete MIDI message, channels are 0-15
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyAftertouch {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// Pitch bend, from -8192 (down) to 8191 (up)
+    PitchBend {
+        channel: u8,
+        value: i16,
+    },
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MidiMessage {
+    /// Channel of a channel message, `None` for system messages
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyAftertouch { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(*channel),
+            _ => None,
+        }
+    }
+}
+
+/// Parser for a MIDI byte stream, keeps the running status between calls
+#[derive(Default)]
+pub struct MidiParser {
+    /// Status byte of the message being received, 0 when there is none
+    status: u8,
+    /// Data bytes received so far
+    data: [u8; 2],
+    /// Amount of data bytes received so far
+    received: usize,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one byte into the parser
+    ///
+    /// # Returns
+    /// The message completed by this byte, if any
+    pub fn parse(&mut self, byte: u8) -> Option<MidiMessage> {
+        // Real-time messages can appear anywhere, even in between data bytes
+        if byte >= 0xF8 {
+            return match byte {
+                0xF8 => Some(MidiMessage::TimingClock),
+                0xFA => Some(MidiMessage::Start),
+                0xFB => Some(MidiMessage::Continue),
+                0xFC => Some(MidiMessage::Stop),
+                _ => None,
+            };
+        }
+        if byte >= 0xF0 {
+            // System common and exclusive messages cancel the running status, their data is ignored
+            self.status = 0;
+            self.received = 0;
+            return None;
+        }
+        if byte >= 0x80 {
+            self.status = byte;
+            self.received = 0;
+            return None;
+        }
+        if self.status == 0 {
+            return None;
+        }
+
+        self.data[self.received] = byte;
+        self.received += 1;
+        let channel = self.status & 0x0F;
+        let kind = self.status & 0xF0;
+        let length = match kind {
+            0xC0 | 0xD0 => 1,
+            _ => 2,
+        };
+        if self.received < length {
+            return None;
+        }
+        // Keep the status for running status
+        self.received = 0;
+        let [first, second] = self.data;
+        match kind {
+            0x80 => Some(MidiMessage::NoteOff {
+                channel,
+                note: first,
+                velocity: second,
+            }),
+            0x90 => Some(MidiMessage::NoteOn {
+                channel,
+                note: first,
+                velocity: second,
+            }),
+            0xA0 => Some(MidiMessage::PolyAftertouch {
+                channel,
+                note: first,
+                pressure: second,
+            }),
+            0xB0 => Some(MidiMessage::ControlChange {
+                channel,
+                controller: first,
+                value: second,
+            }),
+            0xC0 => Some(MidiMessage::ProgramChange {
+                channel,
+                program: first,
+            }),
+            0xD0 => Some(MidiMessage::ChannelPressure {
+                channel,
+                pressure: first,
+            }),
+            _ => Some(MidiMessage::PitchBend {
+                channel,
+                value: ((second as i16) << 7 | first as i16) - 8192,
+            }),
+        }
+    }
+}
@@ -2,23 +2,90 @@
 
 use serde::{Deserialize, Serialize};
 
-use super::AMOUNT_OF_VOICES;
+use super::{patch::SynthMode, AMOUNT_OF_OUTPUT_CHANNELS, AMOUNT_OF_VOICES};
+
+/// Pan position of a voice, fully to the left
+pub const PAN_LEFT: i8 = -100;
+/// Pan position of a voice, fully to the right
+pub const PAN_RIGHT: i8 = 100;
+
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct MixerConfiguration {
     pub gain_voices: [u8; AMOUNT_OF_VOICES],
     pub gain_main: u8,
+    /// Pan position per voice (-100 = left, 0 = center, 100 = right)
+    #[serde(default)]
+    pub pan_voices: [i8; AMOUNT_OF_VOICES],
+    /// Spread the audible voices of one note across the stereo field (0-100)
+    #[serde(default)]
+    pub stereo_spread: u8,
 }
 
 pub struct Mixer {
     pub config: MixerConfiguration,
+    /// Pan position per voice, including the stereo spread
+    pans: [i16; AMOUNT_OF_VOICES],
+    /// Gain percentage per voice for the left and right channel
+    pan_gains: [[i16; AMOUNT_OF_OUTPUT_CHANNELS]; AMOUNT_OF_VOICES],
 }
 
 impl Mixer {
-    pub fn new(config: MixerConfiguration) -> Self {
-        Self { config }
+    pub fn new(config: MixerConfiguration, mode: SynthMode) -> Self {
+        let mut mixer = Self {
+            config,
+            pans: [0; AMOUNT_OF_VOICES],
+            pan_gains: [[100; AMOUNT_OF_OUTPUT_CHANNELS]; AMOUNT_OF_VOICES],
+        };
+        mixer.prepare_panning(mode);
+        mixer
     }
 
-    pub fn reload(&mut self, config: MixerConfiguration) {
+    pub fn reload(&mut self, config: MixerConfiguration, mode: SynthMode) {
         self.config = config;
+        self.prepare_panning(mode);
+    }
+
+    /// Pan position (-100..100) of a voice, including the stereo spread
+    pub fn pan(&self, voice: usize) -> i16 {
+        self.pans[voice]
+    }
+
+    /// Gain percentage (0-100) of a voice for the left and right channel
+    pub fn pan_gains(&self, voice: usize) -> [i16; AMOUNT_OF_OUTPUT_CHANNELS] {
+        self.pan_gains[voice]
+    }
+
+    /// Convert a pan position (-100..100) into a gain percentage for the left and right channel.
+    /// A centered voice plays at full level on both channels, panning attenuates the opposite channel.
+    pub fn pan_law(pan: i16) -> [i16; AMOUNT_OF_OUTPUT_CHANNELS] {
+        let pan = pan.clamp(PAN_LEFT as i16, PAN_RIGHT as i16);
+        [(100 - pan).min(100), (100 + pan).min(100)]
+    }
+
+    /// Calculate the pan gains of every voice, the stereo spread is divided evenly over the
+    /// audible voices (gain > 0) that play the same note.
+    fn prepare_panning(&mut self, mode: SynthMode) {
+        let voices_per_note = mode as usize;
+        for group in 0..AMOUNT_OF_VOICES / voices_per_note {
+            let first_voice = group * voices_per_note;
+            let voices = first_voice..first_voice + voices_per_note;
+            let audible = voices
+                .clone()
+                .filter(|voice| self.config.gain_voices[*voice] > 0)
+                .count() as i16;
+            let mut position: i16 = 0;
+            for voice in voices {
+                let mut pan = self.config.pan_voices[voice] as i16;
+                if self.config.gain_voices[voice] > 0 {
+                    if audible > 1 {
+                        let spread = self.config.stereo_spread as i16;
+                        pan += -spread + 2 * spread * position / (audible - 1);
+                    }
+                    position += 1;
+                }
+                self.pans[voice] = pan;
+                self.pan_gains[voice] = Mixer::pan_law(pan);
+            }
+        }
     }
 }
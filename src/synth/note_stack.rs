@@ -0,0 +1,58 @@
+//! Held keys of a monophonic patch, used to pick the sounding note
+
+use super::patch::NotePriority;
+
+/// Maximum number of held keys, when more keys are held the oldest is forgotten
+pub const NOTE_STACK_SIZE: usize = 16;
+
+/// Keys that are held down, in the order they were pressed
+#[derive(Default)]
+pub struct NoteStack {
+    notes: [u8; NOTE_STACK_SIZE],
+    length: usize,
+}
+
+impl NoteStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pressed key, a key that is already held moves to the top
+    pub fn push(&mut self, note: u8) {
+        self.remove(note);
+        if self.length == NOTE_STACK_SIZE {
+            self.notes.copy_within(1.., 0);
+            self.length -= 1;
+        }
+        self.notes[self.length] = note;
+        self.length += 1;
+    }
+
+    /// Remove a released key
+    pub fn remove(&mut self, note: u8) {
+        if let Some(position) = self.notes().iter().position(|n| *n == note) {
+            self.notes.copy_within(position + 1..self.length, position);
+            self.length -= 1;
+        }
+    }
+
+    /// Forget all held keys
+    pub fn clear(&mut self) {
+        self.length = 0;
+    }
+
+    /// Held keys, the last pressed key last
+    pub fn notes(&self) -> &[u8] {
+        &self.notes[..self.length]
+    }
+
+    /// The held key that should sound, `None` when no key is held
+    pub fn pick(&self, priority: NotePriority) -> Option<u8> {
+        let notes = self.notes().iter().copied();
+        match priority {
+            NotePriority::Last => self.notes().last().copied(),
+            NotePriority::Lowest => notes.min(),
+            NotePriority::Highest => notes.max(),
+        }
+    }
+}
@@ -6,12 +6,40 @@ use super::{
     envelope::EnvelopConfiguration,
     mixer::MixerConfiguration,
     router::RoutingConfiguration,
+    velocity::VelocityConfiguration,
+    vibrato::VibratoConfiguration,
     wavetable_oscillator::{WaveTableLoFreqOscillatorConfig, WaveTableOscillatorConfig},
     AMOUNT_OF_VOICES,
 };
 use serde::Deserialize;
 use serde::Serialize;
 
+mod legacy;
+
+/// First bytes of a postcard patch, followed by the format version. Patches without them have
+/// the layout of little weirdo 0.1, which can never start with these bytes: its second byte is
+/// the `glide` flag of the first voice, 0 or 1
+pub const PATCH_MAGIC: [u8; 2] = *b"LW";
+
+/// Version of the postcard patch layout. Raise it with every release that changes the layout and
+/// keep the previous layout in `legacy` so older patches keep loading
+pub const PATCH_FORMAT_VERSION: u8 = 1;
+
+/// Reasons a postcard patch can not be read or written
+#[derive(Clone, PartialEq, Debug)]
+pub enum PatchError {
+    /// The patch has a format version this version of little weirdo does not know
+    UnsupportedVersion(u8),
+    /// The patch does not match the layout of its version, or does not fit in the buffer
+    Postcard(postcard::Error),
+}
+
+impl From<postcard::Error> for PatchError {
+    fn from(error: postcard::Error) -> Self {
+        PatchError::Postcard(error)
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub enum SynthMode {
     Mono = 8,
@@ -20,15 +48,82 @@ pub enum SynthMode {
     OctoPoly = 1,
 }
 
+/// What to do with a new note when every voice is playing
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum VoiceStealing {
+    /// Ignore the new note
+    #[default]
+    None,
+    /// Steal the voice of the note that was played first
+    Oldest,
+    /// Steal the voice with the lowest envelope level
+    Quietest,
+    /// Play a note again on the voice that played it before, otherwise steal the oldest note
+    SameNote,
+    /// Keep the lowest notes, steal the highest note unless the new note is even higher
+    LowestNote,
+    /// Keep the highest notes, steal the lowest note unless the new note is even lower
+    HighestNote,
+}
+
+/// Which held key sounds in `Mono` mode
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum NotePriority {
+    /// The last pressed key
+    #[default]
+    Last,
+    /// The lowest held key
+    Lowest,
+    /// The highest held key
+    Highest,
+}
+
+/// How `Mono` mode moves from one note to the next while keys overlap
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum MonoMode {
+    /// Every new note restarts the envelopes
+    #[default]
+    Retrigger,
+    /// Overlapping notes keep the envelopes running and glide when the voice has glide enabled,
+    /// a note played after all keys were released starts the envelopes without glide
+    Legato,
+}
+
+/// Pitch bend range used by patches that do not define one, a whole tone
+pub const DEFAULT_PITCH_BEND_RANGE: u16 = 200;
+
+fn default_pitch_bend_range() -> u16 {
+    DEFAULT_PITCH_BEND_RANGE
+}
+
 #[derive(Copy, Clone, Serialize, Deserialize, Debug)]
 pub struct SynthConfiguration {
     pub mode: SynthMode,
+    /// Pitch change of a full pitch bend up or down in cents (100 = one semitone)
+    #[serde(default = "default_pitch_bend_range")]
+    pub pitch_bend_range: u16,
+    /// How much the mod wheel controls the LFO depth as percentage (0-100), at 100 the LFOs
+    /// are silent with the wheel down and at full depth with the wheel up
+    #[serde(default)]
+    pub mod_wheel_to_lfo_depth: u8,
+    /// Which voice plays a new note when every voice is playing
+    #[serde(default)]
+    pub voice_stealing: VoiceStealing,
+    /// Which held key sounds in `Mono` mode
+    #[serde(default)]
+    pub note_priority: NotePriority,
+    /// Retrigger or legato in `Mono` mode
+    #[serde(default)]
+    pub mono_mode: MonoMode,
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Patch {
     pub voices: [WaveTableOscillatorConfig; AMOUNT_OF_VOICES],
     pub envelops: [EnvelopConfiguration; AMOUNT_OF_VOICES],
+    /// Envelope that sweeps the filter cutoff, one is triggered for every note
+    #[serde(default)]
+    pub filter_envelop: EnvelopConfiguration,
     pub lfos: [WaveTableLoFreqOscillatorConfig; AMOUNT_OF_VOICES / 2],
     pub filter_config: FilterConfig,
     pub mixer_config: MixerConfiguration,
@@ -36,4 +131,50 @@ pub struct Patch {
     pub bitcrunch_config: BitcrunchConfiguration,
     pub routering_config: RoutingConfiguration,
     pub synth_config: SynthConfiguration,
+    /// Velocity response curve and what the velocity controls
+    #[serde(default)]
+    pub velocity_config: VelocityConfiguration,
+    /// Vibrato of the voices
+    #[serde(default)]
+    pub vibrato_config: VibratoConfiguration,
+}
+
+impl Patch {
+    /// Move the deprecated LFO routes of an older patch into the modulation matrix, see
+    /// `RoutingConfiguration::migrate_legacy_routes`. The synthesizer does this when it loads a
+    /// patch.
+    pub fn migrate_legacy_routes(&mut self) {
+        self.routering_config
+            .migrate_legacy_routes(&mut self.filter_config);
+    }
+
+    /// Read a postcard patch (.lwp) of the current or an older format version
+    ///
+    /// # Arguments
+    /// * `bytes` - The patch as written by `to_postcard`, or an unversioned little weirdo 0.1 patch
+    pub fn from_postcard(bytes: &[u8]) -> Result<Self, PatchError> {
+        match bytes {
+            [first, second, version, patch @ ..] if [*first, *second] == PATCH_MAGIC => {
+                match *version {
+                    PATCH_FORMAT_VERSION => Ok(postcard::from_bytes(patch)?),
+                    version => Err(PatchError::UnsupportedVersion(version)),
+                }
+            }
+            _ => Ok(postcard::from_bytes::<legacy::PatchV0>(bytes)?.into()),
+        }
+    }
+
+    /// Write the patch as postcard (.lwp) with a header of the current format version
+    ///
+    /// # Arguments
+    /// * `buffer` - Buffer to write the patch into
+    ///
+    /// # Returns
+    /// The part of the buffer that holds the patch
+    pub fn to_postcard<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a mut [u8], PatchError> {
+        Ok(postcard::to_slice(
+            &(PATCH_MAGIC, PATCH_FORMAT_VERSION, self),
+            buffer,
+        )?)
+    }
 }
@@ -0,0 +1,161 @@
+//! Layouts of older postcard patches
+//!
+//! Postcard is not self-describing, a patch can only be read with the exact layout it was written
+//! with. The layouts of earlier format versions are kept here and converted to the current
+//! `Patch`, every field that was added since gets its default.
+
+use serde::Deserialize;
+
+use super::{Patch, SynthConfiguration, SynthMode, DEFAULT_PITCH_BEND_RANGE};
+use crate::synth::{
+    effects::{
+        bitcrunch::BitcrunchConfiguration,
+        filter::{FilterConfig, KindOfFilter},
+        overdrive::OverdriveConfiguration,
+    },
+    envelope::EnvelopConfiguration,
+    mixer::MixerConfiguration,
+    router::{RoutingConfiguration, VoiceToLFORoute},
+    wavetable_oscillator::{WaveTableLoFreqOscillatorConfig, WaveTableOscillatorConfig},
+    AMOUNT_OF_VOICES,
+};
+
+/// Patch without a format header, written by little weirdo 0.1
+#[derive(Deserialize)]
+pub(super) struct PatchV0 {
+    voices: [OscillatorV0; AMOUNT_OF_VOICES],
+    envelops: [EnvelopeV0; AMOUNT_OF_VOICES],
+    lfos: [LfoV0; AMOUNT_OF_VOICES / 2],
+    filter_config: FilterV0,
+    mixer_config: MixerV0,
+    overdrive_config: OverdriveConfiguration,
+    bitcrunch_config: BitcrunchConfiguration,
+    routering_config: RoutingV0,
+    synth_config: SynthV0,
+}
+
+#[derive(Deserialize)]
+struct OscillatorV0 {
+    soundbank_index: u8,
+    glide: bool,
+    glide_rate: u8,
+    detune: i8,
+    freq_detune: i8,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeV0 {
+    attack_time: i16,
+    decay_time: i16,
+    release_time: i16,
+    sustain_level: i16,
+}
+
+#[derive(Deserialize)]
+struct LfoV0 {
+    soundbank_index: u8,
+    time: u16,
+}
+
+#[derive(Deserialize)]
+struct FilterV0 {
+    cutoff_frequency: u16,
+    resonance: u16,
+    kind_of_filter: KindOfFilter,
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct MixerV0 {
+    gain_voices: [u8; AMOUNT_OF_VOICES],
+    gain_main: u8,
+}
+
+#[derive(Deserialize)]
+struct RoutingV0 {
+    voices_to_envelop: [u8; AMOUNT_OF_VOICES],
+    lfo_to_voice: [VoiceToLFORoute; AMOUNT_OF_VOICES / 2],
+    lfo_to_filter: bool,
+    lfo_to_freq: bool,
+    lfo_to_freq_amount: u16,
+}
+
+#[derive(Deserialize)]
+struct SynthV0 {
+    mode: SynthMode,
+}
+
+impl From<PatchV0> for Patch {
+    fn from(patch: PatchV0) -> Self {
+        let filter = patch.filter_config;
+        let mixer = patch.mixer_config;
+        let routing = patch.routering_config;
+        Patch {
+            voices: patch.voices.map(|voice| WaveTableOscillatorConfig {
+                soundbank_index: voice.soundbank_index,
+                glide: voice.glide,
+                glide_rate: voice.glide_rate,
+                detune: voice.detune,
+                freq_detune: voice.freq_detune,
+                interpolation: Default::default(),
+                band_limit_crossfade: false,
+                wavetable_position: 0,
+                portamento_mode: Default::default(),
+                portamento_time: 0,
+                modulation: Default::default(),
+                modulator: 0,
+                modulation_index: 0,
+            }),
+            envelops: patch.envelops.map(|envelope| EnvelopConfiguration {
+                attack_time: envelope.attack_time as i32,
+                decay_time: envelope.decay_time as i32,
+                release_time: envelope.release_time as i32,
+                sustain_level: envelope.sustain_level,
+                ..Default::default()
+            }),
+            lfos: patch.lfos.map(|lfo| WaveTableLoFreqOscillatorConfig {
+                soundbank_index: lfo.soundbank_index,
+                time: lfo.time,
+                ..Default::default()
+            }),
+            filter_config: FilterConfig {
+                cutoff_frequency: filter.cutoff_frequency,
+                resonance: filter.resonance,
+                kind_of_filter: filter.kind_of_filter,
+                enabled: filter.enabled,
+                per_voice: false,
+                envelope_amount: 0,
+                key_tracking: 0,
+                filter_envelope_depth: 0,
+            },
+            mixer_config: MixerConfiguration {
+                gain_voices: mixer.gain_voices,
+                gain_main: mixer.gain_main,
+                pan_voices: [0; AMOUNT_OF_VOICES],
+                stereo_spread: 0,
+            },
+            overdrive_config: patch.overdrive_config,
+            bitcrunch_config: patch.bitcrunch_config,
+            // The LFO routes are moved into the modulation matrix when the patch is loaded
+            routering_config: RoutingConfiguration {
+                voices_to_envelop: routing.voices_to_envelop,
+                modulation_matrix: Default::default(),
+                lfo_to_voice: routing.lfo_to_voice,
+                lfo_to_filter: routing.lfo_to_filter,
+                lfo_to_freq: routing.lfo_to_freq,
+                lfo_to_freq_amount: routing.lfo_to_freq_amount,
+            },
+            synth_config: SynthConfiguration {
+                mode: patch.synth_config.mode,
+                pitch_bend_range: DEFAULT_PITCH_BEND_RANGE,
+                mod_wheel_to_lfo_depth: 0,
+                voice_stealing: Default::default(),
+                note_priority: Default::default(),
+                mono_mode: Default::default(),
+            },
+            filter_envelop: Default::default(),
+            velocity_config: Default::default(),
+            vibrato_config: Default::default(),
+        }
+    }
+}
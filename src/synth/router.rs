@@ -1,24 +1,274 @@
 //! Routing the signals
+//!
+//! Besides routing voices to envelopes, the router holds the modulation matrix: a fixed amount of
+//! slots that each connect a modulation source to a destination with a signed depth. LFOs are
+//! bipolar sources, they swing the destination around its base value.
 
 use serde::{Deserialize, Serialize};
 
-use super::AMOUNT_OF_VOICES;
+use super::{
+    effects::filter::FilterConfig, envelope::ENVELOPE_FULL_SCALE, AMOUNT_OF_VOICES, BLOCK_SIZE,
+};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+/// Number of slots in the modulation matrix
+pub const MODULATION_SLOTS: usize = 8;
+
+/// Full scale of a modulation (source at 100% and depth at 100%), in basis points
+pub const MODULATION_FULL_SCALE: i32 = 10_000;
+
+/// Voice index of a destination that targets all voices
+pub const ALL_VOICES: u8 = 255;
+
+/// Signals that can modulate a destination. LFOs are scaled to -100% to 100% around the base
+/// value of the destination, the other sources to 0-100%
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum ModulationSource {
+    /// Slot is not used
+    #[default]
+    None,
+    /// One of the LFOs (0-3), bipolar unless the slot is unipolar
+    Lfo(u8),
+    /// One of the envelopes (0-7)
+    Envelope(u8),
+    /// Velocity of the last played note after the velocity response curve
+    Velocity,
+    /// Note number of the last played note
+    NoteNumber,
+    /// Position of the mod wheel
+    ModWheel,
+    /// Channel pressure (aftertouch)
+    Aftertouch,
+}
+
+/// Parameters that can be modulated. Voice destinations take the index of the voice (0-7),
+/// or `ALL_VOICES` to modulate every voice.
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum ModulationDestination {
+    /// Slot is not used
+    #[default]
+    None,
+    /// Pitch of a voice, full scale is one octave up or down
+    VoicePitch(u8),
+    /// Amplitude of a voice. With a unipolar source a positive depth fades the voice in with the
+    /// source, a negative depth fades the voice out with the source. A bipolar source raises and
+    /// lowers the amplitude around the voice level, up to twice the level at full scale
+    VoiceAmplitude(u8),
+    /// Wavetable position of a voice, full scale is the whole position range
+    WavetablePosition(u8),
+    /// Cutoff frequency of the filter, full scale is the whole cutoff range
+    FilterCutoff,
+    /// Resonance of the filter, full scale is the whole resonance range
+    FilterResonance,
+    /// Threshold of the overdrive, full scale is the whole sample range
+    OverdriveThreshold,
+    /// Pan position of a voice, full scale is from center to fully left or right
+    Pan(u8),
+    /// Main gain of the mixer, full scale is 100%
+    MixerGain,
+    /// Frequency of a voice, full scale is `VOICE_FREQUENCY_RANGE` Hz up or down. Unlike the
+    /// pitch the offset is the same number of Hz for every note
+    VoiceFrequency(u8),
+}
+
+/// Full scale of the `VoiceFrequency` destination in Hz
+pub const VOICE_FREQUENCY_RANGE: i32 = 100;
+
+/// One slot of the modulation matrix
+#[derive(Copy, Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ModulationSlot {
+    pub source: ModulationSource,
+    pub destination: ModulationDestination,
+    /// Depth of the modulation as percentage (-100 to 100)
+    pub depth: i8,
+    /// Scale a bipolar source to 0-100% like the other sources, the LFO only moves the
+    /// destination to one side of its base value
+    #[serde(default)]
+    pub unipolar: bool,
+}
+
+impl ModulationSlot {
+    /// Whether this slot modulates anything
+    pub fn is_active(&self) -> bool {
+        self.source != ModulationSource::None
+            && self.destination != ModulationDestination::None
+            && self.depth != 0
+    }
+
+    /// Whether the source swings around zero, only LFOs in a slot that is not unipolar
+    pub fn is_bipolar(&self) -> bool {
+        matches!(self.source, ModulationSource::Lfo(_)) && !self.unipolar
+    }
+}
+
+/// Deprecated route of an LFO to the amplitude of up to two voices, replaced by the modulation
+/// matrix
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
 pub struct VoiceToLFORoute {
+    /// Voices whose amplitude follows the LFO (255 = none)
     pub voices: [u8; 2],
     pub enable: bool,
 }
 
+/// Cutoff range the deprecated LFO to filter route swept over, it replaced the configured cutoff
+const LEGACY_FILTER_SWEEP: (i32, i32) = (1_000, 11_000);
+
+/// Depth of the deprecated LFO to filter route, the sweep as percentage of the cutoff range
+const LEGACY_FILTER_DEPTH: i8 =
+    ((LEGACY_FILTER_SWEEP.1 - LEGACY_FILTER_SWEEP.0) * 100 / u16::MAX as i32) as i8;
+
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct RoutingConfiguration {
     pub voices_to_envelop: [u8; AMOUNT_OF_VOICES],
+    #[serde(default)]
+    pub modulation_matrix: [ModulationSlot; MODULATION_SLOTS],
+    /// Deprecated, LFOs to voice amplitude. Moved to the modulation matrix on load
+    #[serde(default)]
     pub lfo_to_voice: [VoiceToLFORoute; AMOUNT_OF_VOICES / 2],
+    /// Deprecated, LFO 0 to the filter cutoff. Moved to the modulation matrix on load
+    #[serde(default)]
     pub lfo_to_filter: bool,
+    /// Deprecated, LFO 0 to the frequency of voice 0. Moved to the modulation matrix on load
+    #[serde(default)]
     pub lfo_to_freq: bool,
+    /// Deprecated, frequency swing of `lfo_to_freq` in Hz
+    #[serde(default)]
     pub lfo_to_freq_amount: u16,
 }
 
+impl RoutingConfiguration {
+    /// Move the deprecated LFO routes into free slots of the modulation matrix. The LFOs of
+    /// these routes only moved the destination up, so the slots are unipolar. Routes that do not
+    /// fit in the matrix anymore are dropped.
+    ///
+    /// # Arguments
+    /// * `filter_config` - Filter of the patch, the LFO to filter route swept a fixed range and
+    ///   replaces its cutoff with the bottom of that range
+    pub fn migrate_legacy_routes(&mut self, filter_config: &mut FilterConfig) {
+        let mut routes: [Option<ModulationSlot>; AMOUNT_OF_VOICES + 2] =
+            [None; AMOUNT_OF_VOICES + 2];
+        let legacy_slot = |lfo: usize, destination, depth| {
+            Some(ModulationSlot {
+                source: ModulationSource::Lfo(lfo as u8),
+                destination,
+                depth,
+                unipolar: true,
+            })
+        };
+        for (lfo, route) in self.lfo_to_voice.iter().enumerate() {
+            if route.enable {
+                for (j, voice) in route.voices.iter().enumerate() {
+                    if (*voice as usize) < AMOUNT_OF_VOICES {
+                        routes[lfo * 2 + j] =
+                            legacy_slot(lfo, ModulationDestination::VoiceAmplitude(*voice), 100);
+                    }
+                }
+            }
+        }
+        if self.lfo_to_filter {
+            // The depth is rounded to whole percents, keep the sweep centred where it was
+            let (bottom, top) = LEGACY_FILTER_SWEEP;
+            let sweep = LEGACY_FILTER_DEPTH as i32 * u16::MAX as i32 / 100;
+            filter_config.cutoff_frequency = ((bottom + top - sweep) / 2) as u16;
+            routes[AMOUNT_OF_VOICES] =
+                legacy_slot(0, ModulationDestination::FilterCutoff, LEGACY_FILTER_DEPTH);
+        }
+        if self.lfo_to_freq && self.lfo_to_freq_amount > 0 {
+            let depth = self.lfo_to_freq_amount.min(VOICE_FREQUENCY_RANGE as u16) as i8;
+            routes[AMOUNT_OF_VOICES + 1] =
+                legacy_slot(0, ModulationDestination::VoiceFrequency(0), depth);
+        }
+
+        let free_slots = self
+            .modulation_matrix
+            .iter_mut()
+            .filter(|slot| !slot.is_active());
+        for (slot, route) in free_slots.zip(routes.into_iter().flatten()) {
+            *slot = route;
+        }
+        self.lfo_to_voice = Default::default();
+        self.lfo_to_filter = false;
+        self.lfo_to_freq = false;
+    }
+}
+
+/// Levels of the modulation sources for one block
+pub(crate) struct ModulationSources<'a> {
+    /// LFO levels (-`i16::MAX` to `i16::MAX`)
+    pub lfos: &'a [[i16; BLOCK_SIZE]; AMOUNT_OF_VOICES / 2],
+    /// Envelope levels (0 to `ENVELOPE_FULL_SCALE`)
+    pub envelops: &'a [[i16; BLOCK_SIZE]; AMOUNT_OF_VOICES],
+    /// MIDI values (0-127) of the velocity, note number, mod wheel and aftertouch
+    pub velocity: u8,
+    pub note: u8,
+    pub mod_wheel: u8,
+    pub aftertouch: u8,
+    /// Depth of the slots with an LFO source in basis points
+    pub lfo_depth: u16,
+}
+
+/// A modulation signal per sample in basis points of the destination range, with a flag
+/// telling whether any slot modulates the destination in the current block
+#[derive(Copy, Clone)]
+pub(crate) struct Modulation {
+    pub active: bool,
+    pub signal: [i16; BLOCK_SIZE],
+}
+
+impl Modulation {
+    const fn new() -> Self {
+        Self {
+            active: false,
+            signal: [0; BLOCK_SIZE],
+        }
+    }
+}
+
+/// The summed modulation signal of every destination
+pub(crate) struct Modulations {
+    pub pitch: [Modulation; AMOUNT_OF_VOICES],
+    pub frequency: [Modulation; AMOUNT_OF_VOICES],
+    pub amplitude: [Modulation; AMOUNT_OF_VOICES],
+    pub wavetable_position: [Modulation; AMOUNT_OF_VOICES],
+    pub pan: [Modulation; AMOUNT_OF_VOICES],
+    pub cutoff: Modulation,
+    pub resonance: Modulation,
+    pub threshold: Modulation,
+    pub gain: Modulation,
+}
+
+impl Modulations {
+    pub const fn new() -> Self {
+        Self {
+            pitch: [Modulation::new(); AMOUNT_OF_VOICES],
+            frequency: [Modulation::new(); AMOUNT_OF_VOICES],
+            amplitude: [Modulation::new(); AMOUNT_OF_VOICES],
+            wavetable_position: [Modulation::new(); AMOUNT_OF_VOICES],
+            pan: [Modulation::new(); AMOUNT_OF_VOICES],
+            cutoff: Modulation::new(),
+            resonance: Modulation::new(),
+            threshold: Modulation::new(),
+            gain: Modulation::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        for modulation in self
+            .pitch
+            .iter_mut()
+            .chain(self.frequency.iter_mut())
+            .chain(self.amplitude.iter_mut())
+            .chain(self.wavetable_position.iter_mut())
+            .chain(self.pan.iter_mut())
+        {
+            modulation.active = false;
+        }
+        self.cutoff.active = false;
+        self.resonance.active = false;
+        self.threshold.active = false;
+        self.gain.active = false;
+    }
+}
+
 pub struct Router {
     pub config: RoutingConfiguration,
 }
@@ -31,4 +281,127 @@ impl Router {
     pub fn reload(&mut self, config: RoutingConfiguration) {
         self.config = config;
     }
+
+    /// Run the modulation matrix over a block, summing the modulation of every slot per destination
+    pub(crate) fn modulate(
+        &self,
+        sources: &ModulationSources,
+        amount_of_frames: usize,
+        modulations: &mut Modulations,
+    ) {
+        modulations.reset();
+        for slot in self
+            .config
+            .modulation_matrix
+            .iter()
+            .filter(|slot| slot.is_active())
+        {
+            let depth = slot.depth as i32;
+            let bipolar = slot.is_bipolar();
+            // A unipolar source can only lower the amplitude of a voice, a positive depth opens up
+            // with the source
+            let offset = match slot.destination {
+                ModulationDestination::VoiceAmplitude(_) if !bipolar => {
+                    -MODULATION_FULL_SCALE / 100 * depth.max(0)
+                }
+                _ => 0,
+            };
+            let (targets, voice) = match slot.destination {
+                ModulationDestination::VoicePitch(voice) => (&mut modulations.pitch[..], voice),
+                ModulationDestination::VoiceFrequency(voice) => {
+                    (&mut modulations.frequency[..], voice)
+                }
+                ModulationDestination::VoiceAmplitude(voice) => {
+                    (&mut modulations.amplitude[..], voice)
+                }
+                ModulationDestination::WavetablePosition(voice) => {
+                    (&mut modulations.wavetable_position[..], voice)
+                }
+                ModulationDestination::Pan(voice) => (&mut modulations.pan[..], voice),
+                ModulationDestination::FilterCutoff => {
+                    (core::slice::from_mut(&mut modulations.cutoff), 0)
+                }
+                ModulationDestination::FilterResonance => {
+                    (core::slice::from_mut(&mut modulations.resonance), 0)
+                }
+                ModulationDestination::OverdriveThreshold => {
+                    (core::slice::from_mut(&mut modulations.threshold), 0)
+                }
+                ModulationDestination::MixerGain => {
+                    (core::slice::from_mut(&mut modulations.gain), 0)
+                }
+                ModulationDestination::None => continue,
+            };
+            let targets = match voice {
+                ALL_VOICES => targets,
+                voice if (voice as usize) < targets.len() => {
+                    &mut targets[voice as usize..=voice as usize]
+                }
+                _ => continue,
+            };
+            let scale = match slot.source {
+                ModulationSource::Lfo(_) => sources.lfo_depth as i32,
+                _ => MODULATION_FULL_SCALE,
+            };
+            for target in targets.iter_mut() {
+                let signal = &mut target.signal[..amount_of_frames];
+                if !target.active {
+                    signal.fill(0);
+                    target.active = true;
+                }
+                for (n, sample) in signal.iter_mut().enumerate() {
+                    let level = Router::level(slot.source, bipolar, sources, n);
+                    let value = scale_depth(level * depth / 100 + offset, scale);
+                    *sample = sample.saturating_add(value as i16);
+                }
+            }
+        }
+    }
+
+    /// Level of a modulation source in basis points at a sample of the block, -10000 to 10000
+    /// for a bipolar source and 0 to 10000 otherwise
+    fn level(
+        source: ModulationSource,
+        bipolar: bool,
+        sources: &ModulationSources,
+        n: usize,
+    ) -> i32 {
+        let midi = |value: u8| value as i32 * MODULATION_FULL_SCALE / 127;
+        match source {
+            ModulationSource::None => 0,
+            ModulationSource::Lfo(lfo) => sources.lfos.get(lfo as usize).map_or(0, |lfo| {
+                let level = lfo[n] as i32;
+                if bipolar {
+                    level * MODULATION_FULL_SCALE / i16::MAX as i32
+                } else {
+                    (level + i16::MAX as i32) * (MODULATION_FULL_SCALE / 2) / i16::MAX as i32
+                }
+            }),
+            ModulationSource::Envelope(envelop) => {
+                sources.envelops.get(envelop as usize).map_or(0, |envelop| {
+                    envelop[n] as i32 * MODULATION_FULL_SCALE / ENVELOPE_FULL_SCALE as i32
+                })
+            }
+            ModulationSource::Velocity => midi(sources.velocity),
+            ModulationSource::NoteNumber => midi(sources.note),
+            ModulationSource::ModWheel => midi(sources.mod_wheel),
+            ModulationSource::Aftertouch => midi(sources.aftertouch),
+        }
+    }
+}
+
+/// Scale a slot output by a depth in basis points
+#[inline]
+fn scale_depth(value: i32, depth: i32) -> i32 {
+    if depth == MODULATION_FULL_SCALE {
+        value
+    } else {
+        value * depth / MODULATION_FULL_SCALE
+    }
+}
+
+/// Scale a modulation signal (in basis points) to a destination range
+#[inline]
+pub(crate) fn scale(signal: i16, range: i32) -> i32 {
+    signal as i32 * range / MODULATION_FULL_SCALE
 }
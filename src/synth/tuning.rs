@@ -0,0 +1,306 @@
+//! Tuning tables
+//!
+//! A tuning table holds the frequency of every MIDI note. The default table is 12-TET tuned
+//! to A4 = 440 Hz, other tables are built from Scala scale (`.scl`) and keyboard mapping
+//! (`.kbm`) files. Parsing works on `no_std`, reading the files from disk needs the `std` feature.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use super::{
+    data::frequencies::{FREQ_SHIFT, MIDI2FREQ},
+    math,
+};
+
+/// Number of MIDI notes in a tuning table
+pub const AMOUNT_OF_NOTES: usize = 128;
+
+/// Reference frequency of A4 as 16.16 fixed point, the master tune of the default tuning
+pub const DEFAULT_MASTER_TUNE: u32 = 440 << FREQ_SHIFT;
+
+/// Key of middle C, the first degree of a scale without a keyboard mapping
+const MIDDLE_C: i32 = 60;
+
+/// Reasons a Scala file can not be turned into a tuning table
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ScalaError {
+    /// The scale file misses its note count or has a pitch that is not a ratio or cents
+    InvalidScale,
+    /// The keyboard mapping misses a header line or has an invalid entry
+    InvalidKeyboardMapping,
+    /// The reference note of the keyboard mapping is not mapped to a scale degree
+    UnmappedReferenceNote,
+    /// The file could not be read
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+}
+
+/// Frequency of every MIDI note, swappable at runtime with `Synth::load_tuning`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TuningTable {
+    /// Frequency per MIDI note in Hz as 16.16 fixed point, 0 = the note is not mapped
+    frequencies: [u32; AMOUNT_OF_NOTES],
+}
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        Self::equal_temperament()
+    }
+}
+
+impl TuningTable {
+    /// 12-TET tuned to A4 = 440 Hz
+    pub fn equal_temperament() -> Self {
+        Self {
+            frequencies: MIDI2FREQ,
+        }
+    }
+
+    /// Creates a tuning table from the frequency of every MIDI note
+    ///
+    /// # Arguments
+    /// * `frequencies` - Frequency per MIDI note in Hz as 16.16 fixed point, 0 leaves the note silent
+    pub fn from_frequencies(frequencies: [u32; AMOUNT_OF_NOTES]) -> Self {
+        Self { frequencies }
+    }
+
+    /// Frequency of a MIDI note
+    ///
+    /// # Returns
+    /// The frequency in Hz as 16.16 fixed point, `None` when the note is not mapped
+    pub fn frequency(&self, note: u8) -> Option<u32> {
+        self.frequencies
+            .get(note as usize)
+            .copied()
+            .filter(|frequency| *frequency > 0)
+    }
+
+    /// Creates a tuning table from the contents of a Scala scale file and an optional keyboard mapping
+    ///
+    /// Without a keyboard mapping the first degree of the scale is played by middle C, which
+    /// keeps its 12-TET frequency, and every next key plays the next degree.
+    ///
+    /// # Arguments
+    /// * `scl` - Contents of the `.scl` file
+    /// * `kbm` - Contents of the `.kbm` file
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, ScalaError> {
+        let scale = parse_scale(scl)?;
+        let mapping = match kbm {
+            Some(kbm) => parse_keyboard_mapping(kbm)?,
+            None => KeyboardMapping::linear(scale.len()),
+        };
+        let key_pitch = |key: i32| mapping.degree(key).map(|degree| pitch(&scale, degree));
+        let reference =
+            key_pitch(mapping.reference_note).ok_or(ScalaError::UnmappedReferenceNote)?;
+        let reference_frequency = math::log2(mapping.reference_frequency);
+
+        let mut frequencies = [0; AMOUNT_OF_NOTES];
+        for (key, frequency) in frequencies.iter_mut().enumerate() {
+            let key = key as i32;
+            if key < mapping.first_note || key > mapping.last_note {
+                continue;
+            }
+            if let Some(pitch) = key_pitch(key) {
+                *frequency = math::exp2(reference_frequency + pitch - reference);
+            }
+        }
+        Ok(Self { frequencies })
+    }
+
+    /// Loads a tuning table from a Scala scale file and an optional keyboard mapping file
+    ///
+    /// # Arguments
+    /// * `scl` - Path of the `.scl` file
+    /// * `kbm` - Path of the `.kbm` file
+    #[cfg(feature = "std")]
+    pub fn load_scala(
+        scl: impl AsRef<std::path::Path>,
+        kbm: Option<impl AsRef<std::path::Path>>,
+    ) -> Result<Self, ScalaError> {
+        let read = |path: &std::path::Path| {
+            std::fs::read_to_string(path).map_err(|error| ScalaError::Io(error.kind()))
+        };
+        let scl = read(scl.as_ref())?;
+        let kbm = match kbm {
+            Some(kbm) => Some(read(kbm.as_ref())?),
+            None => None,
+        };
+        Self::from_scala(&scl, kbm.as_deref())
+    }
+}
+
+/// Keyboard mapping of a Scala `.kbm` file
+struct KeyboardMapping {
+    /// Scale degree per key of the mapping pattern, empty for a linear mapping
+    map: Vec<Option<i32>>,
+    /// Lowest key that plays
+    first_note: i32,
+    /// Highest key that plays
+    last_note: i32,
+    /// Key that plays the first entry of the mapping
+    middle_note: i32,
+    /// Key that is tuned to the reference frequency
+    reference_note: i32,
+    /// Reference frequency in Hz as 16.16 fixed point
+    reference_frequency: u32,
+    /// Scale degrees between two repetitions of the mapping pattern
+    octave_degree: i32,
+}
+
+impl KeyboardMapping {
+    /// Every key plays the next degree, middle C plays the first degree at its 12-TET frequency
+    fn linear(scale_size: usize) -> Self {
+        Self {
+            map: Vec::new(),
+            first_note: 0,
+            last_note: AMOUNT_OF_NOTES as i32 - 1,
+            middle_note: MIDDLE_C,
+            reference_note: MIDDLE_C,
+            reference_frequency: MIDI2FREQ[MIDDLE_C as usize],
+            octave_degree: scale_size as i32,
+        }
+    }
+
+    /// Scale degree played by a key, `None` when the key is not mapped
+    fn degree(&self, key: i32) -> Option<i32> {
+        let offset = key - self.middle_note;
+        if self.map.is_empty() {
+            return Some(offset);
+        }
+        let size = self.map.len() as i32;
+        let degree = self.map[offset.rem_euclid(size) as usize]?;
+        Some(offset.div_euclid(size) * self.octave_degree + degree)
+    }
+}
+
+/// Pitch of a scale degree in octaves as 16.16 fixed point, relative to the first degree
+///
+/// # Arguments
+/// * `scale` - Pitch of every degree after the first one, the last degree is the period of the scale
+/// * `degree` - The scale degree, degrees past the period repeat the scale
+fn pitch(scale: &[i32], degree: i32) -> i32 {
+    let size = scale.len() as i32;
+    let period = scale[scale.len() - 1];
+    let step = degree.rem_euclid(size);
+    let repetitions = degree.div_euclid(size);
+    let offset = if step == 0 {
+        0
+    } else {
+        scale[step as usize - 1]
+    };
+    repetitions * period + offset
+}
+
+/// Lines of a Scala file without the comments
+fn lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('!'))
+}
+
+/// Parse a `.scl` file into the pitch of every degree after the first one
+fn parse_scale(scl: &str) -> Result<Vec<i32>, ScalaError> {
+    // The first line is the description, which may be empty
+    let mut lines = lines(scl).skip(1).filter(|line| !line.is_empty());
+    let count: usize = lines
+        .next()
+        .and_then(|line| first_word(line).parse().ok())
+        .ok_or(ScalaError::InvalidScale)?;
+    let scale = lines
+        .take(count)
+        .map(|line| parse_pitch(first_word(line)).ok_or(ScalaError::InvalidScale))
+        .collect::<Result<Vec<i32>, ScalaError>>()?;
+    if scale.is_empty() || scale.len() != count {
+        return Err(ScalaError::InvalidScale);
+    }
+    Ok(scale)
+}
+
+/// Parse a pitch of a `.scl` file, cents when it has a period, otherwise a ratio or a whole number
+///
+/// # Returns
+/// The pitch in octaves as 16.16 fixed point
+fn parse_pitch(word: &str) -> Option<i32> {
+    if word.contains('.') {
+        let cents: f64 = word.parse().ok()?;
+        return Some(round(cents * (1 << 16) as f64 / 1200.0));
+    }
+    let (numerator, denominator) = match word.split_once('/') {
+        Some((numerator, denominator)) => (numerator.parse().ok()?, denominator.parse().ok()?),
+        None => (word.parse().ok()?, 1),
+    };
+    if numerator == 0 || denominator == 0 {
+        return None;
+    }
+    // Both logarithms are off by the same 16 octaves of the fixed point format
+    Some(math::log2(numerator) - math::log2(denominator))
+}
+
+/// Parse a `.kbm` file
+fn parse_keyboard_mapping(kbm: &str) -> Result<KeyboardMapping, ScalaError> {
+    let mut words = lines(kbm).filter(|line| !line.is_empty()).map(first_word);
+    let mut number = || -> Result<i32, ScalaError> {
+        words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or(ScalaError::InvalidKeyboardMapping)
+    };
+    let size = number()?;
+    let first_note = number()?;
+    let last_note = number()?;
+    let middle_note = number()?;
+    let reference_note = number()?;
+    let reference_frequency: f64 = words
+        .next()
+        .and_then(|word| word.parse().ok())
+        .filter(|frequency| *frequency > 0.0)
+        .ok_or(ScalaError::InvalidKeyboardMapping)?;
+    let octave_degree = words
+        .next()
+        .and_then(|word| word.parse().ok())
+        .ok_or(ScalaError::InvalidKeyboardMapping)?;
+    // A pattern longer than the MIDI keys can never repeat
+    if !(0..=AMOUNT_OF_NOTES as i32).contains(&size) {
+        return Err(ScalaError::InvalidKeyboardMapping);
+    }
+    // Missing entries at the end of the mapping are not mapped
+    let mut map = Vec::with_capacity(size as usize);
+    for _ in 0..size {
+        map.push(match words.next() {
+            None | Some("x") | Some("X") => None,
+            Some(word) => Some(
+                word.parse()
+                    .map_err(|_| ScalaError::InvalidKeyboardMapping)?,
+            ),
+        });
+    }
+    let octave_degree = if octave_degree == 0 {
+        size
+    } else {
+        octave_degree
+    };
+    Ok(KeyboardMapping {
+        map,
+        first_note,
+        last_note,
+        middle_note,
+        reference_note,
+        reference_frequency: round(reference_frequency * (1 << FREQ_SHIFT) as f64) as u32,
+        octave_degree,
+    })
+}
+
+/// First word of a line, the rest of a line of a Scala file is a comment
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// Round to the nearest whole number
+fn round(value: f64) -> i32 {
+    if value < 0.0 {
+        (value - 0.5) as i32
+    } else {
+        (value + 0.5) as i32
+    }
+}
@@ -0,0 +1,118 @@
+//! Velocity response
+//!
+//! Maps the velocity of a played note through a response curve and turns the result into
+//! the gain, cutoff offset and envelope time scale of that note.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of points of a custom velocity curve, spread evenly over the velocity range
+pub const VELOCITY_TABLE_SIZE: usize = 9;
+
+/// Highest MIDI velocity
+const MAX_VELOCITY: i32 = 127;
+
+/// Response curve from the played velocity to the velocity used by the synthesizer
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum VelocityCurve {
+    /// The played velocity
+    #[default]
+    Linear,
+    /// Squared velocity, soft playing stays quiet and hard playing opens up
+    Exponential,
+    /// Every note plays with the same velocity (0-127)
+    Fixed(u8),
+    /// Velocity (0-127) at velocities 0, 16, 32 .. 112 and 127, interpolated in between
+    Table([u8; VELOCITY_TABLE_SIZE]),
+}
+
+/// Amplitude depth used by patches that do not define one, fully velocity sensitive
+pub const DEFAULT_VELOCITY_TO_AMPLITUDE: u8 = 100;
+
+fn default_velocity_to_amplitude() -> u8 {
+    DEFAULT_VELOCITY_TO_AMPLITUDE
+}
+
+/// Configuration of the velocity response
+#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+pub struct VelocityConfiguration {
+    /// Response curve applied to the velocity of every note
+    #[serde(default)]
+    pub curve: VelocityCurve,
+    /// How much the velocity controls the amplitude as percentage (0-100), at 0 every note
+    /// plays at full level, at 100 velocity 0 is silent
+    #[serde(default = "default_velocity_to_amplitude")]
+    pub amplitude: u8,
+    /// Cutoff change at full velocity as percentage of the cutoff range (-100 to 100)
+    #[serde(default)]
+    pub cutoff: i8,
+    /// Change of the attack and decay times at full velocity as percentage (-100 to 127),
+    /// -50 halves the times of the hardest notes
+    #[serde(default)]
+    pub envelope_time: i8,
+}
+
+impl Default for VelocityConfiguration {
+    fn default() -> Self {
+        Self {
+            curve: VelocityCurve::Linear,
+            amplitude: DEFAULT_VELOCITY_TO_AMPLITUDE,
+            cutoff: 0,
+            envelope_time: 0,
+        }
+    }
+}
+
+impl VelocityConfiguration {
+    /// Apply the response curve to a played velocity
+    ///
+    /// # Arguments
+    /// * `velocity` - The played velocity (0-127)
+    ///
+    /// # Returns
+    /// The velocity used by the synthesizer (0-127)
+    pub fn response(&self, velocity: u8) -> u8 {
+        let velocity = (velocity as i32).min(MAX_VELOCITY);
+        let response = match self.curve {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential => velocity * velocity / MAX_VELOCITY,
+            VelocityCurve::Fixed(level) => level as i32,
+            VelocityCurve::Table(table) => {
+                let step = (MAX_VELOCITY + 1) / (VELOCITY_TABLE_SIZE as i32 - 1);
+                let index = ((velocity / step) as usize).min(VELOCITY_TABLE_SIZE - 2);
+                let start = index as i32 * step;
+                // The last segment ends at 127 instead of 128
+                let end = (start + step).min(MAX_VELOCITY);
+                let from = table[index] as i32;
+                let to = table[index + 1] as i32;
+                from + (to - from) * (velocity - start) / (end - start)
+            }
+        };
+        response.clamp(0, MAX_VELOCITY) as u8
+    }
+
+    /// Gain of a note as percentage (0-100)
+    ///
+    /// # Arguments
+    /// * `velocity` - The velocity after the response curve (0-127)
+    pub fn gain(&self, velocity: u8) -> i16 {
+        let depth = self.amplitude.min(100) as i32;
+        (100 - depth * (MAX_VELOCITY - velocity as i32) / MAX_VELOCITY) as i16
+    }
+
+    /// Cutoff offset of a note on the cutoff range (0-65535)
+    ///
+    /// # Arguments
+    /// * `velocity` - The velocity after the response curve (0-127)
+    pub fn cutoff_offset(&self, velocity: u8) -> i32 {
+        self.cutoff as i32 * velocity as i32 * u16::MAX as i32 / (100 * MAX_VELOCITY)
+    }
+
+    /// Scale of the attack and decay times of a note as percentage, 100 keeps the times
+    ///
+    /// # Arguments
+    /// * `velocity` - The velocity after the response curve (0-127)
+    pub fn envelope_time_scale(&self, velocity: u8) -> u16 {
+        let change = self.envelope_time as i32 * velocity as i32 / MAX_VELOCITY;
+        (100 + change).max(0) as u16
+    }
+}
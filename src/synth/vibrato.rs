@@ -0,0 +1,77 @@
+//! Vibrato
+//!
+//! Bends the pitch of the voices up and down with one of the LFOs. The depth is set in cents,
+//! the vibrato can be limited to a subset of the voices and can wait after every note before it
+//! sets in.
+
+use serde::{Deserialize, Serialize};
+
+use super::AMOUNT_OF_VOICES;
+
+/// Voice mask of a vibrato on every voice
+pub const ALL_VOICES_MASK: u8 = u8::MAX;
+
+fn default_voices() -> u8 {
+    ALL_VOICES_MASK
+}
+
+/// Configuration of the vibrato
+#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+pub struct VibratoConfiguration {
+    /// LFO that drives the vibrato (0-3)
+    #[serde(default)]
+    pub lfo: u8,
+    /// Pitch change up and down at the peaks of the LFO in cents (100 = one semitone),
+    /// 0 = no vibrato
+    #[serde(default)]
+    pub depth: u16,
+    /// Time in milliseconds after a note starts before the vibrato sets in
+    #[serde(default)]
+    pub delay_time: u16,
+    /// Voices with vibrato, one bit per voice (bit 0 = voice 0), 255 = every voice
+    #[serde(default = "default_voices")]
+    pub voices: u8,
+}
+
+impl Default for VibratoConfiguration {
+    fn default() -> Self {
+        Self {
+            lfo: 0,
+            depth: 0,
+            delay_time: 0,
+            voices: ALL_VOICES_MASK,
+        }
+    }
+}
+
+impl VibratoConfiguration {
+    /// Whether a voice has vibrato
+    ///
+    /// # Arguments
+    /// * `voice` - Index of the voice (0-7)
+    pub fn applies_to(&self, voice: usize) -> bool {
+        self.depth > 0 && voice < AMOUNT_OF_VOICES && self.voices & (1 << voice) != 0
+    }
+
+    /// Length of the delay before the vibrato sets in
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Sample rate in Hz
+    ///
+    /// # Returns
+    /// The delay in samples
+    pub fn delay_length(&self, sample_rate: u16) -> u32 {
+        (self.delay_time as u32 * sample_rate as u32 + 500) / 1000
+    }
+
+    /// Pitch change at an LFO level
+    ///
+    /// # Arguments
+    /// * `level` - Level of the LFO (-`i16::MAX` to `i16::MAX`)
+    ///
+    /// # Returns
+    /// The pitch change in cents
+    pub fn cents(&self, level: i16) -> i32 {
+        level as i32 * self.depth as i32 / i16::MAX as i32
+    }
+}
@@ -1,7 +1,8 @@
 //! WaveTableOscillator to generate sounds using Wavetable synthesis.
 
-use super::data::wavetables::{BoxedWavetables, Wavetables};
-use super::math::percentage;
+use super::data::frequencies::FREQ_SHIFT;
+use super::data::wavetables::{BoxedWavetables, Wavetables, WAVETABLE_SIZE};
+use super::math::{cents_to_ratio, exp2, hermite_interpolation, linear_interpolation, log2};
 use super::Clockable;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
@@ -9,31 +10,93 @@ use rand::{Rng, SeedableRng};
 use serde::Deserialize;
 use serde::Serialize;
 
+pub use super::lfo::WaveTableLoFreqOscillatorConfig;
+
 extern crate alloc;
 use alloc::sync::Arc;
 
-/// Configuration for low-frequency wave table oscillator
-#[derive(Copy, Clone, Serialize, Deserialize)]
-pub struct WaveTableLoFreqOscillatorConfig {
-    /// Index of the soundbank to use for waveform generation
-    pub soundbank_index: u8,
-    /// Time parameter for LFO operations (in 100x seconds)
-    pub time: u16,
+/// How the oscillator reads in between two samples of the wavetable
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum Interpolation {
+    /// Use the nearest sample below the phase (cheapest)
+    #[default]
+    None,
+    /// Linear interpolation between two neighbouring samples
+    Linear,
+    /// 4-point Hermite interpolation (best quality, most CPU)
+    Hermite,
+}
+
+/// How the portamento time relates to the distance between two notes
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum PortamentoMode {
+    /// Every glide takes the portamento time, whatever the distance
+    #[default]
+    ConstantTime,
+    /// The portamento time is the time to glide one octave, larger distances take longer
+    ConstantRate,
+}
+
+/// How a voice is modulated by an earlier voice of its note, the modulator
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize, Debug)]
+pub enum VoiceModulation {
+    /// The voice plays on its own
+    #[default]
+    None,
+    /// The cycle restarts whenever the cycle of the modulator starts
+    HardSync,
+    /// The output is multiplied by the modulator
+    RingMod,
+    /// The modulator shifts the phase, the modulation index is the peak shift in radians
+    PhaseMod,
+    /// The modulator moves the frequency, the modulation index is the peak change relative to
+    /// the frequency of the voice. The frequency stops at zero
+    LinearFm,
+    /// Like `LinearFm`, but below zero the cycle runs backwards
+    ThroughZeroFm,
 }
 
 /// Configuration for standard wave table oscillator
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
 pub struct WaveTableOscillatorConfig {
     /// Index of the soundbank to use for waveform generation
     pub soundbank_index: u8,
     /// Whether to enable frequency glide (smooth frequency transitions)
     pub glide: bool,
-    /// Rate of frequency glide (0 to 255, higher values = faster glide)
+    /// Rate of frequency glide (1 to 255, higher values = faster glide, 0 = no glide), only used
+    /// without a portamento time
     pub glide_rate: u8,
     /// Detune amount in semitones (-128 to 127)
     pub detune: i8,
     /// Frequency detune amount in Hz (-128 to 127)
     pub freq_detune: i8,
+    /// Interpolation used when reading the wavetable
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// Crossfade between two band-limited levels instead of switching per octave
+    /// (only used when the wavetable has band-limited levels)
+    #[serde(default)]
+    pub band_limit_crossfade: bool,
+    /// Position within a multi-frame wavetable (0 = first frame, 65535 = last frame),
+    /// in between two frames the oscillator crossfades between them
+    #[serde(default)]
+    pub wavetable_position: u16,
+    /// Glide time in ms, gliding evenly in pitch every sample. 0 = use `glide_rate` instead
+    #[serde(default)]
+    pub portamento_time: u16,
+    /// Whether the portamento time is per glide or per octave
+    #[serde(default)]
+    pub portamento_mode: PortamentoMode,
+    /// How the voice is modulated by its modulator
+    #[serde(default)]
+    pub modulation: VoiceModulation,
+    /// Voice of the same note that modulates this voice, counted from the first voice of the
+    /// note. Only a voice before this voice can be the modulator
+    #[serde(default)]
+    pub modulator: u8,
+    /// Modulation index of phase and frequency modulation in hundredths (100 = 1.0)
+    #[serde(default)]
+    pub modulation_index: u16,
 }
 
 /// WaveTableOscillator generates audio signals using wavetable synthesis
@@ -41,124 +104,168 @@ pub struct WaveTableOscillatorConfig {
 pub struct WaveTableOscillator {
     /// Configuration settings for this oscillator instance
     pub config: WaveTableOscillatorConfig,
-    
-    /// Current phase position in the wavetable (0 to loop_end)
-    phase: u16,
-    
-    /// End point of the wavetable loop (determines period length)
-    loop_end: u16,
-    
+
+    /// Current position in the wavetable as 16.16 fixed point (0 to WAVETABLE_SIZE)
+    phase: u32,
+
+    /// Amount the phase advances every sample as 16.16 fixed point, derived from the current frequency
+    phase_increment: u32,
+
+    /// Band-limited level of the wavetable that plays the current frequency without aliasing
+    band_limited_level: u8,
+
+    /// Position of the current frequency within the octave of the band-limited level (0.16 fixed point)
+    band_limited_blend: u16,
+
+    /// Frame of a multi-frame wavetable selected by the wavetable position
+    frame: u16,
+
+    /// Position in between `frame` and the next frame (0.16 fixed point)
+    frame_blend: u16,
+
     /// Flag indicating whether frequency has changed and needs processing
     freq_changed: bool,
-    
+
     /// Random number generator for noise generation
     random: SmallRng,
-    
+
     /// Sample rate of the audio system (used for frequency calculations)
     sample_rate: u16,
-    
-    /// Lookup table for converting phase to wavetable indices
-    lookup_table: [u16; 3000],
-    
-    /// Target frequency for glide operations
-    target_freq: u16,
-    
-    /// Original frequency before any detuning
-    original_freq: u16,
-    
-    /// Step size for frequency glide calculations
-    freq_step: i16,
-    
-    /// Current actual frequency being used for playback
-    current_freq: u16,
-    
-    /// Speed factor for sample processing (controls how often we process samples)
-    speed: u16,
-    
-    /// Last output value generated by the oscillator
-    last_output: i16,
-    
-    /// Counter for tracking when to process next sample
-    speed_count: u16,
-    
+
+    /// Target frequency for glide operations (16.16 fixed point Hz)
+    target_freq: u32,
+
+    /// Step size for frequency glide calculations (16.16 fixed point Hz)
+    freq_step: i32,
+
+    /// Current actual frequency being used for playback (16.16 fixed point Hz)
+    current_freq: u32,
+
+    /// Pitch modulation in cents applied on top of the current frequency
+    pitch_modulation: i32,
+
+    /// Frequency ratio of the pitch modulation (16.16 fixed point)
+    pitch_ratio: u32,
+
+    /// Frequency modulation added after the pitch modulation (16.16 fixed point Hz)
+    frequency_modulation: i32,
+
+    /// Remaining pitch distance of the portamento to the target frequency (8.24 fixed point octaves)
+    portamento_offset: i32,
+
+    /// Pitch distance the portamento covers every sample (8.24 fixed point octaves)
+    portamento_step: i32,
+
+    /// Frequency ratio of the remaining portamento distance (16.16 fixed point)
+    portamento_ratio: u32,
+
+    /// Whether the phase crossed the start of the cycle at the last clock
+    cycle_started: bool,
+
     /// Shared reference to the wavetables data structure
     wavetables: Arc<BoxedWavetables>,
 }
 
+/// Length of one wavetable cycle in phase units (16.16 fixed point)
+const PHASE_END: u32 = (WAVETABLE_SIZE as u32) << 16;
+
+/// Extra fraction bits of the portamento pitch distance, so slow glides keep their exact time
+const PORTAMENTO_SHIFT: u32 = 8;
+
+/// One cycle in hundredths of a radian, the unit of the phase modulation index
+const CYCLE_IN_HUNDREDTHS_OF_RADIANS: i64 = 628;
+
 impl Clockable for WaveTableOscillator {
-    fn clock(&mut self, _sample: Option<i16>) -> i16 {
-        self.speed_count += 1;
-        if self.speed == self.speed_count {
-            self.speed_count = 0;
-
-            // Reset phase when reaching end
-            if self.phase == self.loop_end {
-                self.phase = 0;
-
-                // Handle frequency changes
-                if self.freq_changed {
-                    if self.current_freq != self.target_freq {
-                        let diff = self.target_freq as i16 - self.current_freq as i16;
-                        let step_size = self.freq_step;
-
-                        if diff.abs() < step_size.abs() {
-                            self.current_freq = self.target_freq;
-                            self.freq_changed = false;
-                        } else {
-                            self.current_freq = (self.current_freq as i16 + step_size) as u16;
-                        }
-                    } else {
-                        self.freq_changed = false;
-                    }
-
-                    self.loop_end = self.sample_rate / self.current_freq;
-                    self.calculate_lookup_table();
-                }
+    /// Generate the next sample
+    ///
+    /// # Arguments
+    /// * `sample` - Output of the modulator, used by ring, phase and frequency modulation
+    fn clock(&mut self, sample: Option<i16>) -> i16 {
+        if self.portamento_offset != 0 {
+            self.portamento();
+        }
+        let modulation = match sample {
+            Some(_) => self.config.modulation,
+            None => VoiceModulation::None,
+        };
+        let modulator = sample.unwrap_or_default() as i64;
+
+        // Generate output
+        let output = match self.config.soundbank_index {
+            255 => {
+                // Random noise
+                self.random
+                    .random_range((i16::MIN + 1000)..(i16::MAX - 1000))
             }
+            _ if modulation == VoiceModulation::PhaseMod => {
+                let phase = self.phase;
+                let shift = modulator * self.config.modulation_index as i64 * PHASE_END as i64
+                    / (i16::MAX as i64 * CYCLE_IN_HUNDREDTHS_OF_RADIANS);
+                self.phase = (phase as i64 + shift).rem_euclid(PHASE_END as i64) as u32;
+                let output = self.read();
+                self.phase = phase;
+                output
+            }
+            _ => self.read(),
+        };
+        let output = match modulation {
+            VoiceModulation::RingMod => ((output as i64 * modulator) >> 15) as i16,
+            _ => output,
+        };
 
-            // Generate output
-            let output = match self.config.soundbank_index {
-                255 => {
-                    // Random noise
-                    self.random
-                        .random_range((i16::MIN + 1000)..(i16::MAX - 1000))
+        // Advance the phase and wrap around at the end of the wavetable
+        self.cycle_started = match modulation {
+            VoiceModulation::LinearFm | VoiceModulation::ThroughZeroFm => {
+                let deviation =
+                    self.phase_increment as i64 * modulator * self.config.modulation_index as i64
+                        / (i16::MAX as i64 * 100);
+                let mut increment = self.phase_increment as i64 + deviation;
+                if modulation == VoiceModulation::LinearFm {
+                    increment = increment.max(0);
                 }
-                _ => {
-                    // Wavetable lookup
-                    let index = self.lookup_table[self.phase as usize] as usize;
-                    self.wavetables
-                        .get_wavetable_reference(self.config.soundbank_index)[index]
+                let phase = self.phase as i64 + increment;
+                self.phase = phase.rem_euclid(PHASE_END as i64) as u32;
+                !(0..PHASE_END as i64).contains(&phase)
+            }
+            _ => {
+                self.phase += self.phase_increment;
+                let wrapped = self.phase >= PHASE_END;
+                if wrapped {
+                    self.phase %= PHASE_END;
                 }
-            };
-
-            self.phase += 1;
-            self.last_output = output;
+                wrapped
+            }
+        };
+        // Handle frequency changes (glide) once per cycle
+        if self.cycle_started && self.freq_changed {
+            self.glide();
         }
-
-        self.last_output
+        output
     }
 }
 
-impl WaveTableOscillator {
-    /// Creates a new low-frequency oscillator instance
-    /// Used for modulation effects like LFOs
-    pub fn new_lfo(
-        config: WaveTableLoFreqOscillatorConfig,
-        sample_rate: u16,
-        wavetables: Arc<BoxedWavetables>,
-    ) -> Self {
-        let new_config = WaveTableOscillatorConfig {
-            soundbank_index: config.soundbank_index,
-            glide: false,
-            glide_rate: 0,
-            detune: 0,
-            freq_detune: 0,
-        };
-        let mut osc = Self::new(new_config, sample_rate, wavetables);
-        osc.speed = 4 * config.time;
-        osc
+/// Read the wavetable at a 16.16 fixed point phase
+#[inline]
+fn read_wavetable(wavetable: &[i16], phase: u32, interpolation: Interpolation) -> i16 {
+    let index = (phase >> 16) as usize;
+    let fraction = phase as u16;
+    let size = wavetable.len();
+    match interpolation {
+        Interpolation::None => wavetable[index],
+        Interpolation::Linear => {
+            linear_interpolation(wavetable[index], wavetable[(index + 1) % size], fraction)
+        }
+        Interpolation::Hermite => hermite_interpolation(
+            wavetable[(index + size - 1) % size],
+            wavetable[index],
+            wavetable[(index + 1) % size],
+            wavetable[(index + 2) % size],
+            fraction,
+        ),
     }
+}
 
+impl WaveTableOscillator {
     pub fn new(
         config: WaveTableOscillatorConfig,
         sample_rate: u16,
@@ -167,66 +274,230 @@ impl WaveTableOscillator {
         let mut osc = Self {
             config,
             phase: 0,
-            loop_end: sample_rate / 440 + 1,
+            phase_increment: 0,
+            band_limited_level: 0,
+            band_limited_blend: 0,
+            frame: 0,
+            frame_blend: 0,
             freq_changed: false,
             random: SmallRng::seed_from_u64(23702372039u64),
             sample_rate,
-            lookup_table: [0u16; 3000],
-            target_freq: 440,
-            original_freq: 440,
-            current_freq: 440,
+            target_freq: 440 << FREQ_SHIFT,
+            current_freq: 440 << FREQ_SHIFT,
             freq_step: 0,
-            last_output: 0,
-            speed_count: 0,
-            speed: 1,
+            pitch_modulation: 0,
+            pitch_ratio: 1 << 16,
+            frequency_modulation: 0,
+            portamento_offset: 0,
+            portamento_step: 0,
+            portamento_ratio: 1 << 16,
+            cycle_started: false,
             wavetables,
         };
-        osc.calculate_lookup_table();
+        osc.calculate_phase_increment();
+        osc.select_frame(config.wavetable_position);
         osc
     }
 
-    fn calculate_lookup_table(&mut self) {
-        let sample_rate_expanded: u32 = self.sample_rate as u32 * 10_000;
-        let one_loop: u32 = sample_rate_expanded / self.current_freq as u32;
-        let one_step_loop: u32 = one_loop / 600;
-        let mut increase: u32 = 0;
-        let steps: u16 = self.sample_rate / self.current_freq;
+    /// Select the frame(s) of a multi-frame wavetable to read from for a wavetable position
+    fn select_frame(&mut self, position: u16) {
+        if self.config.soundbank_index == 255 {
+            return;
+        }
+        let frames = self.wavetables.frames(self.config.soundbank_index);
+        if frames <= 1 {
+            self.frame = 0;
+            self.frame_blend = 0;
+            return;
+        }
+        // Scale the position to (frames - 1) as 16.16 fixed point, so 65535 ends on the last frame
+        let scaled = (position as u64 * (frames as u64 - 1) * 0x1_0000 / u16::MAX as u64) as u32;
+        self.frame = (scaled >> 16) as u16;
+        self.frame_blend = scaled as u16;
+    }
+
+    /// Calculate how far the phase advances every sample to play the current frequency
+    fn calculate_phase_increment(&mut self) {
+        let freq = (self.current_freq as u64 * self.pitch_ratio as u64) >> 16;
+        let freq = (freq * self.portamento_ratio as u64) >> 16;
+        let freq = (freq as i64 + self.frequency_modulation as i64).max(0) as u64;
+        self.phase_increment = (freq * WAVETABLE_SIZE as u64 / self.sample_rate as u64) as u32;
+        self.select_band_limited_level();
+    }
+
+    /// Select the band-limited level for the current frequency. Level `n` holds the harmonics
+    /// that stay below Nyquist as long as the phase advances at most `2^n` samples per step.
+    fn select_band_limited_level(&mut self) {
+        let increment = self.phase_increment;
+        if increment <= 1 << 15 {
+            self.band_limited_level = 0;
+            self.band_limited_blend = 0;
+            return;
+        }
+        // Octave of the increment, rounded up: (2^(level - 1), 2^level]
+        let level = 32 - (increment - 1).leading_zeros() - 16;
+        let bottom = 1u64 << (15 + level);
+        self.band_limited_level = level as u8;
+        self.band_limited_blend =
+            (((increment as u64 - bottom) << 16) / bottom).min(u16::MAX as u64) as u16;
+    }
+
+    /// Read the wavetable at the current phase, from the band-limited level when available
+    #[inline]
+    fn read(&self) -> i16 {
+        let levels = self
+            .wavetables
+            .band_limited_levels(self.config.soundbank_index);
+        let level = self.band_limited_level.min(levels);
+        let sample = self.read_level(level);
+        if levels == 0 || !self.config.band_limit_crossfade || level == levels {
+            return sample;
+        }
+        // Fade towards the next (duller) level while the pitch rises through the octave
+        let next = self.read_level(level + 1);
+        linear_interpolation(sample, next, self.band_limited_blend)
+    }
 
-        for i in 0..steps {
-            self.lookup_table[i as usize] = (increase / one_step_loop) as u16;
-            increase += 10_000;
+    /// Read one band-limited level at the current phase, crossfading between two frames
+    /// when the wavetable position is in between them
+    #[inline]
+    fn read_level(&self, level: u8) -> i16 {
+        let frames = self
+            .wavetables
+            .get_band_limited_reference(self.config.soundbank_index, level);
+        let start = self.frame as usize * WAVETABLE_SIZE;
+        let frame = &frames[start..start + WAVETABLE_SIZE];
+        let sample = read_wavetable(frame, self.phase, self.config.interpolation);
+        if self.frame_blend == 0 {
+            return sample;
         }
+        let frame = &frames[start + WAVETABLE_SIZE..start + 2 * WAVETABLE_SIZE];
+        let next = read_wavetable(frame, self.phase, self.config.interpolation);
+        linear_interpolation(sample, next, self.frame_blend)
+    }
+
+    /// Move the pitch one step closer to the target frequency
+    fn portamento(&mut self) {
+        let step = self.portamento_step;
+        self.portamento_offset = if self.portamento_offset > 0 {
+            (self.portamento_offset - step).max(0)
+        } else {
+            (self.portamento_offset + step).min(0)
+        };
+        self.portamento_ratio = exp2(self.portamento_offset >> PORTAMENTO_SHIFT);
+        self.calculate_phase_increment();
+    }
+
+    /// Move the current frequency one step towards the target frequency
+    fn glide(&mut self) {
+        let diff = self.target_freq as i32 - self.current_freq as i32;
+        if diff.abs() <= self.freq_step.abs() {
+            self.current_freq = self.target_freq;
+            self.freq_changed = false;
+        } else {
+            self.current_freq = (self.current_freq as i32 + self.freq_step) as u32;
+        }
+        self.calculate_phase_increment();
     }
 
     /// Reload configuration
     pub fn reload(&mut self, config: WaveTableOscillatorConfig) {
         self.config = config;
+        self.select_frame(config.wavetable_position);
+    }
+
+    /// Move the wavetable position away from the configured position, used for modulation
+    ///
+    /// # Arguments
+    /// * `offset` - Offset added to the configured wavetable position, the result is clamped to 0..=65535
+    pub fn modulate_position(&mut self, offset: i32) {
+        let position = (self.config.wavetable_position as i32 + offset).clamp(0, u16::MAX as i32);
+        self.select_frame(position as u16);
+    }
+
+    /// Whether the last sample started a new cycle, the phase wrapped around in either direction
+    pub fn cycle_started(&self) -> bool {
+        self.cycle_started
     }
 
-    /// Manipulate frequency with percentage
-    pub fn manipulate_freq(&mut self, perc: u8, amount: u16) {
-        self.current_freq = self.original_freq + percentage(amount as i16, perc as i16) as u16;
-        self.freq_changed = true;
+    /// Hard sync, restart the cycle at the start of the wavetable
+    pub fn sync(&mut self) {
+        self.phase = 0;
+    }
+
+    /// Modulate the pitch on top of the current frequency, the frequency and glide are left untouched
+    ///
+    /// # Arguments
+    /// * `cents` - Pitch offset in cents (1200 cents is one octave up)
+    pub fn modulate_pitch(&mut self, cents: i32) {
+        if self.pitch_modulation != cents {
+            self.pitch_modulation = cents;
+            self.pitch_ratio = cents_to_ratio(cents);
+            self.calculate_phase_increment();
+        }
+    }
+
+    /// Modulate the frequency by a fixed amount of Hz on top of the pitch modulation
+    ///
+    /// # Arguments
+    /// * `offset` - Frequency offset in Hz as 16.16 fixed point, the frequency stops at 0 Hz
+    pub fn modulate_frequency(&mut self, offset: i32) {
+        if self.frequency_modulation != offset {
+            self.frequency_modulation = offset;
+            self.calculate_phase_increment();
+        }
     }
 
     /// Change frequency with optional glide
-    pub fn change_freq(&mut self, frequency: u16) {
+    ///
+    /// # Arguments
+    /// * `frequency` - The new frequency in Hz as 16.16 fixed point, see `data::frequencies::MIDI2FREQ`
+    pub fn change_freq(&mut self, frequency: u32) {
         if self.current_freq != frequency {
             self.target_freq = frequency;
 
-            if self.config.glide {
-                let diff = frequency as i16 - self.current_freq as i16;
-                self.freq_step = diff / self.config.glide_rate as i16;
+            if self.config.glide && self.config.portamento_time > 0 {
+                // Glide from the pitch sounding right now, which may be halfway a glide
+                let offset = self.portamento_offset as i64
+                    + ((log2(self.current_freq) as i64 - log2(frequency) as i64)
+                        << PORTAMENTO_SHIFT);
+                self.portamento_offset = offset.clamp(i32::MIN as i64 + 1, i32::MAX as i64) as i32;
+                let ticks =
+                    (self.config.portamento_time as i64 * self.sample_rate as i64 / 1000).max(1);
+                let distance = match self.config.portamento_mode {
+                    PortamentoMode::ConstantTime => self.portamento_offset.unsigned_abs() as i64,
+                    PortamentoMode::ConstantRate => 1 << 24,
+                };
+                self.portamento_step = (distance / ticks).clamp(1, i32::MAX as i64) as i32;
+                self.portamento_ratio = exp2(self.portamento_offset >> PORTAMENTO_SHIFT);
+                self.current_freq = frequency;
+                self.freq_changed = false;
+                self.calculate_phase_increment();
+            } else if self.config.glide && self.config.glide_rate > 0 {
+                let diff = frequency as i32 - self.current_freq as i32;
+                self.freq_step = diff / self.config.glide_rate as i32;
                 // Ensure non-zero step
                 if self.freq_step == 0 {
                     self.freq_step = diff / 2;
                 }
+                self.freq_changed = true;
             } else {
                 self.current_freq = frequency;
+                self.calculate_phase_increment();
             }
-
-            self.original_freq = frequency;
-            self.freq_changed = true;
         }
     }
+
+    /// Change frequency right away, ignoring the glide configuration and stopping a glide in progress
+    ///
+    /// # Arguments
+    /// * `frequency` - The new frequency in Hz as 16.16 fixed point, see `data::frequencies::MIDI2FREQ`
+    pub fn set_freq(&mut self, frequency: u32) {
+        self.target_freq = frequency;
+        self.current_freq = frequency;
+        self.freq_changed = false;
+        self.portamento_offset = 0;
+        self.portamento_ratio = 1 << 16;
+        self.calculate_phase_increment();
+    }
 }
@@ -0,0 +1,148 @@
+mod common;
+
+use std::{f64::consts::PI, fs, sync::Arc};
+
+use common::SAMPLE_RATE;
+use little_weirdo::synth::{
+    data::wavetables::{
+        BoxedWavetable, BoxedWavetables, Wavetables, BAND_LIMITED_LEVELS, WAVETABLE_SIZE,
+    },
+    wavetable_oscillator::{Interpolation, WaveTableOscillator, WaveTableOscillatorConfig},
+    Clockable,
+};
+
+/// wav1 is a sawtooth, rich in harmonics
+fn saw_bytes() -> Vec<u8> {
+    fs::read("examples/soundbank/soundbank_pure_elektro/src/wav1.raw").unwrap()
+}
+
+/// Magnitude of one harmonic of a wavetable cycle
+fn harmonic_magnitude(cycle: &[i16], harmonic: usize) -> f64 {
+    let (mut real, mut imaginary) = (0.0, 0.0);
+    for (n, sample) in cycle.iter().enumerate() {
+        let angle = 2.0 * PI * (harmonic * n) as f64 / cycle.len() as f64;
+        real += *sample as f64 * angle.cos();
+        imaginary += *sample as f64 * angle.sin();
+    }
+    2.0 * (real * real + imaginary * imaginary).sqrt() / cycle.len() as f64
+}
+
+/// Share of the energy of `samples` that is not a harmonic of `freq`
+fn aliasing_ratio(samples: &[i16], freq: usize) -> f64 {
+    let length = samples.len();
+    let bin_width = SAMPLE_RATE as usize / length;
+    let (mut harmonic_energy, mut alias_energy) = (0.0, 0.0);
+    for bin in 1..length / 2 {
+        let (mut real, mut imaginary) = (0.0, 0.0);
+        for (n, sample) in samples.iter().enumerate() {
+            let angle = 2.0 * PI * (bin * n) as f64 / length as f64;
+            real += *sample as f64 * angle.cos();
+            imaginary += *sample as f64 * angle.sin();
+        }
+        let energy = real * real + imaginary * imaginary;
+        if (bin * bin_width).is_multiple_of(freq) {
+            harmonic_energy += energy;
+        } else {
+            alias_energy += energy;
+        }
+    }
+    alias_energy / (harmonic_energy + alias_energy)
+}
+
+fn render_saw(wavetable: BoxedWavetable, freq: u32) -> Vec<i16> {
+    let mut wavetables = BoxedWavetables::new();
+    wavetables.add(wavetable);
+    let config = WaveTableOscillatorConfig {
+        interpolation: Interpolation::Linear,
+        ..Default::default()
+    };
+    let mut osc = WaveTableOscillator::new(config, SAMPLE_RATE, Arc::new(wavetables));
+    osc.change_freq(freq << 16);
+    let mut samples = vec![0i16; SAMPLE_RATE as usize / 10];
+    osc.clock_block(&mut samples);
+    samples
+}
+
+#[test]
+fn test_single_table_has_no_band_limited_levels() {
+    let mut wavetables = BoxedWavetables::new();
+    wavetables.add(BoxedWavetable::new(&saw_bytes()));
+    assert_eq!(wavetables.band_limited_levels(0), 0);
+    assert_eq!(
+        wavetables.get_band_limited_reference(0, 3),
+        wavetables.get_wavetable_reference(0)
+    );
+}
+
+#[test]
+fn test_band_limited_levels_drop_high_harmonics() {
+    let mut wavetables = BoxedWavetables::new();
+    wavetables.add(BoxedWavetable::new_band_limited(&saw_bytes()));
+    assert_eq!(wavetables.band_limited_levels(0), BAND_LIMITED_LEVELS);
+    assert_eq!(
+        wavetables.get_band_limited_reference(0, 0),
+        wavetables.get_wavetable_reference(0)
+    );
+
+    let original = wavetables.get_wavetable_reference(0);
+    for level in 1..=BAND_LIMITED_LEVELS {
+        let cycle = wavetables.get_band_limited_reference(0, level);
+        let max_harmonic = (WAVETABLE_SIZE / 2) >> level;
+        // The harmonics below the limit are kept (up to the level scaling that prevents clipping)
+        let kept = harmonic_magnitude(cycle, max_harmonic);
+        let expected = harmonic_magnitude(original, max_harmonic);
+        assert!(
+            kept > 0.8 * expected,
+            "level {}: {} vs {}",
+            level,
+            kept,
+            expected
+        );
+        // The harmonics above the limit are removed
+        assert!(harmonic_magnitude(cycle, max_harmonic + 1) < 2.0);
+        assert!(harmonic_magnitude(cycle, 2 * max_harmonic + 1) < 2.0);
+    }
+}
+
+#[test]
+fn test_band_limited_levels_keep_the_same_loudness() {
+    let mut wavetables = BoxedWavetables::new();
+    wavetables.add(BoxedWavetable::new_band_limited(&saw_bytes()));
+    let fundamental = harmonic_magnitude(wavetables.get_band_limited_reference(0, 0), 1);
+    for level in 1..=BAND_LIMITED_LEVELS {
+        // Every level is scaled by the same gain, the harmonics they share are equally loud
+        let cycle = wavetables.get_band_limited_reference(0, level);
+        let kept = harmonic_magnitude(cycle, 1);
+        assert!(
+            (kept - fundamental).abs() < 0.002 * fundamental,
+            "level {}: {} vs {}",
+            level,
+            kept,
+            fundamental
+        );
+    }
+    // The loudest level reaches full scale, the overshoot is not clipped
+    let peak = (0..=BAND_LIMITED_LEVELS)
+        .flat_map(|level| wavetables.get_band_limited_reference(0, level).iter())
+        .map(|sample| (*sample as i32).abs())
+        .max()
+        .unwrap();
+    assert!(peak >= i16::MAX as i32 - 1, "peak {}", peak);
+}
+
+#[test]
+fn test_band_limited_oscillator_removes_aliasing() {
+    // 2 kHz is an exact multiple of the 10 Hz analysis bins
+    let aliased = aliasing_ratio(&render_saw(BoxedWavetable::new(&saw_bytes()), 2_000), 2_000);
+    let band_limited = aliasing_ratio(
+        &render_saw(BoxedWavetable::new_band_limited(&saw_bytes()), 2_000),
+        2_000,
+    );
+    assert!(aliased > 0.01, "aliasing {}", aliased);
+    assert!(
+        band_limited < aliased / 10.0,
+        "{} vs {}",
+        band_limited,
+        aliased
+    );
+}
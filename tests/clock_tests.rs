@@ -0,0 +1,167 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, SAMPLE_RATE};
+use little_weirdo::synth::{
+    clock::{beat_length, DivisionKind, NoteDivision, TempoClock, DEFAULT_TEMPO},
+    lfo::{Lfo, LfoShape, WaveTableLoFreqOscillatorConfig},
+    patch::Patch,
+    Clockable, Synth,
+};
+
+/// A sine LFO synced to a note division
+fn synced_lfo(note: u8, kind: DivisionKind) -> Lfo {
+    Lfo::new(
+        0,
+        WaveTableLoFreqOscillatorConfig {
+            shape: LfoShape::Sine,
+            sync: Some(NoteDivision { note, kind }),
+            ..Default::default()
+        },
+        SAMPLE_RATE,
+        load_wavetables(),
+    )
+}
+
+fn clock(lfo: &mut Lfo, length: usize) -> Vec<i16> {
+    (0..length).map(|_| lfo.clock(None)).collect()
+}
+
+/// Samples between two MIDI clock ticks at a tempo in hundredths of a BPM
+fn tick_interval(tempo: u32) -> f64 {
+    SAMPLE_RATE as f64 * 60.0 * 100.0 / (tempo as f64 * 24.0)
+}
+
+#[test]
+fn test_note_divisions() {
+    let ticks = |note, kind| NoteDivision { note, kind }.ticks();
+    assert_eq!(ticks(4, DivisionKind::Straight), (96, 4));
+    assert_eq!(ticks(8, DivisionKind::Triplet), (64, 8));
+    assert_eq!(ticks(16, DivisionKind::Dotted), (144, 16));
+}
+
+#[test]
+fn test_lfo_rate_from_tempo() {
+    // At 120 BPM a quarter note lasts half a second
+    for (note, kind, frequency) in [
+        (4, DivisionKind::Straight, 2.0),
+        (1, DivisionKind::Straight, 0.5),
+        (8, DivisionKind::Triplet, 6.0),
+        (16, DivisionKind::Dotted, 16.0 / 3.0),
+    ] {
+        let mut lfo = synced_lfo(note, kind);
+        let output = clock(&mut lfo, 8 * SAMPLE_RATE as usize);
+        assert!(
+            (measure_frequency(&output) - frequency).abs() < 0.001,
+            "1/{} {:?}",
+            note,
+            kind
+        );
+    }
+
+    // A new tempo changes the rate
+    let mut lfo = synced_lfo(4, DivisionKind::Straight);
+    lfo.set_beat_length(beat_length(9_000, SAMPLE_RATE));
+    let output = clock(&mut lfo, 4 * SAMPLE_RATE as usize);
+    assert!((measure_frequency(&output) - 1.5).abs() < 0.001);
+}
+
+#[test]
+fn test_tempo_from_midi_clock() {
+    let mut clock = TempoClock::new(SAMPLE_RATE);
+    assert_eq!(clock.tempo(), DEFAULT_TEMPO);
+    clock.set_tempo(9_000);
+    assert_eq!(clock.tempo(), 9_000);
+
+    // Ticks arrive at block boundaries, the average over a beat still finds the tempo
+    let interval = tick_interval(12_850);
+    let mut time = 0.0;
+    let mut position = 0;
+    for _ in 0..100 {
+        time += interval;
+        let target = (time / 32.0) as usize * 32;
+        clock.advance(target - position);
+        position = target;
+        assert_eq!(clock.tick(), None);
+    }
+    // Off by at most a block over a beat
+    assert!(
+        (clock.tempo() as i32 - 12_850).abs() <= 25,
+        "{}",
+        clock.tempo()
+    );
+}
+
+#[test]
+fn test_start_stop() {
+    let mut clock = TempoClock::new(SAMPLE_RATE);
+    assert_eq!(clock.tick(), None);
+    clock.start();
+    assert_eq!(clock.tick(), Some(0));
+    assert_eq!(clock.tick(), Some(1));
+    clock.stop();
+    assert_eq!(clock.tick(), None);
+    clock.resume();
+    assert_eq!(clock.tick(), Some(2));
+    clock.start();
+    assert_eq!(clock.tick(), Some(0));
+}
+
+#[test]
+fn test_lfo_locks_to_the_ticks() {
+    let mut lfo = synced_lfo(4, DivisionKind::Straight);
+    // The LFO runs at a slightly wrong tempo, the ticks pull it back every beat
+    lfo.set_beat_length(beat_length(12_300, SAMPLE_RATE));
+    let interval = tick_interval(12_000);
+    let mut time = 0.0;
+    let mut position = 0;
+    for tick in 0..24 * 20 {
+        lfo.sync(tick);
+        if tick % 24 == 0 {
+            // Every beat starts at the start of the cycle
+            assert!(lfo.clock(None).abs() < 50);
+            position += 1;
+        }
+        time += interval;
+        let target = time as usize;
+        clock(&mut lfo, target - position);
+        position = target;
+    }
+}
+
+#[test]
+fn test_synth_follows_midi_clock() {
+    let mut patch: Patch =
+        serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap();
+    patch.lfos[0].sync = Some(NoteDivision {
+        note: 4,
+        kind: DivisionKind::Straight,
+    });
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.set_tempo(10_000);
+    assert_eq!(synth.tempo(), 10_000);
+
+    // Start and clock at 140 BPM in between rendered blocks
+    synth.handle_midi(&[0xFA]);
+    let interval = tick_interval(14_000);
+    let mut time = 0.0;
+    let mut position = 0;
+    let mut output = vec![[0i16; 2]; 64];
+    for _ in 0..48 {
+        synth.handle_midi(&[0xF8]);
+        time += interval;
+        while position + 64 <= time as usize {
+            synth.render(&mut output);
+            position += 64;
+        }
+    }
+    assert!(
+        (synth.tempo() as i32 - 14_000).abs() <= 20,
+        "{}",
+        synth.tempo()
+    );
+
+    // Setting a tempo takes over until the next ticks
+    synth.handle_midi(&[0xFC]);
+    synth.set_tempo(9_000);
+    assert_eq!(synth.tempo(), 9_000);
+}
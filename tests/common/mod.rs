@@ -0,0 +1,57 @@
+#![allow(dead_code)]
+
+use std::{fs, sync::Arc};
+
+use little_weirdo::synth::{
+    data::wavetables::{BoxedWavetable, BoxedWavetables},
+    patch::Patch,
+    Synth,
+};
+
+pub const SAMPLE_RATE: u16 = 44_100;
+
+/// Load the `soundbank_pure_elektro` wavetables used by the example patches
+pub fn load_wavetables() -> Arc<BoxedWavetables> {
+    let mut wt_on_heap = BoxedWavetables::new();
+    for id in 0..10 {
+        let filename = format!(
+            "examples/soundbank/soundbank_pure_elektro/src/wav{}.raw",
+            id
+        );
+        let contents = fs::read(filename).unwrap();
+        wt_on_heap.add(BoxedWavetable::new(&contents));
+    }
+    Arc::new(wt_on_heap)
+}
+
+/// Measure the frequency of a rendered sine by timing its rising zero crossings
+pub fn measure_frequency(samples: &[i16]) -> f64 {
+    let mut crossings: Vec<f64> = Vec::new();
+    for (n, pair) in samples.windows(2).enumerate() {
+        let (a, b) = (pair[0] as f64, pair[1] as f64);
+        if a < 0.0 && b >= 0.0 {
+            // Interpolate the exact moment of the crossing between both samples
+            crossings.push(n as f64 + a / (a - b));
+        }
+    }
+    let periods = (crossings.len() - 1) as f64;
+    let period = (crossings[crossings.len() - 1] - crossings[0]) / periods;
+    SAMPLE_RATE as f64 / period
+}
+
+/// The piano example patch
+pub fn piano() -> Patch {
+    serde_json::from_slice(include_bytes!("../../examples/patches/piano.json")).unwrap()
+}
+
+/// The bass example patch
+pub fn bass() -> Patch {
+    serde_json::from_slice(include_bytes!("../../examples/patches/bass.json")).unwrap()
+}
+
+/// Render frames with the block API
+pub fn render(synth: &mut Synth, length: usize) -> Vec<[i16; 2]> {
+    let mut output = vec![[0i16; 2]; length];
+    synth.render(&mut output);
+    output
+}
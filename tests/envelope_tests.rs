@@ -0,0 +1,268 @@
+mod common;
+
+use common::SAMPLE_RATE;
+use little_weirdo::synth::{
+    envelope::{EnvelopConfiguration, EnvelopeCurve, EnvelopeGenerator, ENVELOPE_FULL_SCALE},
+    Clockable,
+};
+
+/// Time ticks of a time in milliseconds, rounded to the nearest tick
+fn ticks(time: usize) -> usize {
+    (time * SAMPLE_RATE as usize + 500) / 1000
+}
+
+fn envelope(config: EnvelopConfiguration) -> EnvelopeGenerator {
+    EnvelopeGenerator::new(config, SAMPLE_RATE)
+}
+
+fn clock(envelope: &mut EnvelopeGenerator, length: usize) -> Vec<i16> {
+    (0..length).map(|_| envelope.clock(None)).collect()
+}
+
+/// An envelope with a 10 ms decay from full level to silence
+fn decay(curve: EnvelopeCurve) -> Vec<i16> {
+    let mut envelope = envelope(EnvelopConfiguration {
+        attack_time: 0,
+        decay_time: 10,
+        release_time: 0,
+        sustain_level: 0,
+        decay_curve: curve,
+        ..Default::default()
+    });
+    envelope.open_gate();
+    clock(&mut envelope, 1 + ticks(10) + 1)[1..].to_vec()
+}
+
+#[test]
+fn test_linear_stages() {
+    let mut envelope = envelope(EnvelopConfiguration {
+        attack_time: 10,
+        decay_time: 10,
+        release_time: 10,
+        sustain_level: 50,
+        ..Default::default()
+    });
+    let sustain = ENVELOPE_FULL_SCALE / 2;
+    envelope.open_gate();
+
+    // The attack rises in small steps instead of whole percents
+    let attack = clock(&mut envelope, ticks(10) + 1);
+    assert_eq!(attack[0], 0);
+    assert_eq!(attack[ticks(10)], ENVELOPE_FULL_SCALE);
+    assert!(attack.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(attack.windows(2).all(|pair| pair[1] - pair[0] < 100));
+
+    let decay = clock(&mut envelope, ticks(10));
+    let halfway = (ENVELOPE_FULL_SCALE as i32 + sustain as i32) / 2;
+    // Half of the decay falls between two ticks, allow the step of a single tick
+    let step = (ENVELOPE_FULL_SCALE - sustain) as i32 / ticks(10) as i32 + 1;
+    assert!((decay[ticks(5)] as i32 - halfway).abs() <= step);
+    assert_eq!(clock(&mut envelope, 100), vec![sustain; 100]);
+
+    envelope.close_gate();
+    let release = clock(&mut envelope, ticks(10) + 2);
+    assert_eq!(release[0], sustain);
+    assert!(
+        ((release[ticks(5)] - sustain / 2) as i32).abs() <= sustain as i32 / ticks(10) as i32 + 1
+    );
+    assert_eq!(release[ticks(10)], 0);
+    assert_eq!(envelope.level(), 0);
+}
+
+#[test]
+fn test_curved_decays() {
+    let linear = decay(EnvelopeCurve::Linear);
+    let exponential = decay(EnvelopeCurve::Exponential);
+    let logarithmic = decay(EnvelopeCurve::Logarithmic);
+
+    for curve in [&linear, &exponential, &logarithmic] {
+        assert_eq!(curve[0], ENVELOPE_FULL_SCALE);
+        assert_eq!(curve[curve.len() - 1], 0);
+        assert!(curve.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    // Half way the exponential decay dropped 3 of its 6 octaves, the logarithmic one is mirrored
+    let half = ticks(5);
+    let full = ENVELOPE_FULL_SCALE as f64;
+    let expected = (0.125 - 1.0 / 64.0) / (1.0 - 1.0 / 64.0);
+    assert!((exponential[half] as f64 / full - expected).abs() < 0.001);
+    assert!((logarithmic[half] as f64 / full - (1.0 - expected)).abs() < 0.001);
+    assert!(exponential[half] < linear[half] && linear[half] < logarithmic[half]);
+}
+
+#[test]
+fn test_curved_attack() {
+    let attack = |curve| {
+        let mut envelope = envelope(EnvelopConfiguration {
+            attack_time: 10,
+            decay_time: 0,
+            release_time: 0,
+            sustain_level: 100,
+            attack_curve: curve,
+            ..Default::default()
+        });
+        envelope.open_gate();
+        clock(&mut envelope, ticks(10) + 1)
+    };
+    let exponential = attack(EnvelopeCurve::Exponential);
+    let logarithmic = attack(EnvelopeCurve::Logarithmic);
+    // Both reach the top in time, the exponential attack rises fast at the start
+    assert_eq!(exponential[ticks(10)], ENVELOPE_FULL_SCALE);
+    assert_eq!(logarithmic[ticks(10)], ENVELOPE_FULL_SCALE);
+    assert!(exponential[ticks(1)] > ENVELOPE_FULL_SCALE / 4);
+    assert!(logarithmic[ticks(1)] < ENVELOPE_FULL_SCALE / 50);
+}
+
+#[test]
+fn test_retrigger_continues_from_the_current_level() {
+    let mut envelope = envelope(EnvelopConfiguration {
+        attack_time: 10,
+        decay_time: 0,
+        release_time: 10,
+        sustain_level: 100,
+        release_curve: EnvelopeCurve::Exponential,
+        ..Default::default()
+    });
+    envelope.open_gate();
+    clock(&mut envelope, ticks(20));
+    envelope.close_gate();
+    let level = *clock(&mut envelope, ticks(2)).last().unwrap();
+
+    // The attack starts where the release was and only takes the remaining part of its time
+    envelope.open_gate();
+    let attack = clock(&mut envelope, ticks(10));
+    assert!((attack[0] - level).abs() < 100);
+    let remaining =
+        ticks(10) * (ENVELOPE_FULL_SCALE - level) as usize / ENVELOPE_FULL_SCALE as usize;
+    assert_eq!(attack[remaining], ENVELOPE_FULL_SCALE);
+    assert!(attack[remaining - 1] < ENVELOPE_FULL_SCALE);
+}
+
+#[test]
+fn test_delay_and_hold() {
+    let mut envelope = envelope(EnvelopConfiguration {
+        attack_time: 1,
+        decay_time: 1,
+        release_time: 0,
+        sustain_level: 0,
+        delay_time: 5,
+        hold_time: 5,
+        ..Default::default()
+    });
+    envelope.open_gate();
+    let output = clock(&mut envelope, ticks(20));
+
+    // Silent during the delay, at full level during the hold
+    let attack_start = output.iter().position(|level| *level > 0).unwrap();
+    assert_eq!(attack_start, ticks(5) + 2);
+    let hold_start = output
+        .iter()
+        .position(|level| *level == ENVELOPE_FULL_SCALE)
+        .unwrap();
+    assert_eq!(hold_start, attack_start + ticks(1) - 1);
+    let hold = output[hold_start..]
+        .iter()
+        .take_while(|level| **level == ENVELOPE_FULL_SCALE)
+        .count();
+    // The hold time plus the last tick of the attack and the first ticks of the decay
+    assert_eq!(hold, ticks(5) + 3);
+    assert_eq!(output[output.len() - 1], 0);
+}
+
+#[test]
+fn test_looping_envelope() {
+    let mut envelope = envelope(EnvelopConfiguration {
+        attack_time: 5,
+        decay_time: 5,
+        release_time: 5,
+        sustain_level: 0,
+        looping: true,
+        ..Default::default()
+    });
+    envelope.open_gate();
+    let output = clock(&mut envelope, ticks(100));
+
+    // Every cycle takes the attack and decay time, plus a tick at the top and the bottom
+    let peaks: Vec<usize> = (1..output.len())
+        .filter(|n| output[*n] == ENVELOPE_FULL_SCALE && output[n - 1] < ENVELOPE_FULL_SCALE)
+        .collect();
+    assert_eq!(peaks.len(), 10);
+    assert!(peaks
+        .windows(2)
+        .all(|pair| pair[1] - pair[0] == 2 * ticks(5) + 2));
+
+    // Closing the gate releases the envelope and ends the loop
+    envelope.close_gate();
+    clock(&mut envelope, ticks(10));
+    assert!(clock(&mut envelope, ticks(50))
+        .iter()
+        .all(|level| *level == 0));
+}
+
+#[test]
+fn test_configuration_without_extended_stages() {
+    let config: EnvelopConfiguration = serde_json::from_str(
+        r#"{ "attack_time": 5, "decay_time": 10, "release_time": 20, "sustain_level": 80 }"#,
+    )
+    .unwrap();
+    assert_eq!(config.delay_time, 0);
+    assert_eq!(config.hold_time, 0);
+    assert!(!config.looping);
+    assert_eq!(config.attack_curve, EnvelopeCurve::Linear);
+}
+
+#[test]
+fn test_stage_durations_across_sample_rates() {
+    for sample_rate in [22_050u16, 32_000, 44_100, 48_000] {
+        let mut envelope = EnvelopeGenerator::new(
+            EnvelopConfiguration {
+                attack_time: 3,
+                decay_time: 0,
+                release_time: 250,
+                sustain_level: 100,
+                delay_time: 7,
+                ..Default::default()
+            },
+            sample_rate,
+        );
+        // Ticks of a time in milliseconds at this sample rate, rounded to the nearest tick
+        let ticks = |time: usize| (time * sample_rate as usize + 500) / 1000;
+        envelope.open_gate();
+        let output = clock(&mut envelope, ticks(20));
+        // The delay ends with a tick at its end level and the attack starts at zero
+        let attack_start = output.iter().position(|level| *level > 0).unwrap() - 1;
+        assert_eq!(attack_start, ticks(7) + 1, "delay at {} Hz", sample_rate);
+        let top = output
+            .iter()
+            .position(|level| *level == ENVELOPE_FULL_SCALE)
+            .unwrap();
+        assert_eq!(top - attack_start, ticks(3), "attack at {} Hz", sample_rate);
+
+        envelope.close_gate();
+        let release = clock(&mut envelope, ticks(300));
+        let end = release.iter().position(|level| *level == 0).unwrap();
+        assert_eq!(end, ticks(250), "release at {} Hz", sample_rate);
+    }
+}
+
+#[test]
+fn test_release_longer_than_32_seconds() {
+    let sample_rate = 48_000;
+    let mut envelope = EnvelopeGenerator::new(
+        EnvelopConfiguration {
+            attack_time: 0,
+            decay_time: 0,
+            release_time: 60_000,
+            sustain_level: 100,
+            ..Default::default()
+        },
+        sample_rate,
+    );
+    envelope.open_gate();
+    clock(&mut envelope, 10);
+    envelope.close_gate();
+    let release = clock(&mut envelope, 60 * sample_rate as usize + 1);
+    assert!((release[30 * sample_rate as usize] - ENVELOPE_FULL_SCALE / 2).abs() <= 1);
+    assert!(release[59 * sample_rate as usize] > ENVELOPE_FULL_SCALE / 100);
+    assert_eq!(release[60 * sample_rate as usize], 0);
+}
@@ -0,0 +1,227 @@
+mod common;
+
+use common::{load_wavetables, SAMPLE_RATE};
+use little_weirdo::synth::{
+    effects::{
+        filter::{Filter, FilterConfig, KindOfFilter},
+        Effect,
+    },
+    envelope::EnvelopConfiguration,
+    patch::Patch,
+    Synth,
+};
+
+fn bass() -> Patch {
+    let mut patch = common::bass();
+    patch.filter_config.per_voice = true;
+    patch
+}
+
+/// Energy of the left channel while holding a note
+fn rms(patch: &Patch, note: u8) -> f64 {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    synth.note_on(note, 100);
+    let mut output = vec![[0i16; 2]; 10_000];
+    synth.render(&mut output);
+    let sum: f64 = output
+        .iter()
+        .map(|frame| frame[0] as f64 * frame[0] as f64)
+        .sum();
+    (sum / output.len() as f64).sqrt()
+}
+
+#[test]
+fn test_disabled_per_voice_filter() {
+    let mut patch = bass();
+    patch.filter_config.enabled = false;
+    let mut global = patch;
+    global.filter_config.per_voice = false;
+
+    let mut per_voice = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    let mut unfiltered = Synth::new(SAMPLE_RATE, &global, load_wavetables());
+    per_voice.note_on(48, 100);
+    unfiltered.note_on(48, 100);
+    for _ in 0..5_000 {
+        assert_eq!(per_voice.clock_and_output(), unfiltered.clock_and_output());
+    }
+}
+
+#[test]
+fn test_per_voice_filter_key_tracking() {
+    let fixed = bass();
+    let mut tracking = bass();
+    tracking.filter_config.key_tracking = 100;
+
+    // Above C4 the cutoff opens up, below C4 it closes
+    assert!(rms(&tracking, 84) > 1.5 * rms(&fixed, 84));
+    assert!(rms(&tracking, 36) < rms(&fixed, 36) / 1.5);
+    // At C4 key tracking has no effect
+    assert_eq!(rms(&tracking, 60), rms(&fixed, 60));
+}
+
+#[test]
+fn test_per_voice_filter_envelope_amount() {
+    let closed = bass();
+    let mut opened = bass();
+    opened.filter_config.envelope_amount = 50;
+    assert!(rms(&opened, 48) > 1.2 * rms(&closed, 48));
+
+    let mut lowered = bass();
+    lowered.filter_config.cutoff_frequency = 20_000;
+    lowered.filter_config.envelope_amount = -25;
+    let mut unlowered = lowered;
+    unlowered.filter_config.envelope_amount = 0;
+    assert!(rms(&lowered, 48) < rms(&unlowered, 48));
+}
+
+/// Energy of the left channel right after the note starts
+fn rms_attack(patch: &Patch) -> f64 {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    synth.note_on(48, 100);
+    let mut output = vec![[0i16; 2]; 2_000];
+    synth.render(&mut output);
+    let sum: f64 = output
+        .iter()
+        .map(|frame| frame[0] as f64 * frame[0] as f64)
+        .sum();
+    (sum / output.len() as f64).sqrt()
+}
+
+fn plucked(mut patch: Patch) -> Patch {
+    patch.filter_envelop = EnvelopConfiguration {
+        attack_time: 0,
+        decay_time: 100,
+        release_time: 0,
+        sustain_level: 0,
+        ..Default::default()
+    };
+    patch.filter_config.filter_envelope_depth = 50;
+    patch
+}
+
+#[test]
+fn test_filter_envelope_sweeps_output_filter() {
+    let mut patch = bass();
+    patch.filter_config.per_voice = false;
+    assert!(rms_attack(&plucked(patch)) > 1.2 * rms_attack(&patch));
+}
+
+#[test]
+fn test_filter_envelope_sweeps_voice_filters() {
+    let patch = bass();
+    assert!(rms_attack(&plucked(patch)) > 1.2 * rms_attack(&patch));
+
+    // A negative depth closes the filter
+    let mut closing = plucked(patch);
+    closing.filter_config.cutoff_frequency = 40_000;
+    closing.filter_config.filter_envelope_depth = -50;
+    let mut open = closing;
+    open.filter_config.filter_envelope_depth = 0;
+    assert!(rms_attack(&closing) < rms_attack(&open));
+}
+
+/// The filter as it was before the buffers got fractional bits, to compare against
+struct IntegerFilter {
+    config: FilterConfig,
+    buf0: i64,
+    buf1: i64,
+}
+
+impl IntegerFilter {
+    fn new(config: FilterConfig) -> Self {
+        Self {
+            config,
+            buf0: 0,
+            buf1: 0,
+        }
+    }
+
+    fn clock(&mut self, sample: i16) -> i16 {
+        let resonance = self.config.resonance as u32;
+        let cutoff = self.config.cutoff_frequency;
+        let feedback = resonance + ((resonance * (u16::MAX - cutoff) as u32) >> 16);
+        let highpass = sample as i64 - self.buf0;
+        let bandpass = (self.buf0 - self.buf1) as i32;
+        let feedback_on_bandpass = (feedback as i64 * bandpass as i64) >> 16;
+        self.buf0 += ((highpass + feedback_on_bandpass) * cutoff as i64) >> 16;
+        self.buf1 += ((bandpass * cutoff as i32) >> 16) as i64;
+        match self.config.kind_of_filter {
+            KindOfFilter::Low => self.buf1 as i16,
+            KindOfFilter::High => highpass as i16,
+            KindOfFilter::Band => bandpass as i16,
+            KindOfFilter::Notch => (sample as i64 - self.buf0 + self.buf1) as i16,
+        }
+    }
+}
+
+fn filter_config(kind_of_filter: KindOfFilter, cutoff_frequency: u16) -> FilterConfig {
+    let mut config = bass().filter_config;
+    config.enabled = true;
+    config.kind_of_filter = kind_of_filter;
+    config.cutoff_frequency = cutoff_frequency;
+    config.resonance = 20_000;
+    config
+}
+
+/// A square wave of 220 Hz
+fn square(index: usize) -> i16 {
+    if index % 200 < 100 {
+        10_000
+    } else {
+        -10_000
+    }
+}
+
+#[test]
+fn test_filter_matches_integer_filter() {
+    for kind_of_filter in [
+        KindOfFilter::Low,
+        KindOfFilter::High,
+        KindOfFilter::Band,
+        KindOfFilter::Notch,
+    ] {
+        for cutoff_frequency in [10_000, 30_000, 60_000] {
+            let config = filter_config(kind_of_filter, cutoff_frequency);
+            let mut filter = Filter::new(config);
+            let mut reference = IntegerFilter::new(config);
+            for index in 0..5_000 {
+                let sample = square(index);
+                let difference = filter.clock(sample) as i32 - reference.clock(sample) as i32;
+                // Only the rounding of the buffers differs
+                assert!(difference.abs() <= 16, "{index}: {difference}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_filter_decays_to_silence() {
+    let config = filter_config(KindOfFilter::Low, 2_000);
+    let mut filter = Filter::new(config);
+    let mut reference = IntegerFilter::new(config);
+    for index in 0..5_000 {
+        filter.clock(square(index));
+        reference.clock(square(index));
+    }
+    for _ in 0..20_000 {
+        filter.clock(0);
+        reference.clock(0);
+    }
+    // The integer buffers get stuck on a small offset, the fractional bits let it die out
+    assert_ne!(reference.clock(0), 0);
+    assert_eq!(filter.clock(0), 0);
+}
+
+#[test]
+fn test_filter_settles_on_dc() {
+    let config = filter_config(KindOfFilter::Low, 500);
+    let mut filter = Filter::new(config);
+    let mut reference = IntegerFilter::new(config);
+    for _ in 0..100_000 {
+        filter.clock(1_000);
+        reference.clock(1_000);
+    }
+    // Steps smaller than one sample value get lost in the integer buffers
+    assert!((reference.clock(1_000) - 1_000).abs() > 10);
+    assert!((filter.clock(1_000) - 1_000).abs() <= 1);
+}
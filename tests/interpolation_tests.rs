@@ -0,0 +1,67 @@
+mod common;
+
+use std::f64::consts::PI;
+
+use common::{load_wavetables, SAMPLE_RATE};
+use little_weirdo::synth::{
+    data::{frequencies::MIDI2FREQ, wavetables::WAVETABLE_SIZE},
+    math::{hermite_interpolation, linear_interpolation},
+    wavetable_oscillator::{Interpolation, WaveTableOscillator, WaveTableOscillatorConfig},
+    Clockable,
+};
+
+/// Render a sine (wav0) at A1 and return the RMS error against an ideal sine
+fn rms_error(interpolation: Interpolation) -> f64 {
+    let config = WaveTableOscillatorConfig {
+        interpolation,
+        ..Default::default()
+    };
+    let mut osc = WaveTableOscillator::new(config, SAMPLE_RATE, load_wavetables());
+    osc.change_freq(MIDI2FREQ[33]);
+    let mut samples = vec![0i16; 10_000];
+    osc.clock_block(&mut samples);
+
+    // Follow the same fixed point phase as the oscillator, so only the read is compared
+    let increment = (MIDI2FREQ[33] as u64 * WAVETABLE_SIZE as u64 / SAMPLE_RATE as u64) as f64;
+    let mut total = 0.0;
+    for (n, sample) in samples.iter().enumerate() {
+        let phase = (n as f64 * increment / 65536.0) % WAVETABLE_SIZE as f64;
+        let ideal = 32767.0 * (2.0 * PI * phase / WAVETABLE_SIZE as f64).sin();
+        total += (*sample as f64 - ideal).powi(2);
+    }
+    (total / samples.len() as f64).sqrt()
+}
+
+#[test]
+fn test_linear_interpolation() {
+    assert_eq!(linear_interpolation(100, 200, 0), 100);
+    assert_eq!(linear_interpolation(100, 200, 32768), 150);
+    assert_eq!(linear_interpolation(200, -200, 49152), -100);
+}
+
+#[test]
+fn test_hermite_interpolation() {
+    // Hits the sample exactly at the start of the segment
+    assert_eq!(hermite_interpolation(-500, 1000, 3000, 100, 0), 1000);
+    // Follows a straight line like linear interpolation
+    assert_eq!(hermite_interpolation(0, 100, 200, 300, 32768), 150);
+    // Stays within the i16 range when overshooting
+    assert_eq!(
+        hermite_interpolation(-32768, 32767, 32767, -32768, 32768),
+        i16::MAX
+    );
+}
+
+#[test]
+fn test_interpolation_reduces_error() {
+    let none = rms_error(Interpolation::None);
+    let linear = rms_error(Interpolation::Linear);
+    let hermite = rms_error(Interpolation::Hermite);
+    assert!(linear < none / 10.0, "linear {} vs none {}", linear, none);
+    assert!(
+        hermite < none / 10.0,
+        "hermite {} vs none {}",
+        hermite,
+        none
+    );
+}
@@ -0,0 +1,309 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, render, SAMPLE_RATE};
+use little_weirdo::synth::{
+    lfo::{Lfo, LfoMode, LfoShape, WaveTableLoFreqOscillatorConfig},
+    math,
+    patch::{Patch, SynthMode},
+    router::{ModulationDestination, ModulationSource},
+    Clockable, Synth,
+};
+
+/// An LFO at 1 Hz
+fn lfo(shape: LfoShape, mode: LfoMode) -> Lfo {
+    Lfo::new(
+        0,
+        WaveTableLoFreqOscillatorConfig {
+            rate: 100,
+            shape,
+            mode,
+            ..Default::default()
+        },
+        SAMPLE_RATE,
+        load_wavetables(),
+    )
+}
+
+fn clock(lfo: &mut Lfo, length: usize) -> Vec<i16> {
+    (0..length).map(|_| lfo.clock(None)).collect()
+}
+
+/// Samples in one cycle of a 1 Hz LFO
+const CYCLE: usize = SAMPLE_RATE as usize;
+
+#[test]
+fn test_sine() {
+    assert_eq!(math::sine(0), 0);
+    assert_eq!(math::sine(1 << 30), i16::MAX);
+    assert_eq!(math::sine(1 << 31), 0);
+    assert_eq!(math::sine(3 << 30), -i16::MAX);
+    for n in 0..1000u32 {
+        let phase = n * (u32::MAX / 1000);
+        let expected = (phase as f64 / 4294967296.0 * std::f64::consts::TAU).sin() * 32767.0;
+        assert!((math::sine(phase) as f64 - expected).abs() < 16.0);
+    }
+}
+
+#[test]
+fn test_rate_in_centihertz() {
+    for rate in [10, 250, 2_000] {
+        let mut lfo = Lfo::new(
+            0,
+            WaveTableLoFreqOscillatorConfig {
+                rate,
+                shape: LfoShape::Sine,
+                ..Default::default()
+            },
+            SAMPLE_RATE,
+            load_wavetables(),
+        );
+        // Four cycles
+        let output = clock(&mut lfo, 4 * 100 * CYCLE / rate as usize);
+        let frequency = measure_frequency(&output) * 100.0;
+        assert!((frequency - rate as f64).abs() < 0.01, "rate {}", frequency);
+    }
+}
+
+#[test]
+fn test_legacy_rate() {
+    // Patches without a rate keep playing at 110 / time Hz
+    let config: WaveTableLoFreqOscillatorConfig =
+        serde_json::from_str(r#"{ "soundbank_index": 0, "time": 110 }"#).unwrap();
+    assert_eq!(config.rate, 0);
+    assert_eq!(config.shape, LfoShape::Wavetable);
+    assert_eq!(config.mode, LfoMode::Free);
+    let mut wavetable = Lfo::new(0, config, SAMPLE_RATE, load_wavetables());
+    let mut sine = lfo(LfoShape::Sine, LfoMode::Free);
+    // The first wavetable is a sine as well
+    let wavetable = clock(&mut wavetable, 4 * CYCLE);
+    let sine = clock(&mut sine, 4 * CYCLE);
+    assert!((measure_frequency(&wavetable) - measure_frequency(&sine)).abs() < 0.001);
+}
+
+#[test]
+fn test_shapes() {
+    let quarters = |shape| {
+        let output = clock(&mut lfo(shape, LfoMode::Free), CYCLE);
+        [0, 1, 2, 3].map(|quarter| output[quarter * CYCLE / 4] as i32)
+    };
+    let max = i16::MAX as i32;
+    let assert_close = |actual: [i32; 4], expected: [i32; 4]| {
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual - expected).abs() <= 16,
+                "{:?} {:?}",
+                actual,
+                expected
+            );
+        }
+    };
+    assert_close(quarters(LfoShape::Sine), [0, max, 0, -max]);
+    assert_close(quarters(LfoShape::Triangle), [0, max, 0, -max]);
+    assert_close(quarters(LfoShape::SawUp), [-max, -max / 2, 0, max / 2]);
+    assert_close(quarters(LfoShape::SawDown), [max, max / 2, 0, -max / 2]);
+    let square = clock(&mut lfo(LfoShape::Square, LfoMode::Free), CYCLE);
+    assert!(square[..CYCLE / 2 - 1]
+        .iter()
+        .all(|sample| *sample == i16::MAX));
+    assert!(square[CYCLE / 2 + 1..]
+        .iter()
+        .all(|sample| *sample == -i16::MAX));
+}
+
+#[test]
+fn test_random_shapes() {
+    let sample_and_hold = clock(&mut lfo(LfoShape::SampleAndHold, LfoMode::Free), 4 * CYCLE);
+    // A single level every cycle, a new level every next cycle
+    let levels: Vec<&[i16]> = sample_and_hold.chunks(CYCLE).collect();
+    for level in levels.iter() {
+        assert!(level[1..].iter().all(|sample| *sample == level[1]));
+    }
+    assert!(levels.windows(2).all(|pair| pair[0][1] != pair[1][1]));
+
+    // The smooth random shape glides, it never jumps
+    let smooth = clock(&mut lfo(LfoShape::SmoothRandom, LfoMode::Free), 4 * CYCLE);
+    assert!(smooth
+        .windows(2)
+        .all(|pair| (pair[0] as i32 - pair[1] as i32).abs() <= 2));
+    assert!(smooth.iter().any(|sample| *sample != smooth[0]));
+}
+
+#[test]
+fn test_lfos_draw_their_own_random_levels() {
+    let random_lfo = |index| {
+        let config = WaveTableLoFreqOscillatorConfig {
+            rate: 100,
+            shape: LfoShape::SampleAndHold,
+            ..Default::default()
+        };
+        clock(
+            &mut Lfo::new(index, config, SAMPLE_RATE, load_wavetables()),
+            4 * CYCLE,
+        )
+    };
+    // The same LFO plays the same levels every time, two LFOs do not move together
+    assert_eq!(random_lfo(0), random_lfo(0));
+    let first = random_lfo(0);
+    let second = random_lfo(1);
+    assert!((0..4).all(|cycle| first[cycle * CYCLE + 1] != second[cycle * CYCLE + 1]));
+}
+
+#[test]
+fn test_start_phase() {
+    let mut lfo = Lfo::new(
+        0,
+        WaveTableLoFreqOscillatorConfig {
+            rate: 100,
+            shape: LfoShape::Sine,
+            start_phase: 90,
+            ..Default::default()
+        },
+        SAMPLE_RATE,
+        load_wavetables(),
+    );
+    assert_eq!(lfo.clock(None), i16::MAX);
+}
+
+#[test]
+fn test_key_sync_and_free_run() {
+    let mut key_sync = lfo(LfoShape::SawUp, LfoMode::KeySync);
+    let start = clock(&mut key_sync, CYCLE / 3);
+    key_sync.trigger(false);
+    assert_eq!(clock(&mut key_sync, CYCLE / 3), start);
+
+    // A note leaves the phase of a free running LFO alone
+    let mut free = lfo(LfoShape::SawUp, LfoMode::Free);
+    let mut untouched = lfo(LfoShape::SawUp, LfoMode::Free);
+    clock(&mut free, CYCLE / 3);
+    clock(&mut untouched, CYCLE / 3);
+    free.trigger(false);
+    assert_eq!(clock(&mut free, CYCLE), clock(&mut untouched, CYCLE));
+}
+
+#[test]
+fn test_one_shot() {
+    let mut one_shot = lfo(LfoShape::SawUp, LfoMode::OneShot);
+    let cycle = clock(&mut one_shot, CYCLE);
+    // The LFO stops at the end of its cycle
+    let end = clock(&mut one_shot, CYCLE);
+    assert!(end.iter().all(|sample| *sample == i16::MAX));
+    assert!(cycle[CYCLE - 1] > i16::MAX - 100);
+
+    // A note plays the cycle again
+    one_shot.trigger(false);
+    assert_eq!(clock(&mut one_shot, CYCLE), cycle);
+}
+
+#[test]
+fn test_fade_in() {
+    let mut lfo = Lfo::new(
+        0,
+        WaveTableLoFreqOscillatorConfig {
+            rate: 1,
+            shape: LfoShape::Square,
+            fade_in_time: 100,
+            ..Default::default()
+        },
+        SAMPLE_RATE,
+        load_wavetables(),
+    );
+    let fade = CYCLE / 10;
+    let output = clock(&mut lfo, 2 * fade);
+    assert_eq!(output[0], 0);
+    assert!((output[fade / 2] as i32 - i16::MAX as i32 / 2).abs() <= 1);
+    assert!(output[fade..].iter().all(|sample| *sample == i16::MAX));
+
+    // A note fades the LFO in again when it is the only note
+    lfo.trigger(false);
+    assert_eq!(clock(&mut lfo, 2 * fade), output);
+    // Another note keeps the free running LFO at its level
+    lfo.trigger(true);
+    assert!(clock(&mut lfo, 2 * fade)
+        .iter()
+        .all(|sample| *sample == i16::MAX));
+}
+
+#[test]
+fn test_fade_in_of_a_free_lfo_with_held_notes() {
+    let mut patch: Patch =
+        serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap();
+    patch.synth_config.mode = SynthMode::BiPoly;
+    patch.synth_config.mod_wheel_to_lfo_depth = 0;
+    patch.filter_config.enabled = false;
+    patch.lfos[0].rate = 1;
+    patch.lfos[0].shape = LfoShape::Square;
+    patch.lfos[0].fade_in_time = 100;
+    patch.lfos[0].mode = LfoMode::Free;
+    // The first note on the left with the tremolo, the second note on the right
+    patch.mixer_config.gain_voices = [80, 0, 0, 0, 80, 0, 0, 0];
+    patch.mixer_config.pan_voices = [-100, 0, 0, 0, 100, 0, 0, 0];
+    patch.envelops[4] = patch.envelops[0];
+    let fade = CYCLE / 10;
+    let peak = |output: &[[i16; 2]]| output.iter().map(|frame| frame[0].abs()).max().unwrap();
+
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(60, 100);
+    render(&mut synth, 2 * fade);
+    let held = peak(&render(&mut synth, fade / 10));
+    // The held note keeps its level when a second note starts
+    synth.note_on(64, 100);
+    let second = render(&mut synth, fade);
+    assert!(second
+        .chunks(fade / 10)
+        .all(|chunk| (peak(chunk) as i32 - held as i32).abs() < held as i32 / 20));
+
+    // After every note has ended the next note fades in again
+    synth.note_off(60);
+    synth.note_off(64);
+    render(&mut synth, CYCLE);
+    synth.note_on(60, 100);
+    let first = render(&mut synth, fade);
+    // The tremolo fades in from the centre of the LFO, half way down
+    assert!((peak(&first[..fade / 10]) as i32) < held as i32 * 3 / 4);
+}
+
+#[test]
+fn test_notes_restart_a_key_synced_lfo() {
+    let mut patch: Patch =
+        serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap();
+    patch.lfos[0].rate = 300;
+    patch.lfos[0].shape = LfoShape::Sine;
+    patch.lfos[0].mode = LfoMode::KeySync;
+    // The full tremolo on the first voice only
+    patch.synth_config.mod_wheel_to_lfo_depth = 0;
+    patch.mixer_config.gain_voices[1] = 0;
+    patch.filter_config.enabled = false;
+    assert_eq!(
+        patch.routering_config.modulation_matrix[0].source,
+        ModulationSource::Lfo(0)
+    );
+    assert_eq!(
+        patch.routering_config.modulation_matrix[0].destination,
+        ModulationDestination::VoiceAmplitude(0)
+    );
+
+    let tremolo = |patch: &Patch, wait: usize| {
+        let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+        let mut output = vec![[0i16; 2]; wait];
+        synth.render(&mut output);
+        synth.note_on(60, 100);
+        let mut output = vec![[0i16; 2]; CYCLE / 3];
+        synth.render(&mut output);
+        // Peak level every 10 ms
+        output
+            .chunks(CYCLE / 100)
+            .map(|chunk| chunk.iter().map(|frame| frame[0].abs()).max().unwrap() as i32)
+            .collect::<Vec<i32>>()
+    };
+    let differs = |a: Vec<i32>, b: Vec<i32>| {
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| (a - b).abs())
+            .max()
+            .unwrap()
+    };
+    // The tremolo starts with the note, however long the LFO was running before
+    assert!(differs(tremolo(&patch, 1_000), tremolo(&patch, 7_777)) < 100);
+    patch.lfos[0].mode = LfoMode::Free;
+    assert!(differs(tremolo(&patch, 1_000), tremolo(&patch, 7_777)) > 1_000);
+}
@@ -0,0 +1,210 @@
+mod common;
+
+use common::{load_wavetables, piano, render, SAMPLE_RATE};
+use little_weirdo::synth::{
+    midi::{MidiChannel, MidiMessage, MidiParser},
+    router::{ModulationDestination, ModulationSlot, ModulationSource, ALL_VOICES},
+    Synth,
+};
+
+fn parse(bytes: &[u8]) -> Vec<MidiMessage> {
+    let mut parser = MidiParser::new();
+    bytes
+        .iter()
+        .filter_map(|byte| parser.parse(*byte))
+        .collect()
+}
+
+fn synth() -> Synth {
+    Synth::new(SAMPLE_RATE, &piano(), load_wavetables())
+}
+
+#[test]
+fn test_parse_running_status() {
+    assert_eq!(
+        parse(&[0x91, 60, 100, 64, 90, 60, 0]),
+        vec![
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 100
+            },
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 64,
+                velocity: 90
+            },
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 0
+            },
+        ]
+    );
+    assert_eq!(
+        parse(&[0xC0, 5, 6]),
+        vec![
+            MidiMessage::ProgramChange {
+                channel: 0,
+                program: 5
+            },
+            MidiMessage::ProgramChange {
+                channel: 0,
+                program: 6
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_real_time_and_system_exclusive() {
+    // A timing clock in the middle of a note on, then system exclusive data that must be skipped
+    assert_eq!(
+        parse(&[0x80, 60, 0xF8, 10, 0xF0, 0x43, 0x10, 0xF7, 0x7F]),
+        vec![
+            MidiMessage::TimingClock,
+            MidiMessage::NoteOff {
+                channel: 0,
+                note: 60,
+                velocity: 10
+            },
+        ]
+    );
+    assert_eq!(
+        parse(&[0xFA, 0xFB, 0xFC, 0xFE]),
+        vec![MidiMessage::Start, MidiMessage::Continue, MidiMessage::Stop]
+    );
+}
+
+#[test]
+fn test_parse_pitch_bend() {
+    assert_eq!(
+        parse(&[0xE2, 0x00, 0x40, 0x7F, 0x7F, 0x00, 0x00]),
+        vec![
+            MidiMessage::PitchBend {
+                channel: 2,
+                value: 0
+            },
+            MidiMessage::PitchBend {
+                channel: 2,
+                value: 8191
+            },
+            MidiMessage::PitchBend {
+                channel: 2,
+                value: -8192
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_handle_midi_notes() {
+    let mut direct = synth();
+    let mut midi = synth();
+    direct.note_on(60, 100);
+    // Message split over two calls
+    midi.handle_midi(&[0x90, 60]);
+    midi.handle_midi(&[100]);
+    assert_eq!(render(&mut midi, 2_000), render(&mut direct, 2_000));
+
+    // Velocity 0 is a note off
+    direct.note_off(60);
+    midi.handle_midi(&[0x90, 60, 0]);
+    assert_eq!(render(&mut midi, 20_000), render(&mut direct, 20_000));
+}
+
+#[test]
+fn test_handle_midi_receive_channel() {
+    let mut midi = synth();
+    midi.change_midi_channel(MidiChannel::Channel(3));
+    midi.handle_midi(&[0x90, 60, 100]);
+    assert!(render(&mut midi, 1_000)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+    midi.handle_midi(&[0x93, 60, 100]);
+    assert!(render(&mut midi, 1_000)
+        .iter()
+        .any(|frame| *frame != [0, 0]));
+}
+
+#[test]
+fn test_handle_midi_program_change() {
+    let mut midi = synth();
+    assert_eq!(midi.handle_midi(&[0x90, 60, 100]), None);
+    assert_eq!(midi.handle_midi(&[0xC0, 3, 0x90, 60, 0]), Some(3));
+}
+
+#[test]
+fn test_sustain_pedal() {
+    let mut sustained = synth();
+    let mut held = synth();
+    sustained.handle_midi(&[0x90, 60, 100, 0xB0, 64, 127, 0x80, 60, 0]);
+    held.handle_midi(&[0x90, 60, 100]);
+    assert_eq!(render(&mut sustained, 5_000), render(&mut held, 5_000));
+
+    // Releasing the pedal releases the note
+    sustained.handle_midi(&[0xB0, 64, 0]);
+    held.handle_midi(&[0x80, 60, 0]);
+    assert_eq!(render(&mut sustained, 20_000), render(&mut held, 20_000));
+}
+
+#[test]
+fn test_all_sound_off() {
+    let mut midi = synth();
+    midi.handle_midi(&[0x90, 60, 100, 64, 100]);
+    render(&mut midi, 1_000);
+    midi.handle_midi(&[0xB0, 120, 0]);
+    assert!(render(&mut midi, 1_000)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+}
+
+#[test]
+fn test_all_notes_off() {
+    let mut all_off = synth();
+    let mut released = synth();
+    all_off.handle_midi(&[0x90, 60, 100, 64, 100, 0xB0, 64, 127, 0x80, 60, 0]);
+    released.handle_midi(&[0x90, 60, 100, 64, 100]);
+    render(&mut all_off, 1_000);
+    render(&mut released, 1_000);
+    all_off.handle_midi(&[0xB0, 123, 0]);
+    released.handle_midi(&[0x80, 60, 0, 64, 0]);
+    assert_eq!(render(&mut all_off, 20_000), render(&mut released, 20_000));
+}
+
+#[test]
+fn test_pitch_bend() {
+    let mut bent = synth();
+    let mut straight = synth();
+    bent.handle_midi(&[0x90, 60, 100, 0xE0, 0x7F, 0x7F]);
+    straight.handle_midi(&[0x90, 60, 100]);
+    assert_ne!(render(&mut bent, 2_000), render(&mut straight, 2_000));
+
+    // Reset all controllers centers the pitch bend again
+    let mut centered = synth();
+    centered.handle_midi(&[0x90, 60, 100, 0xE0, 0x7F, 0x7F]);
+    render(&mut centered, 2_000);
+    bent.handle_midi(&[0xB0, 121, 0]);
+    centered.handle_midi(&[0xE0, 0x00, 0x40]);
+    assert_eq!(render(&mut bent, 2_000), render(&mut centered, 2_000));
+}
+
+#[test]
+fn test_poly_aftertouch_of_sounding_notes_only() {
+    let mut patch = piano();
+    patch.routering_config.modulation_matrix[0] = ModulationSlot {
+        source: ModulationSource::Aftertouch,
+        destination: ModulationDestination::Pan(ALL_VOICES),
+        depth: 100,
+        ..Default::default()
+    };
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.handle_midi(&[0x90, 60, 100]);
+    // Note 0 is not playing, even though free voices hold no note
+    synth.handle_midi(&[0xA0, 0, 127]);
+    assert!(render(&mut synth, 2_000).iter().any(|frame| frame[0] != 0));
+    // Full pressure on the playing note pans it fully right
+    synth.handle_midi(&[0xA0, 60, 127]);
+    render(&mut synth, 100);
+    assert!(render(&mut synth, 2_000).iter().all(|frame| frame[0] == 0));
+}
@@ -0,0 +1,310 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, piano, SAMPLE_RATE};
+use little_weirdo::synth::{
+    lfo::{LfoMode, LfoShape},
+    math::cents_to_ratio,
+    patch::{Patch, SynthMode},
+    router::{ModulationDestination, ModulationSlot, ModulationSource, ALL_VOICES},
+    wavetable_oscillator::{WaveTableOscillator, WaveTableOscillatorConfig},
+    Clockable, Synth,
+};
+
+fn play(synth: &mut Synth) -> Vec<[i16; 2]> {
+    synth.note_on(60, 100);
+    let mut output = vec![[0i16; 2]; 5_000];
+    synth.render(&mut output);
+    output
+}
+
+fn slot(source: ModulationSource, destination: ModulationDestination, depth: i8) -> ModulationSlot {
+    ModulationSlot {
+        source,
+        destination,
+        depth,
+        unipolar: false,
+    }
+}
+
+#[test]
+fn test_cents_to_ratio() {
+    assert_eq!(cents_to_ratio(0), 1 << 16);
+    assert_eq!(cents_to_ratio(1200), 2 << 16);
+    assert_eq!(cents_to_ratio(-1200), 1 << 15);
+    assert_eq!(cents_to_ratio(2400), 4 << 16);
+    // A fifth, 2^(7/12) = 1.498307
+    assert!((cents_to_ratio(700) as i64 - 98_193).abs() <= 1);
+    // One cent, 2^(1/1200) = 1.000578
+    assert!((cents_to_ratio(1) as i64 - 65_574).abs() <= 1);
+}
+
+#[test]
+fn test_modulate_pitch_one_octave() {
+    let config = WaveTableOscillatorConfig {
+        soundbank_index: 1,
+        ..Default::default()
+    };
+    let mut modulated = WaveTableOscillator::new(config, SAMPLE_RATE, load_wavetables());
+    let mut octave_up = WaveTableOscillator::new(config, SAMPLE_RATE, load_wavetables());
+    modulated.change_freq(220 << 16);
+    modulated.modulate_pitch(1200);
+    octave_up.change_freq(440 << 16);
+    for _ in 0..1_000 {
+        assert_eq!(modulated.clock(None), octave_up.clock(None));
+    }
+}
+
+#[test]
+fn test_empty_slots_do_not_modulate() {
+    let mut patch = piano();
+    let unmodulated = play(&mut Synth::new(SAMPLE_RATE, &patch, load_wavetables()));
+    assert!(unmodulated.iter().any(|frame| frame[0] != 0));
+    patch.routering_config.modulation_matrix[0] = slot(
+        ModulationSource::None,
+        ModulationDestination::VoicePitch(ALL_VOICES),
+        100,
+    );
+    patch.routering_config.modulation_matrix[1] =
+        slot(ModulationSource::ModWheel, ModulationDestination::None, 100);
+    assert_eq!(
+        play(&mut Synth::new(SAMPLE_RATE, &patch, load_wavetables())),
+        unmodulated
+    );
+}
+
+#[test]
+fn test_mod_wheel_to_amplitude() {
+    let mut patch = piano();
+    let unmodulated = play(&mut Synth::new(SAMPLE_RATE, &patch, load_wavetables()));
+    patch.routering_config.modulation_matrix[0] = slot(
+        ModulationSource::ModWheel,
+        ModulationDestination::VoiceAmplitude(ALL_VOICES),
+        100,
+    );
+
+    // With a positive depth the voices fade in with the mod wheel
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    assert!(play(&mut synth).iter().all(|frame| *frame == [0, 0]));
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.change_mod_wheel(127);
+    assert_eq!(play(&mut synth), unmodulated);
+
+    // With a negative depth the voices fade out with the mod wheel
+    patch.routering_config.modulation_matrix[0].depth = -100;
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    assert_eq!(play(&mut synth), unmodulated);
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.change_mod_wheel(127);
+    assert!(play(&mut synth).iter().all(|frame| *frame == [0, 0]));
+}
+
+#[test]
+fn test_aftertouch_to_pan() {
+    let mut patch = piano();
+    patch.routering_config.modulation_matrix[0] = slot(
+        ModulationSource::Aftertouch,
+        ModulationDestination::Pan(ALL_VOICES),
+        100,
+    );
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.change_aftertouch(127);
+    let output = play(&mut synth);
+    assert!(output.iter().all(|frame| frame[0] == 0));
+    assert!(output.iter().any(|frame| frame[1] != 0));
+}
+
+#[test]
+fn test_velocity_to_mixer_gain() {
+    let mut patch = piano();
+    patch.routering_config.modulation_matrix[0] = slot(
+        ModulationSource::Velocity,
+        ModulationDestination::MixerGain,
+        -100,
+    );
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(60, 127);
+    let mut output = vec![[0i16; 2]; 5_000];
+    synth.render(&mut output);
+    assert!(output.iter().all(|frame| *frame == [0, 0]));
+}
+
+#[test]
+fn test_load_patch_applies_modulation_matrix() {
+    let mut patch = piano();
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    patch.routering_config.modulation_matrix[0] = slot(
+        ModulationSource::ModWheel,
+        ModulationDestination::VoiceAmplitude(ALL_VOICES),
+        100,
+    );
+    synth.load_patch(&patch);
+    assert!(play(&mut synth).iter().all(|frame| *frame == [0, 0]));
+}
+
+fn raw_square() -> Patch {
+    serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap()
+}
+
+fn bend(patch: &Patch, value: i16) -> Vec<[i16; 2]> {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    synth.pitch_bend(value);
+    play(&mut synth)
+}
+
+#[test]
+fn test_pitch_bend_range() {
+    let mut patch = piano();
+    let unbent = bend(&patch, 0);
+    assert_ne!(bend(&patch, -8192), unbent);
+
+    // Without a range the pitch bend has no effect
+    patch.synth_config.pitch_bend_range = 0;
+    assert_eq!(bend(&patch, -8192), unbent);
+
+    // A full bend with a range of one semitone equals half a bend with a whole tone range
+    patch.synth_config.pitch_bend_range = 100;
+    let semitone = bend(&patch, -8192);
+    assert_ne!(semitone, unbent);
+    patch.synth_config.pitch_bend_range = 200;
+    assert_eq!(bend(&patch, -4096), semitone);
+
+    // Fractional semitones
+    patch.synth_config.pitch_bend_range = 50;
+    assert_ne!(bend(&patch, -8192), semitone);
+    assert_ne!(bend(&patch, -8192), unbent);
+}
+
+#[test]
+fn test_mod_wheel_scales_lfo_depth() {
+    let patch = raw_square();
+    let mut unscaled_patch = patch;
+    unscaled_patch.synth_config.mod_wheel_to_lfo_depth = 0;
+    let mut without_lfo = patch;
+    without_lfo.routering_config.modulation_matrix[0].depth = 0;
+
+    let play_with_wheel = |patch: &Patch, mod_wheel: u8| {
+        let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+        synth.change_mod_wheel(mod_wheel);
+        play(&mut synth)
+    };
+    let tremolo = play_with_wheel(&unscaled_patch, 0);
+    assert_ne!(tremolo, play_with_wheel(&without_lfo, 0));
+
+    // Wheel down, the LFO has no effect. Wheel up, the full LFO depth
+    assert_eq!(play_with_wheel(&patch, 0), play_with_wheel(&without_lfo, 0));
+    assert_eq!(play_with_wheel(&patch, 127), tremolo);
+    let halfway = play_with_wheel(&patch, 64);
+    assert_ne!(halfway, tremolo);
+    assert_ne!(halfway, play_with_wheel(&without_lfo, 0));
+
+    // Without the mod wheel setting the wheel leaves the LFO alone
+    assert_eq!(play_with_wheel(&unscaled_patch, 127), tremolo);
+}
+
+/// Play raw_square with a square LFO at 1 Hz in the first slot, restarted by the note
+fn square_lfo(destination: ModulationDestination, depth: i8, unipolar: bool) -> Vec<i16> {
+    let mut patch = raw_square();
+    patch.lfos[0].rate = 100;
+    patch.lfos[0].shape = LfoShape::Square;
+    patch.lfos[0].mode = LfoMode::KeySync;
+    patch.synth_config.mod_wheel_to_lfo_depth = 0;
+    patch.mixer_config.gain_voices[1] = 0;
+    patch.filter_config.enabled = false;
+    patch.routering_config.modulation_matrix[0] = ModulationSlot {
+        source: ModulationSource::Lfo(0),
+        destination,
+        depth,
+        unipolar,
+    };
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(69, 127);
+    let mut output = vec![[0i16; 2]; SAMPLE_RATE as usize];
+    synth.render(&mut output);
+    output.iter().map(|frame| frame[0]).collect()
+}
+
+/// Measurement over the middle of the top and the bottom half of the LFO cycle
+fn halves<T>(output: &[i16], measure: impl Fn(&[i16]) -> T) -> (T, T) {
+    let quarter = output.len() / 4;
+    (
+        measure(&output[quarter / 2..quarter * 2 - quarter / 2]),
+        measure(&output[quarter * 2 + quarter / 2..quarter * 4 - quarter / 2]),
+    )
+}
+
+fn peak(samples: &[i16]) -> f64 {
+    samples
+        .iter()
+        .map(|sample| sample.unsigned_abs())
+        .max()
+        .unwrap() as f64
+}
+
+#[test]
+fn test_lfo_vibrato_is_centred() {
+    let pitch = ModulationDestination::VoicePitch(ALL_VOICES);
+    let (base, _) = halves(&square_lfo(pitch, 0, false), measure_frequency);
+    let tenth_octave = 2f64.powf(0.1);
+    let near = |frequency: f64, expected: f64| (frequency / expected - 1.0).abs() < 0.005;
+
+    // Up and down around the played pitch
+    let (top, bottom) = halves(&square_lfo(pitch, 10, false), measure_frequency);
+    assert!(near(top, base * tenth_octave), "{top} {base}");
+    assert!(near(bottom, base / tenth_octave), "{bottom} {base}");
+
+    // A unipolar LFO only bends up
+    let (top, bottom) = halves(&square_lfo(pitch, 10, true), measure_frequency);
+    assert!(near(top, base * tenth_octave), "{top} {base}");
+    assert!(near(bottom, base), "{bottom} {base}");
+}
+
+#[test]
+fn test_lfo_tremolo_is_centred() {
+    let amplitude = ModulationDestination::VoiceAmplitude(ALL_VOICES);
+    let (base, _) = halves(&square_lfo(amplitude, 0, false), peak);
+    let near = |level: f64, expected: f64| (level / expected - 1.0).abs() < 0.02;
+
+    // Louder and softer around the voice level
+    let (top, bottom) = halves(&square_lfo(amplitude, 20, false), peak);
+    assert!(near(top, base * 1.2), "{top} {base}");
+    assert!(near(bottom, base * 0.8), "{bottom} {base}");
+
+    // A unipolar LFO only attenuates
+    let (top, bottom) = halves(&square_lfo(amplitude, 20, true), peak);
+    assert!(near(top, base), "{top} {base}");
+    assert!(near(bottom, base * 0.8), "{bottom} {base}");
+}
+
+#[test]
+fn test_full_bipolar_amplitude_on_every_voice_clips() {
+    let mut patch = raw_square();
+    patch.synth_config.mode = SynthMode::Mono;
+    patch.synth_config.mod_wheel_to_lfo_depth = 0;
+    patch.voices = [patch.voices[0]; 8];
+    patch.routering_config.voices_to_envelop = [0; 8];
+    patch.mixer_config.gain_voices = [100; 8];
+    patch.mixer_config.gain_main = 100;
+    patch.filter_config.enabled = false;
+    patch.lfos[0].rate = 100;
+    patch.lfos[0].shape = LfoShape::Square;
+    patch.lfos[0].mode = LfoMode::KeySync;
+    patch.routering_config.modulation_matrix[0] = slot(
+        ModulationSource::Lfo(0),
+        ModulationDestination::VoiceAmplitude(ALL_VOICES),
+        100,
+    );
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(69, 127);
+    let mut output = vec![[0i16; 2]; SAMPLE_RATE as usize];
+    synth.render(&mut output);
+    let left: Vec<i16> = output.iter().map(|frame| frame[0]).collect();
+
+    // Eight voices at twice their level clip at full scale instead of wrapping around
+    let (top, _) = halves(&left, |samples| {
+        (
+            *samples.iter().max().unwrap(),
+            *samples.iter().min().unwrap(),
+        )
+    });
+    assert_eq!(top, (i16::MAX, i16::MIN), "{top:?}");
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use common::{load_wavetables, render, SAMPLE_RATE};
+use little_weirdo::synth::{
+    envelope::EnvelopConfiguration,
+    note_stack::{NoteStack, NOTE_STACK_SIZE},
+    patch::{MonoMode, NotePriority, Patch},
+    Synth,
+};
+
+fn bass(mono_mode: MonoMode, note_priority: NotePriority) -> Patch {
+    let mut patch = common::bass();
+    patch.synth_config.mono_mode = mono_mode;
+    patch.synth_config.note_priority = note_priority;
+    patch
+}
+
+/// Play notes on a new synth, a negative number releases the note
+fn play(patch: &Patch, notes: &[i16]) -> Synth {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    for note in notes {
+        if *note < 0 {
+            synth.note_off(-*note as u8);
+        } else {
+            synth.note_on(*note as u8, 100);
+        }
+        render(&mut synth, 1_000);
+    }
+    synth
+}
+
+fn is_silent(output: &[[i16; 2]]) -> bool {
+    output.iter().all(|frame| *frame == [0, 0])
+}
+
+#[test]
+fn test_note_stack() {
+    let mut stack = NoteStack::new();
+    assert_eq!(stack.pick(NotePriority::Last), None);
+    for note in [60, 48, 72, 55] {
+        stack.push(note);
+    }
+    assert_eq!(stack.pick(NotePriority::Last), Some(55));
+    assert_eq!(stack.pick(NotePriority::Lowest), Some(48));
+    assert_eq!(stack.pick(NotePriority::Highest), Some(72));
+
+    // Pressing a held key again moves it to the top
+    stack.push(48);
+    assert_eq!(stack.notes(), &[60, 72, 55, 48]);
+    stack.remove(48);
+    stack.remove(72);
+    assert_eq!(stack.notes(), &[60, 55]);
+
+    // The oldest key is forgotten when the stack is full
+    for note in 0..NOTE_STACK_SIZE as u8 {
+        stack.push(30 + note);
+    }
+    assert_eq!(stack.notes().len(), NOTE_STACK_SIZE);
+    assert_eq!(stack.notes()[0], 30);
+    stack.clear();
+    assert_eq!(stack.pick(NotePriority::Highest), None);
+}
+
+#[test]
+fn test_last_note_priority_returns_to_held_note() {
+    for mono_mode in [MonoMode::Legato, MonoMode::Retrigger] {
+        let patch = bass(mono_mode, NotePriority::Last);
+        let mut returned = play(&patch, &[48, 55, -55]);
+        let mut played_again = play(&patch, &[48, 55, 48]);
+        let output = render(&mut returned, 5_000);
+        assert!(!is_silent(&output));
+        assert_eq!(output, render(&mut played_again, 5_000));
+
+        // Releasing a key that does not sound changes nothing
+        let mut released = play(&patch, &[48, 55, -48]);
+        let mut held = play(&patch, &[48, 55]);
+        render(&mut held, 1_000);
+        assert_eq!(render(&mut released, 5_000), render(&mut held, 5_000));
+    }
+}
+
+#[test]
+fn test_lowest_and_highest_note_priority() {
+    let patch = bass(MonoMode::Retrigger, NotePriority::Lowest);
+    let mut both = play(&patch, &[48, 55]);
+    let mut low = play(&patch, &[48]);
+    render(&mut low, 1_000);
+    assert_eq!(render(&mut both, 5_000), render(&mut low, 5_000));
+    // Releasing the low key moves to the high key
+    both.note_off(48);
+    low.note_off(48);
+    assert_ne!(render(&mut both, 5_000), render(&mut low, 5_000));
+    assert!(!is_silent(&render(&mut both, 100)));
+
+    let patch = bass(MonoMode::Retrigger, NotePriority::Highest);
+    let mut both = play(&patch, &[55, 48]);
+    let mut high = play(&patch, &[55]);
+    render(&mut high, 1_000);
+    assert_eq!(render(&mut both, 5_000), render(&mut high, 5_000));
+}
+
+#[test]
+fn test_legato_does_not_retrigger_envelopes() {
+    let plucked = |mono_mode| {
+        let mut patch = bass(mono_mode, NotePriority::Last);
+        patch.envelops = [EnvelopConfiguration {
+            attack_time: 0,
+            decay_time: 20,
+            release_time: 0,
+            sustain_level: 0,
+            ..Default::default()
+        }; 8];
+        let mut synth = play(&patch, &[48]);
+        render(&mut synth, 5_000);
+        synth.note_on(55, 100);
+        render(&mut synth, 1_000)
+    };
+    assert!(is_silent(&plucked(MonoMode::Legato)));
+    assert!(!is_silent(&plucked(MonoMode::Retrigger)));
+}
+
+#[test]
+fn test_legato_glides_between_overlapping_notes() {
+    let mut gliding = bass(MonoMode::Legato, NotePriority::Last);
+    for voice in gliding.voices.iter_mut() {
+        voice.glide = true;
+        voice.glide_rate = 100;
+    }
+    let mut jumping = gliding;
+    for voice in jumping.voices.iter_mut() {
+        voice.glide = false;
+    }
+
+    // The first note of a phrase starts without glide
+    let mut glide = Synth::new(SAMPLE_RATE, &gliding, load_wavetables());
+    let mut jump = Synth::new(SAMPLE_RATE, &jumping, load_wavetables());
+    glide.note_on(36, 100);
+    jump.note_on(36, 100);
+    assert_eq!(render(&mut glide, 5_000), render(&mut jump, 5_000));
+
+    glide.note_on(48, 100);
+    jump.note_on(48, 100);
+    assert_ne!(render(&mut glide, 5_000), render(&mut jump, 5_000));
+}
+
+#[test]
+fn test_mono_sustain() {
+    let patch = bass(MonoMode::Legato, NotePriority::Last);
+    let mut sustained = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    let mut held = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    sustained.note_on(48, 100);
+    sustained.sustain(true);
+    sustained.note_off(48);
+    held.note_on(48, 100);
+    assert_eq!(render(&mut sustained, 5_000), render(&mut held, 5_000));
+
+    sustained.sustain(false);
+    held.note_off(48);
+    assert_eq!(render(&mut sustained, 20_000), render(&mut held, 20_000));
+}
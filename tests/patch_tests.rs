@@ -1,5 +1,11 @@
-use little_weirdo::synth::patch::Patch;
-use postcard;
+mod common;
+
+use common::{load_wavetables, measure_frequency, SAMPLE_RATE};
+use little_weirdo::synth::{
+    patch::{Patch, PatchError, PATCH_FORMAT_VERSION, PATCH_MAGIC},
+    router::{ModulationDestination, ModulationSource},
+    Synth,
+};
 
 #[test]
 fn test_patch_deserialization_json() {
@@ -16,11 +22,129 @@ fn test_patch_deserialization_json() {
 #[test]
 fn test_patch_deserialization_postcard() {
     // Test loading a patch from JSON file
-    let patch: Patch =
-        postcard::from_bytes(include_bytes!("../examples/patches/bass.lwp")).unwrap();
+    let patch = Patch::from_postcard(include_bytes!("../examples/patches/bass.lwp")).unwrap();
 
     // Verify the patch was loaded successfully
     assert!(!patch.voices.is_empty());
     assert!(!patch.envelops.is_empty());
     assert!(!patch.lfos.is_empty());
 }
+
+fn legacy_bass() -> Patch {
+    serde_json::from_slice(include_bytes!("data/legacy_bass.json")).unwrap()
+}
+
+fn postcard(patch: &Patch) -> Vec<u8> {
+    let mut buffer = [0u8; 512];
+    patch.to_postcard(&mut buffer).unwrap().to_vec()
+}
+
+#[test]
+fn test_postcard_patch_has_a_format_version() {
+    let patch: Patch =
+        serde_json::from_slice(include_bytes!("../examples/patches/piano.json")).unwrap();
+    let bytes = postcard(&patch);
+    assert_eq!(bytes[..2], PATCH_MAGIC);
+    assert_eq!(bytes[2], PATCH_FORMAT_VERSION);
+    assert_eq!(postcard(&Patch::from_postcard(&bytes).unwrap()), bytes);
+
+    // A newer layout is rejected instead of being read as garbage
+    let mut newer = bytes.clone();
+    newer[2] = PATCH_FORMAT_VERSION + 1;
+    assert_eq!(
+        Patch::from_postcard(&newer).err(),
+        Some(PatchError::UnsupportedVersion(PATCH_FORMAT_VERSION + 1))
+    );
+    assert!(matches!(
+        Patch::from_postcard(&bytes[..bytes.len() - 1]),
+        Err(PatchError::Postcard(_))
+    ));
+}
+
+#[test]
+fn test_unversioned_postcard_patch_loads() {
+    // Written by little weirdo 0.1, before the format version
+    let patch = Patch::from_postcard(include_bytes!("data/legacy_bass.lwp")).unwrap();
+    assert_eq!(postcard(&patch), postcard(&legacy_bass()));
+}
+
+#[test]
+fn test_legacy_routes_move_to_the_modulation_matrix() {
+    let mut patch = legacy_bass();
+    assert!(patch.routering_config.lfo_to_freq);
+    patch.migrate_legacy_routes();
+    let config = patch.routering_config;
+    let slot = config.modulation_matrix[0];
+    assert_eq!(slot.source, ModulationSource::Lfo(0));
+    assert_eq!(slot.destination, ModulationDestination::VoiceFrequency(0));
+    assert!(slot.unipolar);
+    assert_eq!(slot.depth, 5);
+    assert!(!config.modulation_matrix[1].is_active());
+    assert!(!config.lfo_to_freq);
+
+    // Migrating twice leaves the matrix alone
+    patch.migrate_legacy_routes();
+    assert_eq!(
+        format!("{:?}", patch.routering_config.modulation_matrix),
+        format!("{:?}", config.modulation_matrix)
+    );
+}
+
+/// Render one second of a legacy patch like the fixtures rendered by little weirdo 0.1: note 57
+/// at velocity 100, which was full level there and is 127 now
+fn render_legacy(patch: &Patch) -> Vec<i16> {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    synth.note_on(57, 127);
+    let mut output = vec![[0i16; 2]; SAMPLE_RATE as usize];
+    synth.render(&mut output);
+    output.iter().map(|frame| frame[0]).collect()
+}
+
+/// Left channel as rendered by little weirdo 0.1, mono 16 bit little endian
+fn baseline(bytes: &[u8]) -> Vec<i16> {
+    bytes
+        .chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect()
+}
+
+/// 50 ms windows of a rendered second
+fn windows(samples: &[i16]) -> impl Iterator<Item = &[i16]> {
+    samples.chunks_exact(SAMPLE_RATE as usize / 20)
+}
+
+#[test]
+fn test_legacy_lfo_to_freq_sounds_like_before() {
+    // The LFO adds up to 50 Hz to a sine at 220 Hz
+    let patch: Patch =
+        serde_json::from_slice(include_bytes!("data/legacy_lfo_to_freq.json")).unwrap();
+    let before = baseline(include_bytes!("data/legacy_lfo_to_freq.raw"));
+    let after = render_legacy(&patch);
+    for (before, after) in windows(&before).zip(windows(&after)) {
+        let (before, after) = (measure_frequency(before), measure_frequency(after));
+        assert!(
+            (before - after).abs() < 6.0,
+            "{before:.1} Hz became {after:.1} Hz"
+        );
+    }
+}
+
+#[test]
+fn test_legacy_lfo_to_filter_sounds_like_before() {
+    // The LFO sweeps the cutoff from 1000 to 11000, the configured cutoff is not used
+    let patch: Patch =
+        serde_json::from_slice(include_bytes!("data/legacy_lfo_to_filter.json")).unwrap();
+    let before = baseline(include_bytes!("data/legacy_lfo_to_filter.raw"));
+    let after = render_legacy(&patch);
+    let rms = |samples: &[i16]| {
+        let sum: f64 = samples.iter().map(|s| *s as f64 * *s as f64).sum();
+        (sum / samples.len() as f64).sqrt()
+    };
+    for (before, after) in windows(&before).zip(windows(&after)) {
+        let (before, after) = (rms(before), rms(after));
+        assert!(
+            (before - after).abs() < 1_000.0,
+            "{before:.0} became {after:.0}"
+        );
+    }
+}
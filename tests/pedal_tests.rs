@@ -0,0 +1,116 @@
+mod common;
+
+use common::{load_wavetables, render, SAMPLE_RATE};
+use little_weirdo::synth::Synth;
+
+fn piano() -> Synth {
+    Synth::new(SAMPLE_RATE, &common::piano(), load_wavetables())
+}
+
+/// Renders both synths and checks they sound the same
+fn assert_same(pedal: &mut Synth, keys: &mut Synth, length: usize) {
+    assert_eq!(render(pedal, length), render(keys, length));
+}
+
+#[test]
+fn test_sustained_note_struck_again() {
+    let mut pedal = piano();
+    let mut keys = piano();
+    pedal.note_on(60, 100);
+    keys.note_on(60, 100);
+    pedal.sustain(true);
+    pedal.note_off(60);
+    assert_same(&mut pedal, &mut keys, 2_000);
+
+    // Striking the note again retriggers it, releasing the pedal keeps it playing as the key is down
+    pedal.note_on(60, 100);
+    keys.note_on(60, 100);
+    assert_same(&mut pedal, &mut keys, 2_000);
+    pedal.sustain(false);
+    assert_same(&mut pedal, &mut keys, 5_000);
+
+    pedal.note_off(60);
+    keys.note_off(60);
+    assert_same(&mut pedal, &mut keys, 20_000);
+}
+
+#[test]
+fn test_sustained_note_struck_and_released_again() {
+    let mut pedal = piano();
+    let mut keys = piano();
+    pedal.sustain(true);
+    pedal.note_on(60, 100);
+    keys.note_on(60, 100);
+    pedal.note_off(60);
+    assert_same(&mut pedal, &mut keys, 2_000);
+    pedal.note_on(60, 100);
+    keys.note_on(60, 100);
+    pedal.note_off(60);
+    assert_same(&mut pedal, &mut keys, 2_000);
+
+    pedal.sustain(false);
+    keys.note_off(60);
+    assert_same(&mut pedal, &mut keys, 20_000);
+    // A single release ends the note that was struck twice
+    assert!(render(&mut pedal, 1_000)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+}
+
+#[test]
+fn test_sostenuto_holds_only_notes_down() {
+    let mut pedal = piano();
+    let mut keys = piano();
+    pedal.note_on(60, 100);
+    keys.note_on(60, 100);
+    pedal.sostenuto(true);
+    // Notes played after pressing the pedal are not held
+    pedal.note_on(64, 100);
+    keys.note_on(64, 100);
+    assert_same(&mut pedal, &mut keys, 1_000);
+    pedal.note_off(60);
+    pedal.note_off(64);
+    keys.note_off(64);
+    assert_same(&mut pedal, &mut keys, 5_000);
+
+    // Releasing the pedal releases the held note
+    pedal.sostenuto(false);
+    keys.note_off(60);
+    assert_same(&mut pedal, &mut keys, 20_000);
+}
+
+#[test]
+fn test_sostenuto_with_sustain() {
+    let mut pedal = piano();
+    let mut keys = piano();
+    pedal.note_on(60, 100);
+    keys.note_on(60, 100);
+    pedal.sostenuto(true);
+    pedal.sustain(true);
+    pedal.note_on(64, 100);
+    keys.note_on(64, 100);
+    pedal.note_off(60);
+    pedal.note_off(64);
+    assert_same(&mut pedal, &mut keys, 2_000);
+
+    // The sustain pedal releases the note outside the sostenuto
+    pedal.sustain(false);
+    keys.note_off(64);
+    assert_same(&mut pedal, &mut keys, 5_000);
+
+    pedal.sostenuto(false);
+    keys.note_off(60);
+    assert_same(&mut pedal, &mut keys, 20_000);
+}
+
+#[test]
+fn test_sostenuto_midi() {
+    let mut pedal = piano();
+    let mut keys = piano();
+    pedal.handle_midi(&[0x90, 60, 100, 0xB0, 66, 127, 0x80, 60, 0]);
+    keys.note_on(60, 100);
+    assert_same(&mut pedal, &mut keys, 2_000);
+    pedal.handle_midi(&[0xB0, 66, 0]);
+    keys.note_off(60);
+    assert_same(&mut pedal, &mut keys, 20_000);
+}
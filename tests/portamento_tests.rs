@@ -0,0 +1,142 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, SAMPLE_RATE};
+use little_weirdo::synth::{
+    math::{exp2, log2},
+    wavetable_oscillator::{
+        Interpolation, PortamentoMode, WaveTableOscillator, WaveTableOscillatorConfig,
+    },
+    Clockable,
+};
+
+fn sine_oscillator(portamento_time: u16, portamento_mode: PortamentoMode) -> WaveTableOscillator {
+    let config = WaveTableOscillatorConfig {
+        soundbank_index: 0, // wav0 is a sine
+        glide: true,
+        glide_rate: 10,
+        interpolation: Interpolation::Linear,
+        portamento_time,
+        portamento_mode,
+        ..Default::default()
+    };
+    WaveTableOscillator::new(config, SAMPLE_RATE, load_wavetables())
+}
+
+/// Glide from one frequency to another and measure the frequency at a few moments
+///
+/// # Returns
+/// The frequency measured over 10 ms around every moment (in ms)
+fn glide(
+    portamento_time: u16,
+    portamento_mode: PortamentoMode,
+    from: u32,
+    to: u32,
+    moments: &[usize],
+) -> Vec<f64> {
+    let mut osc = sine_oscillator(portamento_time, portamento_mode);
+    osc.set_freq(from << 16);
+    let mut samples = vec![0i16; SAMPLE_RATE as usize];
+    osc.clock_block(&mut samples[..SAMPLE_RATE as usize / 10]);
+    osc.change_freq(to << 16);
+    osc.clock_block(&mut samples);
+    let ms = SAMPLE_RATE as usize / 1000;
+    moments
+        .iter()
+        .map(|moment| measure_frequency(&samples[(moment - 5) * ms..(moment + 5) * ms]))
+        .collect()
+}
+
+/// Check a measured frequency, the pitch moves during the 10 ms of a measurement so allow some cents
+fn assert_close(measured: f64, expected: f64) {
+    let cents = 1200.0 * (measured / expected).log2();
+    assert!(
+        cents.abs() < 20.0,
+        "measured {:.2} Hz instead of {:.2} Hz",
+        measured,
+        expected
+    );
+}
+
+#[test]
+fn test_log2() {
+    assert_eq!(log2(1 << 16), 0);
+    assert_eq!(log2(2 << 16), 1 << 16);
+    assert_eq!(log2(1 << 15), -(1 << 16));
+    assert_eq!(log2(1024 << 16), 10 << 16);
+    let expected = (440f64.log2() * 65536.0) as i32;
+    assert!((log2(440 << 16) - expected).abs() <= 1);
+    for x in [3 << 16, 98_193, 27_500 << 16, 1_000] {
+        assert!((exp2(log2(x)) as i64 - x as i64).abs() <= (x as i64 >> 14).max(1));
+    }
+}
+
+#[test]
+fn test_constant_time_portamento() {
+    // Half way the glide the pitch is half way in cents, whatever the notes
+    for (from, to) in [(220, 440), (440, 880), (880, 220)] {
+        let measured = glide(200, PortamentoMode::ConstantTime, from, to, &[100, 300]);
+        assert_close(measured[0], (from as f64 * to as f64).sqrt());
+        assert_close(measured[1], to as f64);
+    }
+}
+
+#[test]
+fn test_constant_rate_portamento() {
+    // 100 ms per octave, two octaves take 200 ms
+    let measured = glide(
+        100,
+        PortamentoMode::ConstantRate,
+        220,
+        880,
+        &[50, 100, 150, 300],
+    );
+    assert_close(measured[0], 220.0 * 2f64.sqrt());
+    assert_close(measured[1], 440.0);
+    assert_close(measured[2], 440.0 * 2f64.sqrt());
+    assert_close(measured[3], 880.0);
+}
+
+#[test]
+fn test_portamento_from_halfway_a_glide() {
+    let mut osc = sine_oscillator(100, PortamentoMode::ConstantTime);
+    osc.set_freq(220 << 16);
+    osc.change_freq(880 << 16);
+    let mut samples = vec![0i16; SAMPLE_RATE as usize / 20];
+    osc.clock_block(&mut samples);
+    // Half way, at 440 Hz, turn back to 220 Hz, the glide continues from 440 Hz
+    osc.change_freq(220 << 16);
+    let ms = SAMPLE_RATE as usize / 1000;
+    let mut samples = vec![0i16; 10 * ms];
+    osc.clock_block(&mut samples);
+    assert!(measure_frequency(&samples) > 400.0);
+}
+
+#[test]
+fn test_set_freq_stops_portamento() {
+    let mut osc = sine_oscillator(100, PortamentoMode::ConstantTime);
+    let mut jump = sine_oscillator(0, PortamentoMode::ConstantTime);
+    osc.set_freq(220 << 16);
+    osc.change_freq(880 << 16);
+    osc.set_freq(440 << 16);
+    jump.set_freq(440 << 16);
+    for _ in 0..1_000 {
+        assert_eq!(osc.clock(None), jump.clock(None));
+    }
+}
+
+#[test]
+fn test_glide_rate_zero_jumps() {
+    // Without a portamento time and a glide rate of 0 the frequency changes right away
+    let mut osc = sine_oscillator(0, PortamentoMode::ConstantTime);
+    let mut jump = sine_oscillator(0, PortamentoMode::ConstantTime);
+    osc.reload(WaveTableOscillatorConfig {
+        glide_rate: 0,
+        ..osc.config
+    });
+    osc.set_freq(220 << 16);
+    osc.change_freq(440 << 16);
+    jump.set_freq(440 << 16);
+    for _ in 0..1_000 {
+        assert_eq!(osc.clock(None), jump.clock(None));
+    }
+}
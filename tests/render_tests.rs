@@ -0,0 +1,80 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{bass, load_wavetables, SAMPLE_RATE};
+use little_weirdo::synth::{patch::Patch, Synth};
+
+/// Play a short phrase sample by sample, using `clock_and_output`
+fn play_per_sample(synth: &mut Synth, length: usize) -> Vec<[i16; 2]> {
+    let mut output = Vec::with_capacity(length);
+    for n in 0..length {
+        if n == 0 {
+            synth.note_on(48, 100);
+        }
+        if n == length / 2 {
+            synth.note_off(48);
+        }
+        output.push(synth.clock_and_output());
+    }
+    output
+}
+
+/// Play the same phrase as `play_per_sample`, using `render` with uneven block sizes
+fn play_per_block(synth: &mut Synth, length: usize) -> Vec<[i16; 2]> {
+    let mut output = vec![[0i16; 2]; length];
+    let mut position = 0;
+    let mut block_size = 1;
+    synth.note_on(48, 100);
+    while position < length {
+        let mut end = (position + block_size).min(length);
+        if position < length / 2 && end > length / 2 {
+            end = length / 2;
+        }
+        if position == length / 2 {
+            synth.note_off(48);
+        }
+        synth.render(&mut output[position..end]);
+        position = end;
+        block_size = block_size * 3 % 97 + 1;
+    }
+    output
+}
+
+#[test]
+fn test_render_is_identical_to_clock_and_output() {
+    let wt = load_wavetables();
+    let patches: [&[u8]; 3] = [
+        include_bytes!("../examples/patches/bass.json"),
+        include_bytes!("../examples/patches/piano.json"),
+        include_bytes!("../examples/patches/supersaw_4_oscillators.json"),
+    ];
+    for patch in patches {
+        let patch: Patch = serde_json::from_slice(patch).unwrap();
+        let mut per_sample = Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
+        let mut per_block = Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
+
+        let expected = play_per_sample(&mut per_sample, 20_000);
+        let rendered = play_per_block(&mut per_block, 20_000);
+
+        assert!(expected.iter().any(|frame| frame[0] != 0));
+        assert_eq!(expected, rendered);
+    }
+}
+
+#[test]
+fn test_render_interleaved_is_identical_to_clock_and_output() {
+    let wt = load_wavetables();
+    let patch = bass();
+    let mut per_sample = Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
+    let mut interleaved = Synth::new(SAMPLE_RATE, &patch, Arc::clone(&wt));
+    per_sample.note_on(60, 100);
+    interleaved.note_on(60, 100);
+
+    let mut buffer = [0i16; 2 * 1000];
+    interleaved.render_interleaved(&mut buffer);
+
+    for frame in buffer.chunks_exact(2) {
+        assert_eq!(per_sample.clock_and_output(), [frame[0], frame[1]]);
+    }
+}
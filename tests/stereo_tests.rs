@@ -0,0 +1,50 @@
+mod common;
+
+use common::{bass, load_wavetables, SAMPLE_RATE};
+use little_weirdo::synth::{patch::Patch, Synth};
+
+fn render_note(patch: &Patch) -> Vec<[i16; 2]> {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    synth.note_on(48, 100);
+    let mut frames = vec![[0i16; 2]; 10_000];
+    synth.render(&mut frames);
+    frames
+}
+
+#[test]
+fn test_centered_voices_are_mono() {
+    let mut patch: Patch = serde_json::from_slice(include_bytes!(
+        "../examples/patches/supersaw_4_oscillators.json"
+    ))
+    .unwrap();
+    patch.mixer_config.stereo_spread = 0;
+
+    let frames = render_note(&patch);
+
+    assert!(frames.iter().any(|frame| frame[0] != 0));
+    assert!(frames.iter().all(|frame| frame[0] == frame[1]));
+}
+
+#[test]
+fn test_stereo_spread_widens_voices() {
+    let patch: Patch = serde_json::from_slice(include_bytes!(
+        "../examples/patches/supersaw_4_oscillators.json"
+    ))
+    .unwrap();
+    assert!(patch.mixer_config.stereo_spread > 0);
+
+    let frames = render_note(&patch);
+
+    assert!(frames.iter().any(|frame| frame[0] != frame[1]));
+}
+
+#[test]
+fn test_pan_voice_hard_left() {
+    let mut patch = bass();
+    patch.mixer_config.pan_voices = [-100; 8];
+
+    let frames = render_note(&patch);
+
+    assert!(frames.iter().any(|frame| frame[0] != 0));
+    assert!(frames.iter().all(|frame| frame[1] == 0));
+}
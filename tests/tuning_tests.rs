@@ -0,0 +1,275 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, render, SAMPLE_RATE};
+use little_weirdo::synth::{
+    data::frequencies::MIDI2FREQ,
+    tuning::{ScalaError, TuningTable},
+    wavetable_oscillator::{WaveTableOscillator, WaveTableOscillatorConfig},
+    Clockable, Synth,
+};
+
+/// Maximum allowed deviation from 12-TET in cents
+const MAX_DEVIATION_IN_CENTS: f64 = 1.0;
+
+fn sine_oscillator() -> WaveTableOscillator {
+    let config = WaveTableOscillatorConfig {
+        soundbank_index: 0, // wav0 is a sine
+        ..Default::default()
+    };
+    WaveTableOscillator::new(config, SAMPLE_RATE, load_wavetables())
+}
+
+#[test]
+fn test_rendered_period_of_every_midi_note() {
+    for (note, freq) in MIDI2FREQ.iter().enumerate() {
+        let mut osc = sine_oscillator();
+        osc.change_freq(*freq);
+        let mut samples = vec![0i16; 2 * SAMPLE_RATE as usize];
+        osc.clock_block(&mut samples);
+
+        let expected = 440.0 * 2f64.powf((note as f64 - 69.0) / 12.0);
+        let measured = measure_frequency(&samples);
+        let deviation = 1200.0 * (measured / expected).log2();
+        assert!(
+            deviation.abs() < MAX_DEVIATION_IN_CENTS,
+            "note {} plays {:.3} Hz instead of {:.3} Hz ({:.2} cents)",
+            note,
+            measured,
+            expected,
+            deviation
+        );
+    }
+}
+
+#[test]
+fn test_frequency_table_covers_all_midi_notes() {
+    assert_eq!(MIDI2FREQ.len(), 128);
+    assert!(MIDI2FREQ.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+/// Deviation between two 16.16 frequencies in cents
+fn cents(frequency: u32, expected: f64) -> f64 {
+    1200.0 * (frequency as f64 / 65536.0 / expected).log2()
+}
+
+fn piano() -> Synth {
+    Synth::new(SAMPLE_RATE, &common::piano(), load_wavetables())
+}
+
+#[test]
+fn test_scala_equal_temperament() {
+    let scl = "! 12-TET\n\n 12\n 100.0\n 200.\n 300.0\n 400.0\n 500.0\n 600.0\n 700.0\n 800.0\n 900.0\n 1000.0\n 1100.0\n 2/1\n";
+    let tuning = TuningTable::from_scala(scl, None).unwrap();
+    for note in 0..128u8 {
+        let expected = 440.0 * 2f64.powf((note as f64 - 69.0) / 12.0);
+        let deviation = cents(tuning.frequency(note).unwrap(), expected);
+        assert!(
+            deviation.abs() < 0.1,
+            "note {} is {:.3} cents off",
+            note,
+            deviation
+        );
+    }
+    assert_eq!(TuningTable::default().frequency(127), Some(MIDI2FREQ[127]));
+}
+
+#[test]
+fn test_scala_just_intonation() {
+    let tuning = TuningTable::from_scala(
+        include_str!("../examples/tunings/just_intonation.scl"),
+        Some(include_str!("../examples/tunings/a440.kbm")),
+    )
+    .unwrap();
+    // A4 is the major sixth (5/3) above middle C
+    for (note, expected) in [
+        (69, 440.0),
+        (60, 264.0),
+        (64, 330.0),
+        (67, 396.0),
+        (72, 528.0),
+        (48, 132.0),
+    ] {
+        let deviation = cents(tuning.frequency(note).unwrap(), expected);
+        assert!(
+            deviation.abs() < 0.1,
+            "note {} is {:.3} cents off",
+            note,
+            deviation
+        );
+    }
+}
+
+#[test]
+fn test_scala_non_twelve_tone_scale() {
+    let tuning =
+        TuningTable::from_scala(include_str!("../examples/tunings/19_edo.scl"), None).unwrap();
+    let middle_c = MIDI2FREQ[60] as f64 / 65536.0;
+    for note in [41u8, 60, 61, 79, 98] {
+        let expected = middle_c * 2f64.powf((note as f64 - 60.0) / 19.0);
+        let deviation = cents(tuning.frequency(note).unwrap(), expected);
+        assert!(
+            deviation.abs() < 0.1,
+            "note {} is {:.3} cents off",
+            note,
+            deviation
+        );
+    }
+}
+
+#[test]
+fn test_scala_unmapped_keys() {
+    // Only the white keys play, a 7 note scale repeating every 12 keys
+    let scl = "white keys\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1";
+    let kbm = "12\n0\n127\n60\n60\n261.6256\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+    let tuning = TuningTable::from_scala(scl, Some(kbm)).unwrap();
+    assert_eq!(tuning.frequency(61), None);
+    assert!(cents(tuning.frequency(67).unwrap(), 261.6256 * 1.5).abs() < 0.1);
+    assert!(cents(tuning.frequency(72).unwrap(), 261.6256 * 2.0).abs() < 0.1);
+    assert!(cents(tuning.frequency(59).unwrap(), 261.6256 * 15.0 / 16.0).abs() < 0.1);
+
+    // The synth ignores unmapped keys
+    let mut synth = piano();
+    synth.load_tuning(tuning);
+    synth.note_on(61, 100);
+    assert!(render(&mut synth, 1_000)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+}
+
+#[test]
+fn test_scala_errors() {
+    assert_eq!(
+        TuningTable::from_scala("no count", None),
+        Err(ScalaError::InvalidScale)
+    );
+    assert_eq!(
+        TuningTable::from_scala("too short\n3\n9/8\n5/4", None),
+        Err(ScalaError::InvalidScale)
+    );
+    assert_eq!(
+        TuningTable::from_scala("bad pitch\n1\nfoo", None),
+        Err(ScalaError::InvalidScale)
+    );
+    assert_eq!(
+        TuningTable::from_scala("octave\n1\n2/1", Some("1\n0\n127\n60\n69")),
+        Err(ScalaError::InvalidKeyboardMapping)
+    );
+    assert_eq!(
+        TuningTable::from_scala("octave\n1\n2/1", Some("1\n0\n127\n60\n69\n440.0\n1\nx")),
+        Err(ScalaError::UnmappedReferenceNote)
+    );
+    // A mapping longer than the MIDI keys
+    assert_eq!(
+        TuningTable::from_scala(
+            "octave\n1\n2/1",
+            Some("2000000000\n0\n127\n60\n69\n440.0\n1\n0")
+        ),
+        Err(ScalaError::InvalidKeyboardMapping)
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_load_scala_files() {
+    let loaded = TuningTable::load_scala(
+        "examples/tunings/just_intonation.scl",
+        Some("examples/tunings/a440.kbm"),
+    )
+    .unwrap();
+    let parsed = TuningTable::from_scala(
+        include_str!("../examples/tunings/just_intonation.scl"),
+        Some(include_str!("../examples/tunings/a440.kbm")),
+    )
+    .unwrap();
+    assert_eq!(loaded, parsed);
+    assert!(matches!(
+        TuningTable::load_scala("missing.scl", None::<&str>),
+        Err(ScalaError::Io(_))
+    ));
+}
+
+#[test]
+fn test_master_tune() {
+    // A4 at 880 Hz plays every note an octave higher
+    let mut master_tuned = piano();
+    let mut octave_up = piano();
+    master_tuned.change_master_tune(880 << 16);
+    master_tuned.note_on(57, 100);
+    octave_up.note_on(69, 100);
+    assert_eq!(
+        render(&mut master_tuned, 5_000),
+        render(&mut octave_up, 5_000)
+    );
+}
+
+#[test]
+fn test_load_tuning_at_runtime() {
+    // A tuning that plays every note a major third higher
+    let mut frequencies = [0; 128];
+    for (note, frequency) in frequencies.iter_mut().enumerate().take(124) {
+        *frequency = MIDI2FREQ[note + 4];
+    }
+    let mut retuned = piano();
+    let mut third_up = piano();
+    retuned.note_on(60, 100);
+    retuned.load_tuning(TuningTable::from_frequencies(frequencies));
+    third_up.note_on(64, 100);
+    assert_eq!(render(&mut retuned, 5_000), render(&mut third_up, 5_000));
+
+    // Swapping back retunes the held note
+    retuned.load_tuning(TuningTable::equal_temperament());
+    third_up.note_off(64);
+    assert_ne!(render(&mut retuned, 5_000), render(&mut third_up, 5_000));
+}
+
+#[test]
+fn test_tuning_decides_the_playable_keys() {
+    // Every key of the tuning sounds, from note 0 to note 127
+    let mut frequencies = [0; 128];
+    frequencies[0] = 220 << 16;
+    frequencies[127] = 440 << 16;
+    for note in [0, 127] {
+        let mut synth = piano();
+        synth.load_tuning(TuningTable::from_frequencies(frequencies));
+        synth.note_on(note, 100);
+        assert!(render(&mut synth, 5_000)
+            .iter()
+            .any(|frame| *frame != [0, 0]));
+    }
+    // A key left unmapped by the tuning is silent
+    let mut synth = piano();
+    synth.load_tuning(TuningTable::from_frequencies(frequencies));
+    synth.note_on(60, 100);
+    assert!(render(&mut synth, 5_000)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+
+    // Note 0 is released like any other note
+    let mut synth = piano();
+    synth.note_on(0, 100);
+    assert!(render(&mut synth, 5_000)
+        .iter()
+        .any(|frame| *frame != [0, 0]));
+    synth.note_off(0);
+    render(&mut synth, SAMPLE_RATE as usize * 2);
+    assert!(render(&mut synth, 5_000)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+}
+
+#[test]
+fn test_negative_freq_detune_on_the_lowest_note() {
+    // 8 Hz minus 20 Hz stops at 0 Hz instead of wrapping around to an ultrasonic frequency
+    let mut patch = common::piano();
+    for voice in patch.voices.iter_mut() {
+        voice.freq_detune = -20;
+    }
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(0, 100);
+    let output = render(&mut synth, 5_000);
+    let sign_changes = output
+        .windows(2)
+        .filter(|pair| (pair[0][0] < 0) != (pair[1][0] < 0))
+        .count();
+    assert_eq!(sign_changes, 0);
+}
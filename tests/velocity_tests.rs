@@ -0,0 +1,152 @@
+mod common;
+
+use common::{load_wavetables, piano, render, SAMPLE_RATE};
+use little_weirdo::synth::{
+    envelope::{EnvelopConfiguration, EnvelopeGenerator, ENVELOPE_FULL_SCALE},
+    velocity::{VelocityConfiguration, VelocityCurve},
+    Clockable, Synth,
+};
+
+/// Highest sample on the left channel
+fn peak(frames: &[[i16; 2]]) -> i32 {
+    frames
+        .iter()
+        .map(|frame| (frame[0] as i32).abs())
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn test_velocity_curves() {
+    let curve = |curve| VelocityConfiguration {
+        curve,
+        ..Default::default()
+    };
+    let linear = curve(VelocityCurve::Linear);
+    assert_eq!(linear.response(0), 0);
+    assert_eq!(linear.response(64), 64);
+    assert_eq!(linear.response(127), 127);
+
+    let exponential = curve(VelocityCurve::Exponential);
+    assert_eq!(exponential.response(0), 0);
+    assert_eq!(exponential.response(64), 32);
+    assert_eq!(exponential.response(127), 127);
+
+    let fixed = curve(VelocityCurve::Fixed(90));
+    assert_eq!(fixed.response(1), 90);
+    assert_eq!(fixed.response(127), 90);
+
+    // A table that stays flat in the middle
+    let table = curve(VelocityCurve::Table([0, 40, 64, 64, 64, 64, 64, 100, 127]));
+    assert_eq!(table.response(0), 0);
+    assert_eq!(table.response(8), 20);
+    assert_eq!(table.response(16), 40);
+    assert_eq!(table.response(60), 64);
+    assert_eq!(table.response(120), 114);
+    assert_eq!(table.response(127), 127);
+}
+
+#[test]
+fn test_velocity_gain_and_depth() {
+    let config = VelocityConfiguration::default();
+    assert_eq!(config.gain(127), 100);
+    assert_eq!(config.gain(0), 0);
+
+    // Half the depth keeps soft notes at half level
+    let config = VelocityConfiguration {
+        amplitude: 50,
+        ..Default::default()
+    };
+    assert_eq!(config.gain(127), 100);
+    assert_eq!(config.gain(0), 50);
+}
+
+#[test]
+fn test_velocity_scales_the_level() {
+    let wavetables = load_wavetables();
+    let patch = piano();
+    let mut loud = Synth::new(SAMPLE_RATE, &patch, wavetables.clone());
+    let mut soft = Synth::new(SAMPLE_RATE, &patch, wavetables);
+    loud.note_on(60, 127);
+    soft.note_on(60, 64);
+    let loud = peak(&render(&mut loud, 5_000));
+    let soft = peak(&render(&mut soft, 5_000));
+    let ratio = soft as f64 / loud as f64;
+    assert!((ratio - 64.0 / 127.0).abs() < 0.05, "ratio {}", ratio);
+}
+
+#[test]
+fn test_held_note_keeps_its_velocity() {
+    let wavetables = load_wavetables();
+    let patch = piano();
+    let mut single = Synth::new(SAMPLE_RATE, &patch, wavetables.clone());
+    let mut chord = Synth::new(SAMPLE_RATE, &patch, wavetables);
+    single.note_on(60, 127);
+    chord.note_on(60, 127);
+    assert_eq!(render(&mut single, 1_000), render(&mut chord, 1_000));
+
+    // A silent second note leaves the level of the held note alone
+    chord.note_on(64, 0);
+    assert_eq!(render(&mut single, 5_000), render(&mut chord, 5_000));
+}
+
+#[test]
+fn test_fixed_velocity() {
+    let wavetables = load_wavetables();
+    let mut patch = piano();
+    patch.velocity_config.curve = VelocityCurve::Fixed(100);
+    let mut soft = Synth::new(SAMPLE_RATE, &patch, wavetables.clone());
+    let mut hard = Synth::new(SAMPLE_RATE, &patch, wavetables);
+    soft.note_on(60, 10);
+    hard.note_on(60, 127);
+    assert_eq!(render(&mut soft, 5_000), render(&mut hard, 5_000));
+}
+
+#[test]
+fn test_velocity_to_cutoff() {
+    let wavetables = load_wavetables();
+    let mut patch = piano();
+    patch.filter_config.enabled = true;
+    let mut plain = Synth::new(SAMPLE_RATE, &patch, wavetables.clone());
+    patch.velocity_config.cutoff = 50;
+    let mut tracking = Synth::new(SAMPLE_RATE, &patch, wavetables);
+
+    // Velocity 0 leaves the cutoff alone, a hard note opens the filter
+    plain.note_on(60, 0);
+    tracking.note_on(60, 0);
+    assert_eq!(render(&mut plain, 1_000), render(&mut tracking, 1_000));
+    plain.note_on(64, 127);
+    tracking.note_on(64, 127);
+    assert!(peak(&render(&mut tracking, 5_000)) > peak(&render(&mut plain, 5_000)));
+}
+
+#[test]
+fn test_velocity_to_envelope_time() {
+    let config = VelocityConfiguration {
+        envelope_time: -50,
+        ..Default::default()
+    };
+    assert_eq!(config.envelope_time_scale(0), 100);
+    assert_eq!(config.envelope_time_scale(127), 50);
+
+    // Samples until the attack reaches the top
+    let attack = |time_scale| {
+        let mut envelope = EnvelopeGenerator::new(
+            EnvelopConfiguration {
+                attack_time: 100,
+                decay_time: 100,
+                release_time: 100,
+                sustain_level: 50,
+                ..Default::default()
+            },
+            SAMPLE_RATE,
+        );
+        envelope.set_time_scale(time_scale);
+        envelope.open_gate();
+        (0..)
+            .take_while(|_| envelope.clock(None) < ENVELOPE_FULL_SCALE)
+            .count()
+    };
+    assert_eq!(attack(100), 4_410);
+    assert_eq!(attack(50), 2_205);
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, SAMPLE_RATE};
+use little_weirdo::synth::{
+    lfo::{LfoMode, LfoShape},
+    patch::Patch,
+    vibrato::{VibratoConfiguration, ALL_VOICES_MASK},
+    Synth,
+};
+
+/// Samples in one cycle of a 1 Hz LFO
+const CYCLE: usize = SAMPLE_RATE as usize;
+
+/// raw_square with the square wave of the first voice on every voice and without its tremolo,
+/// a square LFO at 1 Hz restarted by every note
+fn raw_square() -> Patch {
+    let mut patch: Patch =
+        serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap();
+    patch.voices = [patch.voices[0]; 8];
+    patch.routering_config.voices_to_envelop = [0; 8];
+    patch.routering_config.modulation_matrix[0].depth = 0;
+    patch.lfos[1].rate = 100;
+    patch.lfos[1].shape = LfoShape::Square;
+    patch.lfos[1].mode = LfoMode::KeySync;
+    patch.synth_config.mod_wheel_to_lfo_depth = 0;
+    patch.filter_config.enabled = false;
+    patch
+}
+
+/// Play one LFO cycle of a note on a single voice
+fn play(patch: &Patch, voice: usize, mod_wheel: u8) -> Vec<i16> {
+    let mut patch = *patch;
+    patch.mixer_config.gain_voices = [0; 8];
+    patch.mixer_config.gain_voices[voice] = 80;
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.change_mod_wheel(mod_wheel);
+    synth.note_on(69, 127);
+    let mut output = vec![[0i16; 2]; CYCLE];
+    synth.render(&mut output);
+    output.iter().map(|frame| frame[0]).collect()
+}
+
+/// Frequency over the middle of every quarter of the LFO cycle
+fn quarters(output: &[i16]) -> Vec<f64> {
+    let eighth = CYCLE / 8;
+    (0..4)
+        .map(|quarter| {
+            let start = quarter * 2 * eighth;
+            measure_frequency(&output[start + eighth / 2..start + eighth * 3 / 2])
+        })
+        .collect()
+}
+
+fn vibrato(depth: u16, delay_time: u16, voices: u8) -> VibratoConfiguration {
+    VibratoConfiguration {
+        lfo: 1,
+        depth,
+        delay_time,
+        voices,
+    }
+}
+
+fn near(frequency: f64, expected: f64) -> bool {
+    (frequency / expected - 1.0).abs() < 0.005
+}
+
+/// Frequency ratio of a pitch change in cents
+fn ratio(cents: f64) -> f64 {
+    2f64.powf(cents / 1200.0)
+}
+
+#[test]
+fn test_vibrato_depth_in_cents() {
+    let mut patch = raw_square();
+    let base = quarters(&play(&patch, 0, 0))[0];
+    for depth in [25, 100, 300] {
+        patch.vibrato_config = vibrato(depth, 0, ALL_VOICES_MASK);
+        let frequencies = quarters(&play(&patch, 0, 0));
+        let up = base * ratio(depth as f64);
+        let down = base * ratio(-(depth as f64));
+        assert!(near(frequencies[0], up), "{frequencies:?} {up}");
+        assert!(near(frequencies[1], up), "{frequencies:?} {up}");
+        assert!(near(frequencies[2], down), "{frequencies:?} {down}");
+        assert!(near(frequencies[3], down), "{frequencies:?} {down}");
+    }
+}
+
+#[test]
+fn test_vibrato_on_every_voice() {
+    let mut patch = raw_square();
+    patch.vibrato_config = vibrato(100, 0, ALL_VOICES_MASK);
+    for voice in [0, 1, 7] {
+        let frequencies = quarters(&play(&patch, voice, 0));
+        assert!(
+            near(frequencies[0], frequencies[2] * ratio(200.0)),
+            "{voice} {frequencies:?}"
+        );
+    }
+}
+
+#[test]
+fn test_vibrato_on_a_subset_of_voices() {
+    let mut patch = raw_square();
+    patch.vibrato_config = vibrato(100, 0, 0b0000_0010);
+    let frequencies = quarters(&play(&patch, 1, 0));
+    assert!(near(frequencies[0], frequencies[2] * ratio(200.0)));
+    for voice in [0, 2] {
+        let frequencies = quarters(&play(&patch, voice, 0));
+        assert!(
+            near(frequencies[0], frequencies[2]),
+            "{voice} {frequencies:?}"
+        );
+    }
+}
+
+#[test]
+fn test_vibrato_delay() {
+    let mut patch = raw_square();
+    let base = quarters(&play(&patch, 0, 0))[0];
+    // The vibrato sets in halfway the first half of the LFO cycle
+    patch.vibrato_config = vibrato(100, 250, ALL_VOICES_MASK);
+    let frequencies = quarters(&play(&patch, 0, 0));
+    assert!(near(frequencies[0], base), "{frequencies:?} {base}");
+    assert!(near(frequencies[1], base * ratio(100.0)), "{frequencies:?}");
+    assert!(
+        near(frequencies[2], base * ratio(-100.0)),
+        "{frequencies:?}"
+    );
+}
+
+#[test]
+fn test_delay_restarts_with_every_note() {
+    let mut patch = raw_square();
+    patch.vibrato_config = vibrato(100, 250, ALL_VOICES_MASK);
+    patch.mixer_config.gain_voices = [80, 0, 0, 0, 0, 0, 0, 0];
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(69, 127);
+    let mut output = vec![[0i16; 2]; CYCLE];
+    synth.render(&mut output);
+    synth.note_off(69);
+    synth.note_on(69, 127);
+    synth.render(&mut output);
+    let output: Vec<i16> = output.iter().map(|frame| frame[0]).collect();
+    let frequencies = quarters(&output);
+    for (frequency, expected) in frequencies.iter().zip(quarters(&play(&patch, 0, 0))) {
+        assert!(near(*frequency, expected), "{frequencies:?}");
+    }
+}
+
+#[test]
+fn test_mod_wheel_scales_vibrato() {
+    let mut patch = raw_square();
+    patch.vibrato_config = vibrato(100, 0, ALL_VOICES_MASK);
+    patch.synth_config.mod_wheel_to_lfo_depth = 100;
+    let frequencies = quarters(&play(&patch, 0, 0));
+    assert!(near(frequencies[0], frequencies[2]), "{frequencies:?}");
+    let frequencies = quarters(&play(&patch, 0, 127));
+    assert!(
+        near(frequencies[0], frequencies[2] * ratio(200.0)),
+        "{frequencies:?}"
+    );
+}
+
+#[test]
+fn test_patch_without_vibrato() {
+    let mut json: serde_json::Value =
+        serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap();
+    json.as_object_mut().unwrap().remove("vibrato_config");
+    let patch: Patch = serde_json::from_value(json).unwrap();
+    assert_eq!(patch.vibrato_config.depth, 0);
+    assert_eq!(patch.vibrato_config.voices, ALL_VOICES_MASK);
+    assert!(!patch.vibrato_config.applies_to(0));
+}
@@ -0,0 +1,216 @@
+mod common;
+
+use common::{load_wavetables, measure_frequency, SAMPLE_RATE};
+use little_weirdo::synth::{
+    patch::{Patch, SynthMode},
+    wavetable_oscillator::{
+        Interpolation, VoiceModulation, WaveTableOscillator, WaveTableOscillatorConfig,
+    },
+    Clockable, Synth,
+};
+
+fn sine_config(modulation: VoiceModulation, modulation_index: u16) -> WaveTableOscillatorConfig {
+    WaveTableOscillatorConfig {
+        soundbank_index: 0, // wav0 is a sine
+        interpolation: Interpolation::Linear,
+        modulation,
+        modulation_index,
+        ..Default::default()
+    }
+}
+
+/// A sine oscillator at a frequency in Hz
+fn sine(frequency: u32, modulation: VoiceModulation, modulation_index: u16) -> WaveTableOscillator {
+    let mut osc = WaveTableOscillator::new(
+        sine_config(modulation, modulation_index),
+        SAMPLE_RATE,
+        load_wavetables(),
+    );
+    osc.set_freq(frequency << 16);
+    osc
+}
+
+/// Clock an oscillator with a constant modulator
+fn clock(osc: &mut WaveTableOscillator, modulator: i16, length: usize) -> Vec<i16> {
+    (0..length).map(|_| osc.clock(Some(modulator))).collect()
+}
+
+#[test]
+fn test_modulator_without_modulation() {
+    let mut plain = sine(440, VoiceModulation::None, 0);
+    let mut unmodulated = sine(440, VoiceModulation::None, 0);
+    for _ in 0..1_000 {
+        assert_eq!(unmodulated.clock(Some(i16::MAX)), plain.clock(None));
+    }
+}
+
+#[test]
+fn test_ring_mod() {
+    let mut plain = sine(440, VoiceModulation::None, 0);
+    let mut ring = sine(440, VoiceModulation::RingMod, 0);
+    for modulator in [i16::MAX, 16_384, 0, -16_384] {
+        for _ in 0..100 {
+            let expected = (plain.clock(None) as i32 * modulator as i32) >> 15;
+            assert_eq!(ring.clock(Some(modulator)) as i32, expected);
+        }
+    }
+}
+
+#[test]
+fn test_phase_mod() {
+    // Without modulation the phase stays put
+    let mut plain = sine(440, VoiceModulation::None, 0);
+    let mut phase_mod = sine(440, VoiceModulation::PhaseMod, 314);
+    for _ in 0..1_000 {
+        assert_eq!(phase_mod.clock(Some(0)), plain.clock(None));
+    }
+
+    // A constant modulator shifts the phase, an index of pi at full scale is half a cycle
+    let mut plain = sine(441, VoiceModulation::None, 0);
+    let mut phase_mod = sine(441, VoiceModulation::PhaseMod, 314);
+    for _ in 0..1_000 {
+        let inverted = -plain.clock(None) as i32;
+        assert!((phase_mod.clock(Some(i16::MAX)) as i32 - inverted).abs() < 200);
+    }
+}
+
+#[test]
+fn test_linear_fm() {
+    // A full scale modulator at index 1.0 doubles the frequency
+    let mut osc = sine(441, VoiceModulation::LinearFm, 100);
+    assert!((measure_frequency(&clock(&mut osc, i16::MAX, 4_410)) - 882.0).abs() < 1.0);
+    let mut osc = sine(441, VoiceModulation::LinearFm, 50);
+    assert!((measure_frequency(&clock(&mut osc, i16::MAX, 4_410)) - 661.5).abs() < 1.0);
+    let mut osc = sine(441, VoiceModulation::LinearFm, 50);
+    assert!((measure_frequency(&clock(&mut osc, -i16::MAX, 4_410)) - 220.5).abs() < 1.0);
+
+    // Below zero the frequency stops
+    let mut osc = sine(441, VoiceModulation::LinearFm, 200);
+    let output = clock(&mut osc, -i16::MAX, 1_000);
+    assert!(output.iter().all(|sample| *sample == output[0]));
+}
+
+#[test]
+fn test_through_zero_fm() {
+    // Below zero the cycle runs backwards, a falling sine
+    let mut osc = sine(441, VoiceModulation::ThroughZeroFm, 200);
+    let output = clock(&mut osc, -i16::MAX, 4_410);
+    assert!((measure_frequency(&output) - 441.0).abs() < 1.0);
+    let mut plain = sine(441, VoiceModulation::None, 0);
+    for backward in output {
+        assert!((backward as i32 + plain.clock(None) as i32).abs() < 200);
+    }
+}
+
+#[test]
+fn test_cycle_starts() {
+    let mut osc = sine(441, VoiceModulation::None, 0);
+    let starts: Vec<usize> = (0..1_000)
+        .filter(|_| {
+            osc.clock(None);
+            osc.cycle_started()
+        })
+        .collect();
+    // A new cycle every 100 samples
+    assert_eq!(starts.len(), 10);
+    assert!(starts.windows(2).all(|pair| pair[1] - pair[0] == 100));
+}
+
+#[test]
+fn test_hard_sync() {
+    let mut master = sine(150, VoiceModulation::None, 0);
+    let mut slave = sine(410, VoiceModulation::HardSync, 0);
+    let mut restart = sine(410, VoiceModulation::None, 0);
+    let first = restart.clock(None);
+    let mut synced = 0;
+    let mut after_sync = false;
+    for _ in 0..10_000 {
+        master.clock(None);
+        let sample = slave.clock(Some(0));
+        if after_sync {
+            // The slave restarts its cycle right after the master started a new cycle
+            assert_eq!(sample, first);
+            synced += 1;
+        }
+        after_sync = master.cycle_started();
+        if after_sync {
+            slave.sync();
+        }
+    }
+    assert!(synced >= 30);
+}
+
+/// The first voice of every note at A3 modulates the second voice, the other voices are silent
+fn operator_pair(mode: SynthMode, modulation: VoiceModulation, modulator: u8) -> Patch {
+    let mut patch: Patch =
+        serde_json::from_slice(include_bytes!("../examples/patches/raw_square.json")).unwrap();
+    patch.synth_config.mode = mode;
+    patch.voices = [sine_config(VoiceModulation::None, 0); 8];
+    patch.voices[1].modulation = modulation;
+    patch.voices[1].modulator = modulator;
+    patch.voices[1].modulation_index = 300;
+    patch.voices[1].detune = 12;
+    patch.routering_config.voices_to_envelop = [0; 8];
+    patch.routering_config.modulation_matrix[0].depth = 0;
+    patch.mixer_config.gain_voices = [0, 80, 0, 0, 0, 0, 0, 0];
+    patch.filter_config.enabled = false;
+    patch
+}
+
+fn play(patch: &Patch) -> Vec<i16> {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    synth.note_on(57, 127);
+    let mut output = vec![[0i16; 2]; 4_410];
+    synth.render(&mut output);
+    output.iter().map(|frame| frame[0]).collect()
+}
+
+#[test]
+fn test_voices_of_a_note_modulate_each_other() {
+    let unmodulated = play(&operator_pair(SynthMode::BiPoly, VoiceModulation::None, 0));
+    for modulation in [
+        VoiceModulation::HardSync,
+        VoiceModulation::RingMod,
+        VoiceModulation::PhaseMod,
+        VoiceModulation::LinearFm,
+        VoiceModulation::ThroughZeroFm,
+    ] {
+        let modulated = play(&operator_pair(SynthMode::BiPoly, modulation, 0));
+        assert_ne!(modulated, unmodulated, "{modulation:?}");
+    }
+}
+
+#[test]
+fn test_modulator_must_be_an_earlier_voice_of_the_note() {
+    let unmodulated = play(&operator_pair(SynthMode::Mono, VoiceModulation::None, 0));
+    // The voice itself and later voices do not modulate
+    for modulator in [1, 2, 7] {
+        let patch = operator_pair(SynthMode::Mono, VoiceModulation::RingMod, modulator);
+        assert_eq!(play(&patch), unmodulated);
+    }
+    // Every voice plays its own note in OctoPoly mode
+    let unmodulated = play(&operator_pair(
+        SynthMode::OctoPoly,
+        VoiceModulation::None,
+        0,
+    ));
+    let patch = operator_pair(SynthMode::OctoPoly, VoiceModulation::RingMod, 0);
+    assert_eq!(play(&patch), unmodulated);
+}
+
+#[test]
+fn test_voice_config_without_modulation() {
+    let mut json: serde_json::Value =
+        serde_json::from_slice(include_bytes!("../examples/patches/piano.json")).unwrap();
+    for voice in json["voices"].as_array_mut().unwrap() {
+        let voice = voice.as_object_mut().unwrap();
+        voice.remove("modulation");
+        voice.remove("modulator");
+        voice.remove("modulation_index");
+    }
+    let patch: Patch = serde_json::from_value(json).unwrap();
+    assert!(patch
+        .voices
+        .iter()
+        .all(|voice| voice.modulation == VoiceModulation::None && voice.modulation_index == 0));
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use common::{load_wavetables, render, SAMPLE_RATE};
+use little_weirdo::synth::{
+    patch::{Patch, SynthMode, VoiceStealing},
+    Synth,
+};
+
+/// Notes that take up every voice of the piano
+const CHORD: [u8; 8] = [60, 62, 64, 65, 67, 69, 71, 72];
+
+fn piano(voice_stealing: VoiceStealing) -> Patch {
+    let mut patch = common::piano();
+    patch.synth_config.voice_stealing = voice_stealing;
+    patch
+}
+
+/// A synth playing every note of the chord, one after the other
+fn full(patch: &Patch) -> Synth {
+    let mut synth = Synth::new(SAMPLE_RATE, patch, load_wavetables());
+    for note in CHORD {
+        synth.note_on(note, 100);
+        render(&mut synth, 500);
+    }
+    synth
+}
+
+/// Whether releasing the note after playing `new_note` changes the output, false if the
+/// voice of the note was stolen
+fn is_playing(patch: &Patch, new_note: u8, note: u8) -> bool {
+    let mut released = full(patch);
+    let mut held = full(patch);
+    released.note_on(new_note, 100);
+    held.note_on(new_note, 100);
+    render(&mut released, 1_000);
+    render(&mut held, 1_000);
+    released.note_off(note);
+    render(&mut released, 5_000) != render(&mut held, 5_000)
+}
+
+#[test]
+fn test_no_voice_stealing() {
+    let patch = piano(VoiceStealing::None);
+    let mut ignored = full(&patch);
+    let mut unplayed = full(&patch);
+    ignored.note_on(48, 100);
+    assert_eq!(render(&mut ignored, 5_000), render(&mut unplayed, 5_000));
+}
+
+#[test]
+fn test_steal_oldest() {
+    let patch = piano(VoiceStealing::Oldest);
+    assert!(!is_playing(&patch, 48, 60));
+    assert!(is_playing(&patch, 48, 62));
+    assert!(is_playing(&patch, 48, 48));
+}
+
+#[test]
+fn test_steal_quietest() {
+    let mut patch = piano(VoiceStealing::Quietest);
+    for envelop in patch.envelops.iter_mut() {
+        envelop.attack_time = 500;
+    }
+    // The last note is still in its attack phase
+    assert!(!is_playing(&patch, 48, 72));
+    assert!(is_playing(&patch, 48, 60));
+}
+
+#[test]
+fn test_steal_lowest_and_highest_note_priority() {
+    let patch = piano(VoiceStealing::LowestNote);
+    assert!(!is_playing(&patch, 48, 72));
+    assert!(is_playing(&patch, 48, 60));
+    // A higher note than every playing note is ignored
+    assert!(!is_playing(&patch, 84, 84));
+
+    let patch = piano(VoiceStealing::HighestNote);
+    assert!(!is_playing(&patch, 84, 60));
+    assert!(is_playing(&patch, 84, 72));
+    assert!(!is_playing(&patch, 48, 48));
+}
+
+#[test]
+fn test_same_note_plays_on_its_voice() {
+    let play_again = |voice_stealing| {
+        let mut patch = piano(voice_stealing);
+        // Only the second voice is silent
+        patch.mixer_config.gain_voices[1] = 0;
+        let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+        synth.note_on(60, 100);
+        synth.note_on(62, 100);
+        synth.note_off(60);
+        synth.note_off(62);
+        render(&mut synth, 20_000);
+        synth.note_on(62, 100);
+        render(&mut synth, 2_000)
+    };
+    assert!(play_again(VoiceStealing::SameNote)
+        .iter()
+        .all(|frame| *frame == [0, 0]));
+    assert!(play_again(VoiceStealing::Oldest)
+        .iter()
+        .any(|frame| *frame != [0, 0]));
+}
+
+#[test]
+fn test_stolen_voice_fades_out() {
+    let mut patch = piano(VoiceStealing::Oldest);
+    // Two notes, only the voices of the first note are audible
+    patch.synth_config.mode = SynthMode::BiPoly;
+    patch.mixer_config.gain_voices[4..].fill(0);
+    let mut synth = Synth::new(SAMPLE_RATE, &patch, load_wavetables());
+    synth.note_on(60, 100);
+    synth.note_on(64, 100);
+    render(&mut synth, 5_000);
+    synth.note_on(67, 100);
+    // The fade takes 3 ms
+    let fade = render(&mut synth, SAMPLE_RATE as usize * 3 / 1000);
+    let peak = |frames: &[[i16; 2]]| {
+        frames
+            .iter()
+            .map(|frame| frame[0].unsigned_abs())
+            .max()
+            .unwrap()
+    };
+    assert!(peak(&fade[..20]) > 4 * peak(&fade[fade.len() - 20..]));
+    // The new note starts from silence
+    let attack = render(&mut synth, 2_000);
+    assert!(peak(&attack[..5]) < peak(&fade[..20]) / 4);
+    assert!(peak(&attack[1_000..]) > peak(&attack[..5]));
+}
+
+#[test]
+fn test_voice_stealing_render_is_identical_to_clock_and_output() {
+    let patch = piano(VoiceStealing::Oldest);
+    let mut per_sample = full(&patch);
+    let mut per_block = full(&patch);
+    per_sample.note_on(48, 100);
+    per_block.note_on(48, 100);
+    let expected: Vec<[i16; 2]> = (0..2_000).map(|_| per_sample.clock_and_output()).collect();
+    let mut output = vec![[0i16; 2]; 2_000];
+    for block in output.chunks_mut(7) {
+        per_block.render(block);
+    }
+    assert_eq!(output, expected);
+}
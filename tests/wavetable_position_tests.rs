@@ -0,0 +1,131 @@
+mod common;
+
+use std::{fs, sync::Arc};
+
+use common::{bass, load_wavetables, SAMPLE_RATE};
+use little_weirdo::synth::{
+    data::wavetables::{BoxedWavetable, BoxedWavetables, Wavetables, BAND_LIMITED_LEVELS},
+    patch::Patch,
+    router::{ModulationDestination, ModulationSlot, ModulationSource},
+    wavetable_oscillator::{Interpolation, WaveTableOscillator, WaveTableOscillatorConfig},
+    Clockable, Synth,
+};
+
+fn wavetable_bytes(id: u8) -> Vec<u8> {
+    fs::read(format!(
+        "examples/soundbank/soundbank_pure_elektro/src/wav{}.raw",
+        id
+    ))
+    .unwrap()
+}
+
+/// Wavetables with a sine (0) and a saw (1), followed by one table holding both as frames (2)
+fn sine_saw_wavetables() -> Arc<BoxedWavetables> {
+    let mut wavetables = BoxedWavetables::new();
+    wavetables.add(BoxedWavetable::new(&wavetable_bytes(0)));
+    wavetables.add(BoxedWavetable::new(&wavetable_bytes(1)));
+    let frames = [wavetable_bytes(0), wavetable_bytes(1)].concat();
+    wavetables.add(BoxedWavetable::new(&frames));
+    Arc::new(wavetables)
+}
+
+fn render(wavetables: &Arc<BoxedWavetables>, soundbank_index: u8, position: u16) -> Vec<i16> {
+    let config = WaveTableOscillatorConfig {
+        soundbank_index,
+        interpolation: Interpolation::Linear,
+        wavetable_position: position,
+        ..Default::default()
+    };
+    let mut osc = WaveTableOscillator::new(config, SAMPLE_RATE, Arc::clone(wavetables));
+    osc.change_freq(220 << 16);
+    let mut samples = vec![0i16; 1_000];
+    osc.clock_block(&mut samples);
+    samples
+}
+
+#[test]
+fn test_single_frame_wavetables() {
+    let wavetables = load_wavetables();
+    for index in 0..10 {
+        assert_eq!(wavetables.frames(index), 1);
+    }
+    // The wavetable position has no effect on a single frame
+    let wavetables = sine_saw_wavetables();
+    assert_eq!(render(&wavetables, 1, 0), render(&wavetables, 1, u16::MAX));
+}
+
+#[test]
+fn test_multi_frame_wavetable() {
+    let wavetables = sine_saw_wavetables();
+    assert_eq!(wavetables.frames(2), 2);
+    assert_eq!(render(&wavetables, 2, 0), render(&wavetables, 0, 0));
+    assert_eq!(render(&wavetables, 2, u16::MAX), render(&wavetables, 1, 0));
+}
+
+#[test]
+fn test_wavetable_position_crossfades_frames() {
+    let wavetables = sine_saw_wavetables();
+    let sine = render(&wavetables, 0, 0);
+    let saw = render(&wavetables, 1, 0);
+    let middle = render(&wavetables, 2, u16::MAX / 2);
+    for ((middle, sine), saw) in middle.iter().zip(sine.iter()).zip(saw.iter()) {
+        let expected = (*sine as i32 + *saw as i32) / 2;
+        assert!((*middle as i32 - expected).abs() <= 2);
+    }
+}
+
+#[test]
+fn test_modulate_position() {
+    let wavetables = sine_saw_wavetables();
+    let config = WaveTableOscillatorConfig {
+        soundbank_index: 2,
+        interpolation: Interpolation::Linear,
+        ..Default::default()
+    };
+    let mut osc = WaveTableOscillator::new(config, SAMPLE_RATE, Arc::clone(&wavetables));
+    osc.change_freq(220 << 16);
+    // Offsets beyond the range are clamped to the last frame
+    osc.modulate_position(100_000);
+    let mut samples = vec![0i16; 1_000];
+    osc.clock_block(&mut samples);
+    assert_eq!(samples, render(&wavetables, 1, 0));
+}
+
+#[test]
+fn test_band_limited_multi_frame_wavetable() {
+    let frames = [wavetable_bytes(0), wavetable_bytes(1)].concat();
+    let wavetable = BoxedWavetable::new_band_limited(&frames);
+    assert_eq!(wavetable.frames(), 2);
+    assert_eq!(wavetable.band_limited_levels(), BAND_LIMITED_LEVELS);
+}
+
+#[test]
+fn test_envelope_sweeps_wavetable_position() {
+    let mut wavetables = BoxedWavetables::new();
+    let frames = [wavetable_bytes(0), wavetable_bytes(1)].concat();
+    for _ in 0..10 {
+        wavetables.add(BoxedWavetable::new(&frames));
+    }
+    let wavetables = Arc::new(wavetables);
+    let mut patch = bass();
+    let play = |patch: &Patch| {
+        let mut synth = Synth::new(SAMPLE_RATE, patch, Arc::clone(&wavetables));
+        synth.note_on(48, 100);
+        let mut output = vec![[0i16; 2]; 5_000];
+        synth.render(&mut output);
+        output
+    };
+
+    let unrouted = play(&patch);
+    // A slot without depth leaves the sound untouched
+    patch.routering_config.modulation_matrix[1] = ModulationSlot {
+        source: ModulationSource::Envelope(0),
+        destination: ModulationDestination::WavetablePosition(0),
+        depth: 0,
+        unipolar: false,
+    };
+    assert_eq!(play(&patch), unrouted);
+
+    patch.routering_config.modulation_matrix[1].depth = 100;
+    assert_ne!(play(&patch), unrouted);
+}